@@ -1,45 +1,237 @@
+use std::fmt::Display;
+
 use crate::*;
 
-#[derive(Clone)]
+/// Assembler dialect the compiler output is written for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AssemblySyntax {
+    /// NASM with Intel syntax
+    #[default]
+    Nasm,
+    /// GNU `as` using `.intel_syntax noprefix`
+    Gas,
+}
+
+/// A memory reference `[base + index * scale + displacement]`, or `[rip + symbol + displacement]`
+/// when it refers to a symbol
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemoryOperand {
+    pub size: Size,
+    pub base: Option<Register>,
+    /// Index register and its scale, which is 1, 2, 4 or 8
+    pub index: Option<(Register, u8)>,
+    pub displacement: i32,
+    pub symbol: Option<String>,
+}
+
+impl MemoryOperand {
+    pub fn new(size: Size, base: Register, displacement: i32) -> Self {
+        MemoryOperand {
+            size,
+            base: Some(base),
+            index: None,
+            displacement,
+            symbol: None,
+        }
+    }
+
+    /// `[rip + symbol]`
+    pub fn symbol(size: Size, symbol: &str) -> Self {
+        MemoryOperand {
+            size,
+            base: None,
+            index: None,
+            displacement: 0,
+            symbol: Some(symbol.to_string()),
+        }
+    }
+
+    /// The address inside the brackets
+    fn address(&self, syntax: AssemblySyntax) -> String {
+        let mut address = String::new();
+        if let Some(symbol) = &self.symbol {
+            address.push_str(match syntax {
+                AssemblySyntax::Nasm => "rel ",
+                AssemblySyntax::Gas => "rip+",
+            });
+            address.push_str(symbol);
+        }
+        if let Some(base) = self.base {
+            address.push_str(&base.as_qword());
+        }
+        if let Some((index, scale)) = self.index {
+            if !address.is_empty() {
+                address.push('+');
+            }
+            address.push_str(&format!("{}*{scale}", index.as_qword()));
+        }
+        if address.is_empty() {
+            address = self.displacement.to_string();
+        } else if self.displacement != 0 {
+            address.push_str(&format!("{:+}", self.displacement));
+        }
+        address
+    }
+}
+
+/// Operand of an [`Instruction`], only turned into text by the emitter
+#[derive(Clone, Debug, PartialEq)]
+pub enum MachineOperand {
+    Register(Register, Size),
+    Memory(MemoryOperand),
+    Immediate(i64),
+    /// The address of a symbol, e.g. a string define
+    Symbol(String),
+}
+
+impl MachineOperand {
+    pub fn is_immediate(&self) -> bool {
+        matches!(self, MachineOperand::Immediate(_) | MachineOperand::Symbol(_))
+    }
+
+    /// A 64 bit immediate that no instruction other than a move to a register can take
+    pub fn is_wide_immediate(&self) -> bool {
+        matches!(self, MachineOperand::Immediate(value) if i32::try_from(*value).is_err())
+    }
+
+    pub fn is_register(&self) -> bool {
+        matches!(self, MachineOperand::Register(..))
+    }
+
+    pub fn is_memory(&self) -> bool {
+        matches!(self, MachineOperand::Memory(_))
+    }
+
+    /// The width of the operand, immediates take the size of the other operand
+    pub fn size(&self) -> Option<Size> {
+        match self {
+            MachineOperand::Register(_, size) => Some(*size),
+            MachineOperand::Memory(memory) => Some(memory.size),
+            MachineOperand::Immediate(_) | MachineOperand::Symbol(_) => None,
+        }
+    }
+
+    pub fn render(&self, syntax: AssemblySyntax) -> String {
+        match (self, syntax) {
+            (MachineOperand::Register(register, size), _) => register.as_size(size),
+            (MachineOperand::Memory(memory), AssemblySyntax::Nasm) => {
+                format!("{} [{}]", memory.size.name(), memory.address(syntax))
+            }
+            (MachineOperand::Memory(memory), AssemblySyntax::Gas) => {
+                format!("{} PTR [{}]", memory.size.name(), memory.address(syntax))
+            }
+            (MachineOperand::Immediate(value), _) => value.to_string(),
+            (MachineOperand::Symbol(symbol), AssemblySyntax::Nasm) => symbol.clone(),
+            // A bare symbol would be a memory operand in GAS' Intel mode
+            (MachineOperand::Symbol(symbol), AssemblySyntax::Gas) => format!("OFFSET {symbol}"),
+        }
+    }
+}
+
+impl Display for MachineOperand {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.render(AssemblySyntax::Nasm))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub enum Instruction {
     AsmLiteral(String),
     Label(String),
-    Move(ValueCodegen, ValueCodegen),
-    IntMultiply(ValueCodegen, ValueCodegen),
-    Multiply(ValueCodegen, ValueCodegen),
-    Compare(ValueCodegen, ValueCodegen),
+    Move(MachineOperand, MachineOperand),
+    IntMultiply(MachineOperand, MachineOperand),
+    Multiply(MachineOperand, MachineOperand),
+    Compare(MachineOperand, MachineOperand),
     Return,
-    Push(ValueCodegen),
-    Pop(ValueCodegen),
-    Add(ValueCodegen, ValueCodegen),
-    Sub(ValueCodegen, ValueCodegen),
-    LoadAddress(ValueCodegen, ValueCodegen),
+    Push(MachineOperand),
+    Pop(MachineOperand),
+    Add(MachineOperand, MachineOperand),
+    Sub(MachineOperand, MachineOperand),
+    LoadAddress(MachineOperand, MachineOperand),
     Call(String),
+    Jump(String),
+    /// Jumps to the address in `target`, which is one of the `destinations` labels
+    JumpIndirect
+    {
+        target : MachineOperand,
+        destinations : Vec<String>,
+    },
     JumpConditional
     {
         label_destination : String,
-        conditional : CompareOperation
+        conditional : CompareOperation,
+        /// Whether the compare before it was between signed numbers
+        signed : bool,
     },
 }
 
 impl Instruction {
     pub fn codegen_x86(self) -> String {
+        self.render(AssemblySyntax::Nasm)
+    }
+
+    /// Same as [`Instruction::codegen_x86`] but for GNU `as` in Intel mode, inline assembly is
+    /// passed through untouched so it has to be written for `as` as well
+    pub fn codegen_gas(self) -> String {
+        self.render(AssemblySyntax::Gas)
+    }
+
+    /// Registers the instruction uses, inline assembly uses every register it names
+    pub fn registers(&self) -> Vec<Register> {
+        let operands = match self {
+            Instruction::Move(dst, src)
+            | Instruction::IntMultiply(dst, src)
+            | Instruction::Multiply(dst, src)
+            | Instruction::Compare(dst, src)
+            | Instruction::Add(dst, src)
+            | Instruction::Sub(dst, src)
+            | Instruction::LoadAddress(dst, src) => vec![dst, src],
+            Instruction::Push(operand)
+            | Instruction::Pop(operand)
+            | Instruction::JumpIndirect { target: operand, .. } => vec![operand],
+            Instruction::AsmLiteral(asm) => {
+                return asm
+                    .split(|c: char| !c.is_alphanumeric())
+                    .filter_map(Register::from_name)
+                    .collect();
+            }
+            Instruction::Label(_)
+            | Instruction::Return
+            | Instruction::Call(_)
+            | Instruction::Jump(_)
+            | Instruction::JumpConditional { .. } => vec![],
+        };
+        operands
+            .into_iter()
+            .flat_map(|v| match v {
+                MachineOperand::Register(register, _) => vec![*register],
+                MachineOperand::Memory(memory) => memory.base.into_iter().chain(memory.index.map(|v| v.0)).collect(),
+                MachineOperand::Immediate(_) | MachineOperand::Symbol(_) => vec![],
+            })
+            .collect()
+    }
+
+    fn render(self, syntax: AssemblySyntax) -> String {
+        let op = |v: &MachineOperand| v.render(syntax);
         match self {
             Instruction::Label(name) => format!("{name}:"),
-            Instruction::Move(dst, src) => format!("mov {dst}, {src}"),
-            Instruction::IntMultiply(dst, src) => format!("imul {dst}, {src}"),
-            Instruction::Multiply(dst, src) => format!("mul {dst}, {src}"),
+            Instruction::Move(dst, src) => format!("mov {}, {}", op(&dst), op(&src)),
+            Instruction::IntMultiply(dst, src) => format!("imul {}, {}", op(&dst), op(&src)),
+            Instruction::Multiply(dst, src) => format!("mul {}, {}", op(&dst), op(&src)),
             Instruction::Return => "ret".to_string(),
-            Instruction::Push(src) => format!("push {src}"),
-            Instruction::Pop(dst) => format!("pop {dst}"),
-            Instruction::Add(dst, src) => format!("add {dst}, {src}"),
-            Instruction::Sub(dst, src) => format!("sub {dst}, {src}"),
-            Instruction::Compare(lhs, rhs) => format!("cmp {lhs}, {rhs}"),
-            Instruction::LoadAddress(dst, src) => format!("lea {dst}, {src}"),
+            Instruction::Push(src) => format!("push {}", op(&src)),
+            Instruction::Pop(dst) => format!("pop {}", op(&dst)),
+            Instruction::Add(dst, src) => format!("add {}, {}", op(&dst), op(&src)),
+            Instruction::Sub(dst, src) => format!("sub {}, {}", op(&dst), op(&src)),
+            Instruction::Compare(lhs, rhs) => format!("cmp {}, {}", op(&lhs), op(&rhs)),
+            Instruction::LoadAddress(dst, src) => format!("lea {}, {}", op(&dst), op(&src)),
             Instruction::Call(name) => format!("call {name}"),
-            Instruction::JumpConditional { label_destination, conditional } =>
+            Instruction::Jump(label) => format!("jmp {label}"),
+            Instruction::JumpIndirect { target, .. } => format!("jmp {}", op(&target)),
+            Instruction::JumpConditional { label_destination, conditional, signed } =>
             {
-                format!("j{} {label_destination}", conditional.as_suffix())
+                let suffix = if signed { conditional.as_suffix() } else { conditional.as_unsigned_suffix() };
+                format!("j{suffix} {label_destination}")
             }
             Instruction::AsmLiteral(literal) => literal,
         }
@@ -0,0 +1,299 @@
+use std::path::{Path, PathBuf};
+use std::process::{exit, Command};
+
+use low_level_ir::*;
+
+const USAGE: &str = "\
+Usage: lowir [OPTIONS] <INPUT>
+
+Compiles a textual (.lir) or JSON (.json) IR file.
+
+Options:
+  -o <PATH>           Output path (defaults to the input name with the right extension,
+                      <INPUT>.out for an executable of an input without one)
+  --emit <KIND>       asm, obj, exe or ssa [default: asm]
+  --target <TARGET>   Architecture to compile for: x86_64, aarch64, riscv64 or wasm32
+                      [default: x86_64]
+  --syntax <SYNTAX>   x86_64 assembly syntax to emit: nasm or gas [default: nasm]
+  -O <LEVEL>          Optimization level, 0 disables all passes [default: 0]
+  --export <NAME>     Keeps a function that isn't reachable from main when optimizing,
+                      can be given more than once
+  --inline-threshold <N>
+                      Largest function, in operands, that is inlined when optimizing
+                      [default: 8]
+  --debug             Reports what the optimization passes changed
+  --linker <LINKER>   Linker used for --emit exe: cc or ld [default: cc]
+  --assembler <ASM>   How x86_64 objects are produced: builtin, or external to run nasm/as
+                      [default: builtin]
+  -h, --help          Print this message";
+
+#[derive(PartialEq)]
+enum Emit {
+    Asm,
+    Object,
+    Executable,
+    /// The SSA form of every function, for debugging the middle-end
+    Ssa,
+}
+
+struct Options {
+    input: PathBuf,
+    output: Option<PathBuf>,
+    emit: Emit,
+    opt_level: u8,
+    exports: Vec<String>,
+    inline_threshold: usize,
+    debug: bool,
+    syntax: AssemblySyntax,
+    target: Target,
+    linker: String,
+    external_assembler: bool,
+}
+
+fn fail(message: impl AsRef<str>) -> ! {
+    eprintln!("lowir: {}", message.as_ref());
+    exit(1)
+}
+
+fn parse_args() -> Options {
+    let mut args = std::env::args().skip(1);
+    let mut input = None;
+    let mut output = None;
+    let mut emit = Emit::Asm;
+    let mut opt_level = 0;
+    let mut exports = vec![];
+    let mut inline_threshold = DEFAULT_INLINE_THRESHOLD;
+    let mut debug = false;
+    let mut syntax = AssemblySyntax::Nasm;
+    let mut target = Target::X86_64;
+    let mut linker = "cc".to_string();
+    let mut external_assembler = false;
+
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next()
+                .unwrap_or_else(|| fail(format!("{flag} expects a value")))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                exit(0)
+            }
+            "-o" => output = Some(PathBuf::from(value("-o"))),
+            "--emit" => {
+                emit = match value("--emit").as_str() {
+                    "asm" => Emit::Asm,
+                    "obj" => Emit::Object,
+                    "exe" => Emit::Executable,
+                    "ssa" => Emit::Ssa,
+                    other => fail(format!("Unknown emit kind `{other}`")),
+                }
+            }
+            "--syntax" => {
+                syntax = match value("--syntax").as_str() {
+                    "nasm" => AssemblySyntax::Nasm,
+                    "gas" => AssemblySyntax::Gas,
+                    other => fail(format!("Unknown syntax `{other}`")),
+                }
+            }
+            "--target" => {
+                target = match value("--target").as_str() {
+                    "x86_64" => Target::X86_64,
+                    "aarch64" => Target::AArch64,
+                    "riscv64" => Target::RiscV64,
+                    "wasm32" => Target::Wasm32,
+                    other => fail(format!("Unknown target `{other}`")),
+                }
+            }
+            "--export" => exports.push(value("--export")),
+            "--inline-threshold" => {
+                let threshold = value("--inline-threshold");
+                inline_threshold = threshold
+                    .parse()
+                    .unwrap_or_else(|_| fail(format!("Invalid inline threshold `{threshold}`")));
+            }
+            "--debug" => debug = true,
+            "--linker" => linker = value("--linker"),
+            "--assembler" => {
+                external_assembler = match value("--assembler").as_str() {
+                    "builtin" => false,
+                    "external" => true,
+                    other => fail(format!("Unknown assembler `{other}`")),
+                }
+            }
+            _ if arg.starts_with("-O") => {
+                let level = match arg.strip_prefix("-O").unwrap() {
+                    "" => value("-O"),
+                    level => level.to_string(),
+                };
+                opt_level = level
+                    .parse()
+                    .unwrap_or_else(|_| fail(format!("Invalid optimization level `{level}`")));
+            }
+            _ if arg.starts_with('-') => fail(format!("Unknown option `{arg}`\n\n{USAGE}")),
+            _ => {
+                if input.replace(PathBuf::from(&arg)).is_some() {
+                    fail("Only one input file is supported")
+                }
+            }
+        }
+    }
+
+    Options {
+        input: input.unwrap_or_else(|| fail(format!("No input file\n\n{USAGE}"))),
+        output,
+        emit,
+        opt_level,
+        exports,
+        inline_threshold,
+        debug,
+        syntax,
+        target,
+        linker,
+        external_assembler,
+    }
+}
+
+fn read_operands(path: &Path) -> Vec<Operand> {
+    let source = std::fs::read_to_string(path)
+        .unwrap_or_else(|e| fail(format!("Unable to read {}: {e}", path.display())));
+
+    if path.extension().is_some_and(|ext| ext == "json") {
+        #[cfg(feature = "serde")]
+        return from_json(&source)
+            .unwrap_or_else(|e| fail(format!("{}: {e}", path.display())));
+        #[cfg(not(feature = "serde"))]
+        fail("JSON input requires lowir to be built with the `serde` feature")
+    }
+
+    parse_ir(&source).unwrap_or_else(|e| fail(format!("{}:{e}", path.display())))
+}
+
+fn run(program: &str, args: &[&Path]) {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .unwrap_or_else(|e| fail(format!("Unable to run {program}: {e}")));
+
+    if !status.success() {
+        fail(format!("{program} exited with {status}"))
+    }
+}
+
+fn assemble(target: Target, gas: bool, asm_path: &Path, object_path: &Path) {
+    match target {
+        Target::X86_64 if !gas => run(
+            "nasm",
+            &[Path::new("-felf64"), asm_path, Path::new("-o"), object_path],
+        ),
+        Target::X86_64 => run(
+            "as",
+            &[Path::new("--64"), asm_path, Path::new("-o"), object_path],
+        ),
+        Target::AArch64 => run(
+            "aarch64-linux-gnu-as",
+            &[asm_path, Path::new("-o"), object_path],
+        ),
+        Target::RiscV64 => run(
+            "riscv64-linux-gnu-as",
+            &[Path::new("-march=rv64im"), asm_path, Path::new("-o"), object_path],
+        ),
+        Target::Wasm32 => unreachable!(),
+    }
+}
+
+fn main() {
+    let options = parse_args();
+
+    let mut compiler = Compiler::new();
+    compiler.opt_level = options.opt_level;
+    compiler.exports = options.exports;
+    compiler.inline_threshold = options.inline_threshold;
+    compiler.debug = options.debug;
+    compiler.syntax = options.syntax;
+    compiler.target = options.target;
+    compiler.operands = read_operands(&options.input);
+    let diagnostics = validate(&compiler.operands);
+    if !diagnostics.is_empty() {
+        for diagnostic in &diagnostics {
+            eprintln!("{}: {diagnostic}", options.input.display());
+        }
+        fail(format!("{} errors found", diagnostics.len()))
+    }
+
+    // Only x86_64 can be written for NASM, everything else is GNU as syntax
+    let gas = options.syntax == AssemblySyntax::Gas || options.target != Target::X86_64;
+    let asm_extension = match options.target {
+        Target::Wasm32 => "wat",
+        _ if gas => "s",
+        _ => "asm",
+    };
+
+    let output = options.output.clone().unwrap_or_else(|| {
+        let output = options.input.with_extension(match options.emit {
+            Emit::Asm => asm_extension,
+            Emit::Object => "o",
+            Emit::Executable => "",
+            Emit::Ssa => "ssa",
+        });
+        // An input without an extension would otherwise be overwritten by its executable
+        if output == options.input {
+            output.with_extension("out")
+        } else {
+            output
+        }
+    });
+
+    let write = |path: &Path, contents: &[u8]| {
+        std::fs::write(path, contents)
+            .unwrap_or_else(|e| fail(format!("Unable to write {}: {e}", path.display())))
+    };
+
+    if options.emit == Emit::Ssa {
+        let functions = compiler
+            .compile_ssa()
+            .unwrap_or_else(|e| fail(format!("{}: {e}", options.input.display())));
+        let text = functions.iter().map(|v| format!("{v}\n")).collect::<String>();
+        write(&output, text.as_bytes());
+        return;
+    }
+    if options.emit == Emit::Asm {
+        write(&output, compiler.compile().as_bytes());
+        return;
+    }
+    if options.target == Target::Wasm32 {
+        fail("wasm32 only supports --emit asm, use a WAT assembler such as wat2wasm")
+    }
+
+    let object_path = match options.emit {
+        Emit::Object => output.clone(),
+        _ => output.with_extension("o"),
+    };
+    if options.target == Target::X86_64 && !options.external_assembler {
+        let object = compiler
+            .compile_object()
+            .unwrap_or_else(|e| fail(format!("{e}, try --assembler external")));
+        write(&object_path, &object);
+    } else {
+        let asm_path = output.with_extension(asm_extension);
+        write(&asm_path, compiler.compile().as_bytes());
+        assemble(options.target, gas, &asm_path, &object_path);
+        std::fs::remove_file(&asm_path).ok();
+    }
+
+    if options.emit == Emit::Executable {
+        let (cc, ld) = match options.target {
+            Target::X86_64 => ("cc", "ld"),
+            Target::AArch64 => ("aarch64-linux-gnu-gcc", "aarch64-linux-gnu-ld"),
+            Target::RiscV64 => ("riscv64-linux-gnu-gcc", "riscv64-linux-gnu-ld"),
+            Target::Wasm32 => unreachable!(),
+        };
+        match options.linker.as_str() {
+            "cc" => run(cc, &[Path::new("-no-pie"), &object_path, Path::new("-o"), &output]),
+            "ld" => run(ld, &[&object_path, Path::new("-o"), &output]),
+            other => fail(format!("Unknown linker `{other}`")),
+        }
+        std::fs::remove_file(&object_path).ok();
+    }
+}
@@ -0,0 +1,531 @@
+//! Typed construction of the `Operand` tree.
+//!
+//! ```
+//! use low_level_ir::*;
+//!
+//! let mut module = ModuleBuilder::new();
+//! let int = OperandType::Int(Size::DoubleWord);
+//! let add = module.declare_function("add", &int, &[int.clone(), int.clone()]).unwrap();
+//!
+//! let mut f = module.define(&add, &["a", "b"]).unwrap();
+//! let (a, b) = (f.param(0), f.param(1));
+//! let sum = f.add(&a, &b).unwrap();
+//! f.ret(sum).unwrap();
+//! module.finish_function(f).unwrap();
+//!
+//! let operands = module.finish().unwrap();
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    DuplicateFunction(String),
+    DuplicateVariable(String),
+    UndefinedFunction(String),
+    MissingReturn(String),
+    /// Parameters are only passed in registers, so there can't be more than there are registers
+    TooManyParameters(String),
+    ArityMismatch {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    TypeMismatch {
+        expected: OperandType,
+        found: OperandType,
+    },
+    NotAPointer(String),
+    NotAnLvalue(Value),
+}
+
+impl Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuildError::DuplicateFunction(name) => write!(f, "Function {name} is already declared"),
+            BuildError::DuplicateVariable(name) => write!(f, "Variable {name} is already declared"),
+            BuildError::UndefinedFunction(name) => {
+                write!(f, "Function {name} is declared but never defined")
+            }
+            BuildError::MissingReturn(name) => write!(f, "No return statement in function {name}"),
+            BuildError::TooManyParameters(name) => write!(
+                f,
+                "Function {name} has more than {} parameters",
+                PARAMETER_REGISTERS.len()
+            ),
+            BuildError::ArityMismatch {
+                function,
+                expected,
+                found,
+            } => write!(f, "{function} takes {expected} arguments but {found} were given"),
+            BuildError::TypeMismatch { expected, found } => {
+                write!(f, "Expected a value of type {expected}, found {found}")
+            }
+            BuildError::NotAPointer(name) => write!(f, "Variable {name} is not a pointer"),
+            BuildError::NotAnLvalue(value) => write!(f, "{value} can't be assigned to"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// A function signature handed out by [`ModuleBuilder::declare_function`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionHandle {
+    name: String,
+    return_type: OperandType,
+    parameters: Vec<OperandType>,
+}
+
+impl FunctionHandle {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn return_type(&self) -> &OperandType {
+        &self.return_type
+    }
+
+    pub fn parameters(&self) -> &[OperandType] {
+        &self.parameters
+    }
+}
+
+/// A variable or parameter declared through a [`FunctionBuilder`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableHandle {
+    name: String,
+    ty: OperandType,
+}
+
+impl VariableHandle {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn ty(&self) -> &OperandType {
+        &self.ty
+    }
+
+    pub fn reference(&self) -> Expr {
+        Expr {
+            value: Value::Reference(self.name.clone()),
+            ty: Some(OperandType::Pointer(Box::new(self.ty.clone()))),
+        }
+    }
+
+    pub fn dereference(&self) -> Result<Expr, BuildError> {
+        match &self.ty {
+            OperandType::Pointer(inner) => Ok(Expr {
+                value: Value::Dereference(self.name.clone()),
+                ty: Some(*inner.clone()),
+            }),
+            _ => Err(BuildError::NotAPointer(self.name.clone())),
+        }
+    }
+}
+
+/// A [`Value`] along with its type, literals have no type of their own and fit any integer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    value: Value,
+    ty: Option<OperandType>,
+}
+
+impl Expr {
+    pub fn int(value: i64) -> Self {
+        Self {
+            value: Value::Int(value.to_string()),
+            ty: None,
+        }
+    }
+
+    pub fn char(value: char) -> Self {
+        Self {
+            value: Value::Char(value),
+            ty: Some(OperandType::Char),
+        }
+    }
+
+    pub fn string(value: &str) -> Self {
+        Self {
+            value: Value::StringLiteral(value.to_string()),
+            ty: Some(OperandType::Pointer(Box::new(OperandType::Char))),
+        }
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn ty(&self) -> Option<&OperandType> {
+        self.ty.as_ref()
+    }
+
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+}
+
+impl From<&VariableHandle> for Expr {
+    fn from(variable: &VariableHandle) -> Self {
+        Self {
+            value: Value::Variable(variable.name.clone()),
+            ty: Some(variable.ty.clone()),
+        }
+    }
+}
+
+impl From<VariableHandle> for Expr {
+    fn from(variable: VariableHandle) -> Self {
+        Expr::from(&variable)
+    }
+}
+
+impl From<&Expr> for Expr {
+    fn from(expr: &Expr) -> Self {
+        expr.clone()
+    }
+}
+
+/// Integers of the same width are interchangeable, everything else has to match exactly
+fn compatible(expected: &OperandType, found: &OperandType) -> bool {
+    let integer = |ty: &OperandType| {
+        matches!(ty, OperandType::Int(_) | OperandType::UInt(_) | OperandType::Char)
+    };
+
+    expected == found || (integer(expected) && integer(found) && expected.size() == found.size())
+}
+
+fn check(expected: &OperandType, expr: &Expr) -> Result<(), BuildError> {
+    match &expr.ty {
+        Some(found) if !compatible(expected, found) => Err(BuildError::TypeMismatch {
+            expected: expected.clone(),
+            found: found.clone(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Default)]
+pub struct ModuleBuilder {
+    functions: HashMap<String, FunctionHandle>,
+    defined: Vec<String>,
+    operands: Vec<Operand>,
+}
+
+impl ModuleBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Declares a signature, every function has to be declared before anything can call it
+    pub fn declare_function(
+        &mut self,
+        name: &str,
+        return_type: &OperandType,
+        parameters: &[OperandType],
+    ) -> Result<FunctionHandle, BuildError> {
+        if self.functions.contains_key(name) {
+            return Err(BuildError::DuplicateFunction(name.to_string()));
+        }
+        if parameters.len() > PARAMETER_REGISTERS.len() {
+            return Err(BuildError::TooManyParameters(name.to_string()));
+        }
+
+        let handle = FunctionHandle {
+            name: name.to_string(),
+            return_type: return_type.clone(),
+            parameters: parameters.to_vec(),
+        };
+        self.functions.insert(name.to_string(), handle.clone());
+        Ok(handle)
+    }
+
+    /// Starts the body of a declared function, naming its parameters
+    pub fn define(
+        &self,
+        function: &FunctionHandle,
+        parameter_names: &[&str],
+    ) -> Result<FunctionBuilder, BuildError> {
+        if self.defined.contains(&function.name) {
+            return Err(BuildError::DuplicateFunction(function.name.clone()));
+        }
+        if parameter_names.len() != function.parameters.len() {
+            return Err(BuildError::ArityMismatch {
+                function: function.name.clone(),
+                expected: function.parameters.len(),
+                found: parameter_names.len(),
+            });
+        }
+
+        let mut builder = FunctionBuilder {
+            function: function.clone(),
+            functions: self.functions.clone(),
+            parameters: vec![],
+            variables: HashMap::new(),
+            bodies: vec![vec![]],
+            inline_hint: InlineHint::Auto,
+        };
+
+        for (name, ty) in parameter_names.iter().zip(&function.parameters) {
+            let parameter = builder.register_variable(name, ty)?;
+            builder.parameters.push(parameter);
+        }
+
+        Ok(builder)
+    }
+
+    pub fn finish_function(&mut self, function: FunctionBuilder) -> Result<(), BuildError> {
+        let FunctionBuilder {
+            function,
+            parameters,
+            mut bodies,
+            inline_hint,
+            ..
+        } = function;
+        let body = bodies.pop().expect("Function body is never popped");
+
+        if !body.iter().any(|v| matches!(v, Operand::Return(_))) {
+            return Err(BuildError::MissingReturn(function.name));
+        }
+
+        self.defined.push(function.name.clone());
+        self.operands.push(Operand::FunctionDecl(
+            function.return_type,
+            function.name,
+            body,
+            parameters.into_iter().map(|v| (v.name, v.ty)).collect(),
+            inline_hint,
+        ));
+        Ok(())
+    }
+
+    pub fn finish(self) -> Result<Vec<Operand>, BuildError> {
+        let mut names = self.functions.keys().collect::<Vec<&String>>();
+        names.sort();
+        if let Some(name) = names.into_iter().find(|v| !self.defined.contains(v)) {
+            return Err(BuildError::UndefinedFunction(name.clone()));
+        }
+
+        Ok(self.operands)
+    }
+}
+
+pub struct FunctionBuilder {
+    function: FunctionHandle,
+    functions: HashMap<String, FunctionHandle>,
+    parameters: Vec<VariableHandle>,
+    variables: HashMap<String, OperandType>,
+    /// The innermost body being built is last
+    bodies: Vec<Vec<Operand>>,
+    inline_hint: InlineHint,
+}
+
+impl FunctionBuilder {
+    fn register_variable(
+        &mut self,
+        name: &str,
+        ty: &OperandType,
+    ) -> Result<VariableHandle, BuildError> {
+        if self.variables.contains_key(name) {
+            return Err(BuildError::DuplicateVariable(name.to_string()));
+        }
+
+        self.variables.insert(name.to_string(), ty.clone());
+        Ok(VariableHandle {
+            name: name.to_string(),
+            ty: ty.clone(),
+        })
+    }
+
+    fn push(&mut self, operand: Operand) {
+        self.bodies
+            .last_mut()
+            .expect("Function body is never popped")
+            .push(operand);
+    }
+
+    /// Tells the inliner whether calls to this function should be replaced with its body
+    pub fn set_inline_hint(&mut self, hint: InlineHint) {
+        self.inline_hint = hint;
+    }
+
+    pub fn param(&self, index: usize) -> VariableHandle {
+        self.parameters[index].clone()
+    }
+
+    pub fn declare(
+        &mut self,
+        name: &str,
+        ty: &OperandType,
+        value: impl Into<Expr>,
+    ) -> Result<VariableHandle, BuildError> {
+        let value = value.into();
+        check(ty, &value)?;
+        let variable = self.register_variable(name, ty)?;
+        self.push(Operand::DeclareVariable(ty.clone(), name.to_string(), value.value));
+        Ok(variable)
+    }
+
+    /// Assigns to a variable or a dereferenced pointer
+    pub fn set(&mut self, dst: impl Into<Expr>, value: impl Into<Expr>) -> Result<(), BuildError> {
+        let (dst, value) = (dst.into(), value.into());
+        if !matches!(dst.value, Value::Variable(_) | Value::Dereference(_)) {
+            return Err(BuildError::NotAnLvalue(dst.value));
+        }
+        if let Some(ty) = &dst.ty {
+            check(ty, &value)?;
+        }
+
+        self.push(Operand::SetValue(dst.value, value.value));
+        Ok(())
+    }
+
+    fn arithmetic(
+        lhs: Expr,
+        rhs: Expr,
+        op: fn(Box<Value>, Box<Value>) -> Value,
+    ) -> Result<Expr, BuildError> {
+        if let Some(ty) = lhs.ty.as_ref().or(rhs.ty.as_ref()) {
+            check(ty, &lhs)?;
+            check(ty, &rhs)?;
+        }
+
+        let ty = lhs.ty.or(rhs.ty);
+        Ok(Expr {
+            value: op(Box::new(lhs.value), Box::new(rhs.value)),
+            ty,
+        })
+    }
+
+    pub fn add(&self, lhs: impl Into<Expr>, rhs: impl Into<Expr>) -> Result<Expr, BuildError> {
+        Self::arithmetic(lhs.into(), rhs.into(), Value::Add)
+    }
+
+    pub fn sub(&self, lhs: impl Into<Expr>, rhs: impl Into<Expr>) -> Result<Expr, BuildError> {
+        Self::arithmetic(lhs.into(), rhs.into(), Value::Sub)
+    }
+
+    /// Builds a call expression, checking it against the callee's signature
+    pub fn call(&self, function: &FunctionHandle, arguments: &[Expr]) -> Result<Expr, BuildError> {
+        let function = self
+            .functions
+            .get(&function.name)
+            .ok_or_else(|| BuildError::UndefinedFunction(function.name.clone()))?;
+
+        if arguments.len() != function.parameters.len() {
+            return Err(BuildError::ArityMismatch {
+                function: function.name.clone(),
+                expected: function.parameters.len(),
+                found: arguments.len(),
+            });
+        }
+        for (ty, argument) in function.parameters.iter().zip(arguments) {
+            check(ty, argument)?;
+        }
+
+        Ok(Expr {
+            value: Value::FunctionCall(
+                function.name.clone(),
+                arguments.iter().map(|v| v.value.clone()).collect(),
+            ),
+            ty: Some(function.return_type.clone()),
+        })
+    }
+
+    /// Calls a function for its side effects, discarding the result
+    pub fn call_stmt(
+        &mut self,
+        function: &FunctionHandle,
+        arguments: &[Expr],
+    ) -> Result<(), BuildError> {
+        if let Value::FunctionCall(name, arguments) = self.call(function, arguments)?.value {
+            self.push(Operand::FunctionCall(name, arguments));
+        }
+        Ok(())
+    }
+
+    pub fn drop_variable(&mut self, variable: VariableHandle) {
+        self.push(Operand::DropVariable(variable.name));
+    }
+
+    pub fn inline_assembly(&mut self, asm: &str) {
+        self.push(Operand::InlineAssembly(asm.to_string()));
+    }
+
+    /// Inline assembly with its operands bound to values, see [`ExtendedAssembly`]
+    pub fn extended_assembly(&mut self, asm: ExtendedAssembly) {
+        self.push(Operand::ExtendedAssembly(asm));
+    }
+
+    /// Runs `body` to build the operands executed when the comparison holds
+    pub fn if_then(
+        &mut self,
+        lhs: impl Into<Expr>,
+        operation: CompareOperation,
+        rhs: impl Into<Expr>,
+        body: impl FnOnce(&mut FunctionBuilder) -> Result<(), BuildError>,
+    ) -> Result<(), BuildError> {
+        let (lhs, rhs) = (lhs.into(), rhs.into());
+        if let Some(ty) = lhs.ty.as_ref().or(rhs.ty.as_ref()) {
+            check(ty, &lhs)?;
+            check(ty, &rhs)?;
+        }
+
+        self.bodies.push(vec![]);
+        let result = body(self);
+        let main_body = self.bodies.pop().expect("If body was pushed above");
+        result?;
+
+        self.push(Operand::If {
+            predicate: ComparePredicate {
+                operation,
+                lhs: lhs.value,
+                rhs: rhs.value,
+            },
+            main_body,
+        });
+        Ok(())
+    }
+
+    /// Runs `body` once for every case in `cases` and then with `None` for the default, to build
+    /// the operands executed when the scrutinee equals that case
+    pub fn switch(
+        &mut self,
+        scrutinee: impl Into<Expr>,
+        cases: &[i64],
+        mut body: impl FnMut(&mut FunctionBuilder, Option<i64>) -> Result<(), BuildError>,
+    ) -> Result<(), BuildError> {
+        let scrutinee = scrutinee.into();
+        let mut bodies = vec![];
+        for case in cases.iter().copied().map(Some).chain([None]) {
+            self.bodies.push(vec![]);
+            let result = body(self, case);
+            bodies.push(self.bodies.pop().expect("Case body was pushed above"));
+            result?;
+        }
+
+        let default = bodies.pop().expect("The default is built last");
+        self.push(Operand::Switch {
+            scrutinee: scrutinee.value,
+            cases: cases.iter().copied().zip(bodies).collect(),
+            default,
+        });
+        Ok(())
+    }
+
+    pub fn ret(&mut self, value: impl Into<Expr>) -> Result<(), BuildError> {
+        let value = value.into();
+        check(&self.function.return_type, &value)?;
+        self.push(Operand::Return(value.value));
+        Ok(())
+    }
+
+    pub fn ret_void(&mut self) {
+        self.push(Operand::Return(Value::Null));
+    }
+}
@@ -1,11 +1,46 @@
 use crate::*;
 
+/// A function defined outside of the IR, such as a libc or host function, that can be called
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExternFunction {
+    pub name: String,
+    pub return_type: OperandType,
+    pub parameters: Vec<OperandType>,
+}
+
+/// A partially computed value moved out of the way of code that would overwrite it
+pub(crate) struct Preserved {
+    pub operand: MachineOperand,
+    /// Stack slot holding the value once every scratch register is taken
+    temporary: Option<String>,
+}
+
 pub struct Compiler {
     pub(crate) compiled: Vec<Instruction>,
+    /// Registers holding values that are still needed, calls save the caller saved ones
+    pub(crate) live_registers: Vec<Register>,
+    /// Registers inline assembly in the current function declared clobbered
+    pub(crate) asm_clobbers: Vec<Register>,
+    /// The function being generated, returns inside its bodies leave it
+    pub(crate) function: Option<ReturnContext>,
     pub(crate) scope_manager: ScopeManager,
     pub operands: Vec<Operand>,
     pub string_defines: Vec<(String, String)>,
+    /// Jump tables of switches, each with the label of every entry
+    pub(crate) jump_tables: Vec<(String, Vec<String>)>,
+    pub externs: Vec<ExternFunction>,
     pub id : usize,
+    /// 0 disables every optimization pass, higher levels enable more of them
+    pub opt_level : u8,
+    /// Functions called from outside the program, anything they can't reach is removed when
+    /// optimizing. `main` is used when empty
+    pub exports : Vec<String>,
+    /// Functions with more operands than this aren't inlined unless they ask to be
+    pub inline_threshold : usize,
+    /// Prints what the optimization passes changed to stderr
+    pub debug : bool,
+    pub syntax : AssemblySyntax,
+    pub target : Target,
 }
 
 impl Compiler {
@@ -13,9 +48,20 @@ impl Compiler {
         Compiler {
             scope_manager: ScopeManager::new(),
             compiled: vec![],
+            live_registers: vec![],
+            asm_clobbers: vec![],
+            function: None,
             operands: vec![],
             string_defines : vec![],
-            id : 0
+            jump_tables: vec![],
+            externs : vec![],
+            id : 0,
+            opt_level : 0,
+            exports : vec![],
+            inline_threshold : DEFAULT_INLINE_THRESHOLD,
+            debug : false,
+            syntax : AssemblySyntax::Nasm,
+            target : Target::X86_64,
         }
     }
 
@@ -29,16 +75,186 @@ impl Compiler {
         self.compiled.push(instr)
     }
 
+    /// Generates code with `registers` marked live, so calls made by it keep them intact
+    pub(crate) fn with_live<T>(&mut self, registers: &[Register], codegen: impl FnOnce(&mut Self) -> T) -> T {
+        let len = self.live_registers.len();
+        self.live_registers.extend_from_slice(registers);
+        let out = codegen(self);
+        self.live_registers.truncate(len);
+        out
+    }
+
+    /// Moves `operand` into a free scratch register, or a stack slot when there is none, so
+    /// evaluating something else can't overwrite it. It stays live until released
+    pub(crate) fn preserve(&mut self, operand: MachineOperand, size: &Size) -> Preserved {
+        let free = SCRATCH_REGISTERS.iter().find(|v| !self.live_registers.contains(v));
+        if let Some(&register) = free {
+            let destination = register.as_gen(size);
+            self.new_instruction(Instruction::Move(destination.clone(), operand));
+            self.live_registers.push(register);
+            return Preserved { operand: destination, temporary: None };
+        }
+
+        let name = self.fetch_id("temporary ");
+        let (location, _) = self
+            .scope_manager
+            .get_variable_manager()
+            .allocate(&name, &OperandType::Int(*size))
+            .expect("Unable to allocate variable");
+        let destination = location.as_gen(size);
+        m_set_variable(size, &destination, operand, self);
+        Preserved { operand: destination, temporary: Some(name) }
+    }
+
+    /// An operand for `operand` that arithmetic and compares can take, a 64 bit immediate is
+    /// moved into a free scratch register or a stack slot. It has to be used right away
+    pub(crate) fn fit_immediate(&mut self, operand: MachineOperand, size: &Size) -> MachineOperand {
+        if *size != Size::QuadWord || !operand.is_wide_immediate() {
+            return operand;
+        }
+        let preserved = self.preserve(operand, size);
+        let operand = preserved.operand.clone();
+        self.release(preserved);
+        operand
+    }
+
+    pub(crate) fn release(&mut self, preserved: Preserved) {
+        match (preserved.temporary, preserved.operand) {
+            (Some(name), _) => {
+                self.scope_manager.get_variable_manager().deallocate(&name);
+            }
+            (None, MachineOperand::Register(register, _)) => {
+                let i = self.live_registers.iter().rposition(|v| *v == register);
+                self.live_registers.remove(i.expect("Preserved registers stay live"));
+            }
+            (None, _) => unreachable!("Preserved values are in a register or a stack slot"),
+        }
+    }
+
+    /// Lets the IR call `name`, which the linker or JIT resolves
+    pub fn declare_extern(&mut self, name: &str, return_type: &OperandType, parameters: &[OperandType]) {
+        self.externs.push(ExternFunction {
+            name: name.to_string(),
+            return_type: return_type.clone(),
+            parameters: parameters.to_vec(),
+        });
+    }
+
+    /// Validates the operands first, reporting every problem instead of panicking during codegen
+    pub fn try_compile(self) -> Result<String, Vec<Diagnostic>> {
+        let diagnostics = validate_with_externs(&self.operands, &self.externs);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+
+        Ok(self.compile())
+    }
+
     pub fn compile(mut self) -> String {
+        self.optimize_operands();
+        match self.target {
+            Target::X86_64 => {}
+            Target::AArch64 => {
+                return lower_module::<AArch64>(&self.operands, &self.string_defines, &self.externs)
+            }
+            Target::RiscV64 => {
+                return lower_module::<RiscV64>(&self.operands, &self.string_defines, &self.externs)
+            }
+            Target::Wasm32 => return compile_wat_with_externs(&self.operands, &self.externs),
+        }
+
+        let mut globals = String::new();
+        for name in self.declare_functions() {
+            // Export every function so the output can be linked into an executable
+            match self.syntax {
+                AssemblySyntax::Nasm => globals.push_str(&format!("global {name}\n")),
+                AssemblySyntax::Gas => globals.push_str(&format!(".globl {name}\n")),
+            }
+        }
+        // GAS treats undefined symbols as external by itself
+        if self.syntax == AssemblySyntax::Nasm {
+            for function in &self.externs {
+                globals.push_str(&format!("extern {}\n", function.name));
+            }
+        }
+
+        let mut buffer = String::new();
+        self.codegen_operands();
+
         let mut defines = String::new();
+        // Codegen adds a define for every string literal
         for (name, value) in &self.string_defines
         {
-            let value = value.replace("\\n", "\", 10, \"");
-            defines.push_str(&format!("{name}:\n\tdb \"{value}\", 0\n"));
+            match self.syntax {
+                AssemblySyntax::Nasm => {
+                    let value = value.replace("\\n", "\", 10, \"");
+                    defines.push_str(&format!("{name}:\n\tdb \"{value}\", 0\n"));
+                }
+                // GAS understands the escapes itself
+                AssemblySyntax::Gas => defines.push_str(&format!("{name}:\n\t.asciz \"{value}\"\n")),
+            }
+        }
+
+        // NASM scopes local labels to the function they are in, so outside of it they need the
+        // function's name in front
+        let mut functions = std::collections::HashMap::new();
+        let mut function = "";
+        for instruction in &self.compiled {
+            if let Instruction::Label(label) = instruction {
+                if label.starts_with('.') {
+                    functions.insert(label.as_str(), function);
+                } else {
+                    function = label;
+                }
+            }
+        }
+        for (name, entries) in &self.jump_tables {
+            match self.syntax {
+                AssemblySyntax::Nasm => {
+                    let entries: Vec<String> =
+                        entries.iter().map(|v| format!("{}{v}", functions[v.as_str()])).collect();
+                    defines.push_str(&format!("align 8\n{name}:\n\tdq {}\n", entries.join(", ")));
+                }
+                AssemblySyntax::Gas => {
+                    defines.push_str(&format!(".balign 8\n{name}:\n\t.quad {}\n", entries.join(", ")));
+                }
+            }
+        }
+
+        for asm in self.compiled {
+            match self.syntax {
+                AssemblySyntax::Nasm => buffer.push_str(&asm.codegen_x86()),
+                AssemblySyntax::Gas => buffer.push_str(&asm.codegen_gas()),
+            }
+            buffer.push('\n')
         }
 
+        match self.syntax {
+            AssemblySyntax::Nasm => {
+                format!("section .rodata\n{defines}\nsection .text\n{globals}{buffer}")
+            }
+            AssemblySyntax::Gas => format!(
+                ".intel_syntax noprefix\n.section .rodata\n{defines}\n.text\n{globals}{buffer}\
+                 .section .note.GNU-stack,\"\",@progbits\n"
+            ),
+        }
+    }
+}
+
+impl Compiler {
+    /// Registers every function ahead of codegen so calls can precede the callee, returning the
+    /// names of the ones defined in the IR
+    pub(crate) fn declare_functions(&mut self) -> Vec<String> {
+        for function in &self.externs {
+            self.scope_manager
+                .declare_function_global(&function.name, &function.return_type, &function.parameters);
+        }
+
+        let mut names = vec![];
         for operand in &self.operands {
-            if let Operand::FunctionDecl(_type, name, _, parameters) = operand {
+            if let Operand::FunctionDecl(_type, name, _, parameters, ..) = operand {
+                names.push(name.clone());
+
                 // If its a function, add it to the function  declaration.
                 self.scope_manager.declare_function_global(
                     name,
@@ -51,21 +267,177 @@ impl Compiler {
                 );
             }
         }
+        names
+    }
 
-        let mut buffer = String::new();
+    /// Runs the passes that work on the IR itself, before any target sees it
+    pub(crate) fn optimize_operands(&mut self) {
+        if self.opt_level > 0 {
+            let (operands, report) = inline_functions(&self.operands, &self.externs, self.inline_threshold);
+            self.operands = fold_constants(&operands, &self.externs);
+            if self.debug {
+                eprint!("{report}");
+            }
+
+            let exports = match self.exports.is_empty() {
+                true => vec!["main".to_string()],
+                false => self.exports.clone(),
+            };
+            let (operands, report) = eliminate_dead_code(&self.operands, &exports);
+            self.operands = operands;
+            if self.debug {
+                eprint!("{report}");
+            }
+        }
+    }
+
+    pub(crate) fn codegen_operands(&mut self) {
         // take ownership of operands
         let operands = std::mem::take(&mut self.operands);
+        let functions = FunctionManager::from_operands(&operands, &self.externs);
         for operand in &operands {
-            operand.codegen(&mut self);
+            match operand {
+                // Functions SSA can't represent, like ones with inline assembly, keep the direct path
+                Operand::FunctionDecl(return_type, name, body, parameters, ..) if self.opt_level >= 2 => {
+                    match SsaFunction::build(&functions, return_type, name, body, parameters) {
+                        Ok(function) => function.codegen(self),
+                        Err(_) => operand.codegen(self),
+                    }
+                }
+                _ => operand.codegen(self),
+            }
         }
 
-        for asm in self.compiled {
-            buffer.push_str(&asm.codegen_x86());
-            buffer.push('\n')
+        if self.opt_level > 0 {
+            self.compiled = peephole(std::mem::take(&mut self.compiled));
+            // Unreachable switches lose their dispatch and case labels, their tables go with them
+            let labels: std::collections::HashSet<&String> = self
+                .compiled
+                .iter()
+                .filter_map(|v| match v {
+                    Instruction::Label(label) => Some(label),
+                    _ => None,
+                })
+                .collect();
+            self.jump_tables
+                .retain(|(_, entries)| entries.iter().all(|v| labels.contains(v)));
         }
+    }
+
+    /// Converts every function into SSA form after the IR passes ran, the form `-O2` and up
+    /// generate code from
+    pub fn compile_ssa(mut self) -> Result<Vec<SsaFunction>, SsaError> {
+        self.optimize_operands();
+        build_ssa(&self.operands, &self.externs)
+    }
+
+    /// Encodes the x86-64 output directly into an ELF64 relocatable object, no assembler needed
+    pub fn compile_object(mut self) -> Result<Vec<u8>, EncodeError> {
+        if self.target != Target::X86_64 {
+            return Err(EncodeError::Unsupported(format!("{:?} objects", self.target)));
+        }
+        self.optimize_operands();
+
+        let names = self.declare_functions();
+        self.codegen_operands();
+        let encoded = encode_x86(&self.compiled)?;
+
+        let mut object = ElfObject {
+            text: encoded.code,
+            relocations: encoded.relocations,
+            ..Default::default()
+        };
+
+        for (name, value) in &self.string_defines {
+            let offset = object.rodata.len() as u64;
+            object.rodata.extend_from_slice(&unescape(value));
+            object.rodata.push(0);
+            object.rodata_symbols.push(ElfSymbol {
+                name: name.clone(),
+                offset,
+                size: object.rodata.len() as u64 - offset,
+                global: false,
+            });
+        }
+
+        // Jump table entries are the addresses of labels in the functions
+        let mut labels: Vec<&String> = vec![];
+        for (name, entries) in &self.jump_tables {
+            object.rodata.resize(object.rodata.len().next_multiple_of(8), 0);
+            let offset = object.rodata.len();
+            for (i, label) in entries.iter().enumerate() {
+                object.rodata_relocations.push(Relocation {
+                    offset: offset + i * 8,
+                    symbol: label.clone(),
+                    kind: RelocationKind::Abs64,
+                    addend: 0,
+                });
+                if !labels.contains(&label) {
+                    labels.push(label);
+                }
+            }
+            object.rodata.resize(offset + entries.len() * 8, 0);
+            object.rodata_symbols.push(ElfSymbol {
+                name: name.clone(),
+                offset: offset as u64,
+                size: entries.len() as u64 * 8,
+                global: false,
+            });
+        }
+        for label in labels {
+            let offset = *encoded
+                .labels
+                .get(label)
+                .ok_or_else(|| EncodeError::UndefinedLabel(label.clone()))?;
+            object.labels.push(ElfSymbol {
+                name: label.clone(),
+                offset: offset as u64,
+                size: 0,
+                global: false,
+            });
+        }
+
+        // A function runs until the next one starts
+        let mut starts: Vec<usize> = names.iter().map(|v| encoded.labels[v]).collect();
+        starts.sort();
+        for name in names {
+            let offset = encoded.labels[&name];
+            let end = starts
+                .iter()
+                .find(|v| **v > offset)
+                .copied()
+                .unwrap_or(object.text.len());
+            object.functions.push(ElfSymbol {
+                name,
+                offset: offset as u64,
+                size: (end - offset) as u64,
+                global: true,
+            });
+        }
+
+        Ok(object.write())
+    }
+}
 
-        format!("section .rodata\n{defines}\nsection .text\n{buffer}")
+/// Resolves the C style escapes assemblers accept in string defines
+pub(crate) fn unescape(value: &str) -> Vec<u8> {
+    let mut out = vec![];
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('r') => '\r',
+                Some('0') => '\0',
+                Some(c) => c,
+                None => '\\',
+            },
+            c => c,
+        };
+        out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
     }
+    out
 }
 
 impl Default for Compiler {
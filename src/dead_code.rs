@@ -0,0 +1,380 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+
+use crate::*;
+
+/// Everything [`eliminate_dead_code`] removed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeadCodeReport {
+    /// Functions that are never called from an exported one
+    pub functions: Vec<String>,
+    /// Function and variable of every assignment that is never read
+    pub stores: Vec<(String, String)>,
+}
+
+impl Display for DeadCodeReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for function in &self.functions {
+            writeln!(f, "removed uncalled function {function}")?;
+        }
+        for (function, variable) in &self.stores {
+            writeln!(f, "in function {function}: removed dead store to {variable}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Names of the variables a value reads or takes the address of
+fn uses(value: &Value, names: &mut HashSet<String>) {
+    match value {
+        Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+            uses(lhs, names);
+            uses(rhs, names);
+        }
+        Value::Reference(name) | Value::Dereference(name) | Value::Variable(name) => {
+            names.insert(name.clone());
+        }
+        Value::FunctionCall(_, parameters) => parameters.iter().for_each(|v| uses(v, names)),
+        Value::Char(_) | Value::Int(_) | Value::StringLiteral(_) | Value::Null => {}
+    }
+}
+
+/// Calls made while evaluating a value, which still have to happen when the result is unused
+fn calls(value: &Value, out: &mut Vec<Operand>) {
+    match value {
+        Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+            calls(lhs, out);
+            calls(rhs, out);
+        }
+        Value::FunctionCall(name, parameters) => {
+            out.push(Operand::FunctionCall(name.clone(), parameters.clone()))
+        }
+        _ => {}
+    }
+}
+
+/// Functions a value calls, or refers to by name
+fn callees(value: &Value, functions: &mut HashSet<String>) {
+    match value {
+        Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+            callees(lhs, functions);
+            callees(rhs, functions);
+        }
+        Value::FunctionCall(name, parameters) => {
+            functions.insert(name.clone());
+            parameters.iter().for_each(|v| callees(v, functions));
+        }
+        Value::Int(symbol) => {
+            functions.insert(symbol.clone());
+        }
+        _ => {}
+    }
+}
+
+fn operand_values(operand: &Operand) -> Vec<&Value> {
+    match operand {
+        Operand::DeclareVariable(_, _, value) | Operand::Return(value) => vec![value],
+        Operand::Add(_, lhs, rhs) | Operand::Subtract(_, lhs, rhs) | Operand::SetValue(lhs, rhs) => {
+            vec![lhs, rhs]
+        }
+        Operand::FunctionCall(_, parameters) => parameters.iter().collect(),
+        Operand::If { predicate, .. } => vec![&predicate.lhs, &predicate.rhs],
+        Operand::Switch { scrutinee, .. } => vec![scrutinee],
+        Operand::ExtendedAssembly(asm) => asm.operands().map(|v| &v.value).collect(),
+        Operand::FunctionDecl(..) | Operand::DropVariable(_) | Operand::InlineAssembly(_) => vec![],
+    }
+}
+
+/// Every function a body calls, inline assembly counts when it mentions the name
+pub(crate) fn body_callees(body: &[Operand], names: &[&String], functions: &mut HashSet<String>) {
+    for operand in body {
+        if let Operand::FunctionCall(name, _) = operand {
+            functions.insert(name.clone());
+        }
+        for value in operand_values(operand) {
+            callees(value, functions);
+        }
+        for body in operand.bodies() {
+            body_callees(body, names, functions);
+        }
+        match operand {
+            Operand::InlineAssembly(asm)
+            | Operand::ExtendedAssembly(ExtendedAssembly { template: asm, .. }) => {
+                let words: HashSet<&str> = asm
+                    .split(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+                    .collect();
+                functions.extend(names.iter().filter(|v| words.contains(v.as_str())).map(|v| v.to_string()));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Variables whose address is taken anywhere in the body
+pub(crate) fn address_taken(body: &[Operand], names: &mut HashSet<String>) {
+    fn visit(value: &Value, names: &mut HashSet<String>) {
+        match value {
+            Value::Reference(name) => {
+                names.insert(name.clone());
+            }
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                visit(lhs, names);
+                visit(rhs, names);
+            }
+            Value::FunctionCall(_, parameters) => parameters.iter().for_each(|v| visit(v, names)),
+            _ => {}
+        }
+    }
+
+    for operand in body {
+        operand_values(operand).into_iter().for_each(|v| visit(v, names));
+        for body in operand.bodies() {
+            address_taken(body, names);
+        }
+    }
+}
+
+fn has_inline_assembly(body: &[Operand]) -> bool {
+    body.iter().any(|v| match v {
+        Operand::InlineAssembly(_) | Operand::ExtendedAssembly(_) => true,
+        _ => v.bodies().into_iter().any(has_inline_assembly),
+    })
+}
+
+struct Liveness<'a> {
+    function: &'a str,
+    /// Variables that have their address taken can be read through a pointer at any point
+    referenced: HashSet<String>,
+    report: &'a mut DeadCodeReport,
+}
+
+impl Liveness<'_> {
+    /// Walks the body backwards, `live` holds the variables read after it and ends up with the
+    /// ones read by the body or after it. `assigned` works the same way for variables that are
+    /// still assigned to, their declaration has to stay even when its value is never read.
+    fn body(
+        &mut self,
+        body: &[Operand],
+        live: &mut HashSet<String>,
+        assigned: &mut HashSet<String>,
+    ) -> Vec<Operand> {
+        // Nothing after a return runs, so it's dropped instead of keeping uses of variables
+        // whose declarations are removed
+        let end = body
+            .iter()
+            .position(|v| matches!(v, Operand::Return(_)))
+            .map_or(body.len(), |v| v + 1);
+        let mut out = vec![];
+        for operand in body[..end].iter().rev() {
+            match operand {
+                Operand::DeclareVariable(_, name, _) if assigned.contains(name) => {
+                    live.remove(name);
+                    assigned.remove(name);
+                }
+                Operand::DeclareVariable(_, name, value)
+                | Operand::SetValue(Value::Variable(name), value)
+                    if !live.contains(name) && !self.referenced.contains(name) =>
+                {
+                    self.report.stores.push((self.function.to_string(), name.clone()));
+                    let mut kept = vec![];
+                    calls(value, &mut kept);
+                    for call in kept.iter().rev() {
+                        operand_values(call).into_iter().for_each(|v| uses(v, live));
+                    }
+                    out.extend(kept.into_iter().rev());
+                    continue;
+                }
+                Operand::DeclareVariable(_, name, _) | Operand::DropVariable(name) => {
+                    live.remove(name);
+                    assigned.remove(name);
+                }
+                Operand::SetValue(Value::Variable(name), _) => {
+                    live.remove(name);
+                    assigned.insert(name.clone());
+                }
+                // Only the returned value is read, the body ends here
+                Operand::Return(_) => live.clear(),
+                Operand::If {
+                    predicate,
+                    main_body,
+                } => {
+                    // The body may not run, so whatever is live after it stays live
+                    let mut inside = live.clone();
+                    let mut inside_assigned = assigned.clone();
+                    let main_body = self.body(main_body, &mut inside, &mut inside_assigned);
+                    live.extend(inside);
+                    assigned.extend(inside_assigned);
+                    uses(&predicate.lhs, live);
+                    uses(&predicate.rhs, live);
+                    out.push(Operand::If {
+                        predicate: predicate.clone(),
+                        main_body,
+                    });
+                    continue;
+                }
+                Operand::Switch {
+                    scrutinee,
+                    cases,
+                    default,
+                } => {
+                    // Any one of the bodies runs, or none of them without a default
+                    let (mut inside, mut inside_assigned) = (live.clone(), assigned.clone());
+                    let mut body = |body: &[Operand]| {
+                        let mut body_live = live.clone();
+                        let mut body_assigned = assigned.clone();
+                        let body = self.body(body, &mut body_live, &mut body_assigned);
+                        inside.extend(body_live);
+                        inside_assigned.extend(body_assigned);
+                        body
+                    };
+                    let cases = cases.iter().map(|(case, v)| (*case, body(v))).collect();
+                    let default = body(default);
+                    *live = inside;
+                    *assigned = inside_assigned;
+                    uses(scrutinee, live);
+                    out.push(Operand::Switch {
+                        scrutinee: scrutinee.clone(),
+                        cases,
+                        default,
+                    });
+                    continue;
+                }
+                _ => {}
+            }
+
+            match operand {
+                // Assigning to a variable doesn't read it
+                Operand::SetValue(Value::Variable(_), value) => uses(value, live),
+                _ => operand_values(operand).into_iter().for_each(|v| uses(v, live)),
+            }
+            out.push(operand.clone());
+        }
+        out.reverse();
+        out
+    }
+}
+
+/// Drops of variables whose declaration was removed
+fn remove_orphaned_drops(body: Vec<Operand>, declared: &mut Vec<HashSet<String>>) -> Vec<Operand> {
+    let mut out = vec![];
+    for operand in body {
+        match operand {
+            Operand::DeclareVariable(_, ref name, _) => {
+                declared.last_mut().expect("Bodies have a scope").insert(name.clone());
+            }
+            Operand::DropVariable(ref name) => {
+                let Some(scope) = declared.iter_mut().rev().find(|v| v.contains(name)) else {
+                    continue;
+                };
+                scope.remove(name);
+            }
+            Operand::If { predicate, main_body } => {
+                declared.push(HashSet::new());
+                let main_body = remove_orphaned_drops(main_body, declared);
+                declared.pop();
+                out.push(Operand::If { predicate, main_body });
+                continue;
+            }
+            Operand::Switch { scrutinee, cases, default } => {
+                let mut body = |body: Vec<Operand>| {
+                    declared.push(HashSet::new());
+                    let body = remove_orphaned_drops(body, declared);
+                    declared.pop();
+                    body
+                };
+                let cases = cases.into_iter().map(|(case, v)| (case, body(v))).collect();
+                let default = body(default);
+                out.push(Operand::Switch { scrutinee, cases, default });
+                continue;
+            }
+            _ => {}
+        }
+        out.push(operand);
+    }
+    out
+}
+
+/// Removes functions that can't be reached from `exported` ones, operands after a return, and
+/// assignments to variables that are never read afterwards. Calls made by a removed assignment
+/// are kept.
+///
+/// Nothing is removed when none of `exported` is defined, and functions with inline assembly
+/// keep every variable as it may address them directly.
+pub fn eliminate_dead_code(operands: &[Operand], exported: &[String]) -> (Vec<Operand>, DeadCodeReport) {
+    let mut report = DeadCodeReport::default();
+    let names: Vec<&String> = operands
+        .iter()
+        .filter_map(|v| match v {
+            Operand::FunctionDecl(_, name, ..) => Some(name),
+            _ => None,
+        })
+        .collect();
+
+    let mut reachable: HashSet<String> = exported
+        .iter()
+        .filter(|v| names.contains(v))
+        .cloned()
+        .collect();
+    let keep_all = reachable.is_empty();
+    let mut pending: Vec<String> = reachable.iter().cloned().collect();
+    // Top level inline assembly can refer to anything
+    for operand in operands {
+        if let Operand::InlineAssembly(_) = operand {
+            let mut found = HashSet::new();
+            body_callees(std::slice::from_ref(operand), &names, &mut found);
+            pending.extend(found);
+        }
+    }
+    while let Some(name) = pending.pop() {
+        reachable.insert(name.clone());
+        let body = operands.iter().find_map(|v| match v {
+            Operand::FunctionDecl(_, function, body, ..) if *function == name => Some(body),
+            _ => None,
+        });
+        let mut found = HashSet::new();
+        if let Some(body) = body {
+            body_callees(body, &names, &mut found);
+        }
+        pending.extend(found.into_iter().filter(|v| !reachable.contains(v)));
+    }
+
+    let mut out = vec![];
+    for operand in operands {
+        let Operand::FunctionDecl(return_type, name, body, parameters, hint) = operand else {
+            out.push(operand.clone());
+            continue;
+        };
+        if !keep_all && !reachable.contains(name) {
+            report.functions.push(name.clone());
+            continue;
+        }
+        if has_inline_assembly(body) {
+            out.push(operand.clone());
+            continue;
+        }
+
+        let mut referenced = HashSet::new();
+        address_taken(body, &mut referenced);
+        let first_store = report.stores.len();
+        let mut liveness = Liveness {
+            function: name,
+            referenced,
+            report: &mut report,
+        };
+        let body = liveness.body(body, &mut HashSet::new(), &mut HashSet::new());
+        // Found while walking backwards
+        report.stores[first_store..].reverse();
+        let mut declared = vec![parameters.iter().map(|v| v.0.clone()).collect()];
+        let body = remove_orphaned_drops(body, &mut declared);
+
+        out.push(Operand::FunctionDecl(
+            return_type.clone(),
+            name.clone(),
+            body,
+            parameters.clone(),
+            *hint,
+        ));
+    }
+
+    (out, report)
+}
@@ -0,0 +1,318 @@
+use crate::*;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+const STT_SECTION: u8 = 3;
+
+/// Section header indices, in the order they are written
+const TEXT: u16 = 1;
+const RODATA: u16 = 2;
+const SYMTAB: u32 = 4;
+const STRTAB: u32 = 5;
+const DATA: u16 = 9;
+const BSS: u16 = 10;
+
+const SYMBOL_SIZE: u64 = 24;
+const RELA_SIZE: u64 = 24;
+const HEADER_SIZE: u64 = 64;
+const SECTION_HEADER_SIZE: u64 = 64;
+
+/// A symbol defined in the object file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfSymbol {
+    pub name: String,
+    pub offset: u64,
+    pub size: u64,
+    /// Exported to the linker, otherwise local to the object file
+    pub global: bool,
+}
+
+/// Contents of an x86-64 relocatable object file
+#[derive(Debug, Clone, Default)]
+pub struct ElfObject {
+    pub text: Vec<u8>,
+    pub rodata: Vec<u8>,
+    /// Writable data with its initial value
+    pub data: Vec<u8>,
+    /// Size of the zero initialized writable data, it takes no space in the file
+    pub bss_size: u64,
+    pub functions: Vec<ElfSymbol>,
+    /// Symbols pointing into `rodata`
+    pub rodata_symbols: Vec<ElfSymbol>,
+    /// Symbols pointing into `data`
+    pub data_symbols: Vec<ElfSymbol>,
+    /// Symbols pointing into the zero initialized data
+    pub bss_symbols: Vec<ElfSymbol>,
+    /// Local labels in `text` that relocations refer to, such as jump table entries
+    pub labels: Vec<ElfSymbol>,
+    pub relocations: Vec<Relocation>,
+    /// Fields in `rodata` patched by the linker
+    pub rodata_relocations: Vec<Relocation>,
+}
+
+/// Null terminated names, offset 0 is the empty name
+struct StringTable(Vec<u8>);
+
+impl StringTable {
+    fn new() -> Self {
+        StringTable(vec![0])
+    }
+
+    fn add(&mut self, name: &str) -> u32 {
+        let offset = self.0.len() as u32;
+        self.0.extend_from_slice(name.as_bytes());
+        self.0.push(0);
+        offset
+    }
+}
+
+struct Symbol {
+    name: u32,
+    info: u8,
+    section: u16,
+    value: u64,
+    size: u64,
+}
+
+impl Symbol {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.name.to_le_bytes());
+        out.push(self.info);
+        out.push(0);
+        out.extend_from_slice(&self.section.to_le_bytes());
+        out.extend_from_slice(&self.value.to_le_bytes());
+        out.extend_from_slice(&self.size.to_le_bytes());
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entry_size: u64,
+}
+
+impl SectionHeader {
+    fn write(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.name.to_le_bytes());
+        out.extend_from_slice(&self.kind.to_le_bytes());
+        out.extend_from_slice(&self.flags.to_le_bytes());
+        out.extend_from_slice(&0u64.to_le_bytes());
+        out.extend_from_slice(&self.offset.to_le_bytes());
+        out.extend_from_slice(&self.size.to_le_bytes());
+        out.extend_from_slice(&self.link.to_le_bytes());
+        out.extend_from_slice(&self.info.to_le_bytes());
+        out.extend_from_slice(&self.align.to_le_bytes());
+        out.extend_from_slice(&self.entry_size.to_le_bytes());
+    }
+}
+
+fn relocation_type(kind: RelocationKind) -> u64 {
+    match kind {
+        RelocationKind::Abs64 => 1,
+        RelocationKind::Pc32 => 2,
+        RelocationKind::Plt32 => 4,
+        RelocationKind::Abs32S => 11,
+    }
+}
+
+fn align(out: &mut Vec<u8>, alignment: usize) {
+    while !out.len().is_multiple_of(alignment) {
+        out.push(0);
+    }
+}
+
+impl ElfObject {
+    /// Serializes the object as an `ET_REL` ELF64 file
+    pub fn write(&self) -> Vec<u8> {
+        let mut strtab = StringTable::new();
+
+        // The null symbol and the section symbols come first, every local has to precede the globals
+        let mut symbols = vec![
+            Symbol { name: 0, info: 0, section: 0, value: 0, size: 0 },
+            Symbol { name: 0, info: STT_SECTION, section: TEXT, value: 0, size: 0 },
+            Symbol { name: 0, info: STT_SECTION, section: RODATA, value: 0, size: 0 },
+            Symbol { name: 0, info: STT_SECTION, section: DATA, value: 0, size: 0 },
+            Symbol { name: 0, info: STT_SECTION, section: BSS, value: 0, size: 0 },
+        ];
+        let mut indices = std::collections::HashMap::new();
+
+        let defined = self
+            .rodata_symbols
+            .iter()
+            .map(|v| (v, RODATA, STT_OBJECT))
+            .chain(self.data_symbols.iter().map(|v| (v, DATA, STT_OBJECT)))
+            .chain(self.bss_symbols.iter().map(|v| (v, BSS, STT_OBJECT)))
+            .chain(self.functions.iter().map(|v| (v, TEXT, STT_FUNC)))
+            .chain(self.labels.iter().map(|v| (v, TEXT, STT_NOTYPE)));
+        let (locals, globals): (Vec<_>, Vec<_>) = defined.partition(|v| !v.0.global);
+        let first_global = symbols.len() + locals.len();
+
+        for (symbol, section, kind) in locals.into_iter().chain(globals) {
+            let binding = if symbol.global { STB_GLOBAL } else { STB_LOCAL };
+            indices.insert(symbol.name.clone(), symbols.len() as u64);
+            symbols.push(Symbol {
+                name: strtab.add(&symbol.name),
+                info: (binding << 4) | kind,
+                section,
+                value: symbol.offset,
+                size: symbol.size,
+            });
+        }
+
+        // Anything referenced but not defined here is resolved by the linker
+        for relocation in self.relocations.iter().chain(&self.rodata_relocations) {
+            if !indices.contains_key(&relocation.symbol) {
+                indices.insert(relocation.symbol.clone(), symbols.len() as u64);
+                symbols.push(Symbol {
+                    name: strtab.add(&relocation.symbol),
+                    info: (STB_GLOBAL << 4) | STT_NOTYPE,
+                    section: 0,
+                    value: 0,
+                    size: 0,
+                });
+            }
+        }
+
+        let rela = |relocations: &[Relocation]| {
+            let mut rela = vec![];
+            for relocation in relocations {
+                let info = (indices[&relocation.symbol] << 32) | relocation_type(relocation.kind);
+                rela.extend_from_slice(&(relocation.offset as u64).to_le_bytes());
+                rela.extend_from_slice(&info.to_le_bytes());
+                rela.extend_from_slice(&relocation.addend.to_le_bytes());
+            }
+            rela
+        };
+        let rodata_rela = rela(&self.rodata_relocations);
+        let rela = rela(&self.relocations);
+
+        let mut symtab = vec![];
+        for symbol in &symbols {
+            symbol.write(&mut symtab);
+        }
+
+        let mut shstrtab = StringTable::new();
+        let names = [
+            0,
+            shstrtab.add(".text"),
+            shstrtab.add(".rodata"),
+            shstrtab.add(".rela.text"),
+            shstrtab.add(".symtab"),
+            shstrtab.add(".strtab"),
+            shstrtab.add(".shstrtab"),
+            shstrtab.add(".note.GNU-stack"),
+            shstrtab.add(".rela.rodata"),
+            shstrtab.add(".data"),
+            shstrtab.add(".bss"),
+        ];
+
+        let mut out = vec![0; HEADER_SIZE as usize];
+        let place = |out: &mut Vec<u8>, bytes: &[u8], alignment: usize| {
+            align(out, alignment);
+            let offset = out.len() as u64;
+            out.extend_from_slice(bytes);
+            offset
+        };
+        let text = place(&mut out, &self.text, 16);
+        let rodata = place(&mut out, &self.rodata, 16);
+        let data = place(&mut out, &self.data, 16);
+        let rela_offset = place(&mut out, &rela, 8);
+        let rodata_rela_offset = place(&mut out, &rodata_rela, 8);
+        let symtab_offset = place(&mut out, &symtab, 8);
+        let strtab_offset = place(&mut out, &strtab.0, 1);
+        let shstrtab_offset = place(&mut out, &shstrtab.0, 1);
+
+        let section = |name, kind, flags, offset, size: usize, align| SectionHeader {
+            name,
+            kind,
+            flags,
+            offset,
+            size: size as u64,
+            link: 0,
+            info: 0,
+            align,
+            entry_size: 0,
+        };
+        let headers = [
+            section(names[0], 0, 0, 0, 0, 0),
+            section(names[1], SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, text, self.text.len(), 16),
+            section(names[2], SHT_PROGBITS, SHF_ALLOC, rodata, self.rodata.len(), 16),
+            SectionHeader {
+                link: SYMTAB,
+                info: TEXT as u32,
+                entry_size: RELA_SIZE,
+                ..section(names[3], SHT_RELA, SHF_INFO_LINK, rela_offset, rela.len(), 8)
+            },
+            SectionHeader {
+                link: STRTAB,
+                info: first_global as u32,
+                entry_size: SYMBOL_SIZE,
+                ..section(names[4], SHT_SYMTAB, 0, symtab_offset, symtab.len(), 8)
+            },
+            section(names[5], SHT_STRTAB, 0, strtab_offset, strtab.0.len(), 1),
+            section(names[6], SHT_STRTAB, 0, shstrtab_offset, shstrtab.0.len(), 1),
+            // Marks the stack as non executable
+            section(names[7], SHT_PROGBITS, 0, shstrtab_offset, 0, 1),
+            SectionHeader {
+                link: SYMTAB,
+                info: RODATA as u32,
+                entry_size: RELA_SIZE,
+                ..section(names[8], SHT_RELA, SHF_INFO_LINK, rodata_rela_offset, rodata_rela.len(), 8)
+            },
+            section(names[9], SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, data, self.data.len(), 16),
+            // Nothing is stored for it, the offset only has to be inside the file
+            section(names[10], SHT_NOBITS, SHF_ALLOC | SHF_WRITE, data, self.bss_size as usize, 16),
+        ];
+
+        align(&mut out, 8);
+        let section_headers = out.len() as u64;
+        for header in &headers {
+            header.write(&mut out);
+        }
+
+        let mut header = vec![];
+        header.extend_from_slice(b"\x7fELF");
+        // 64 bit, little endian, version 1, System V ABI
+        header.extend_from_slice(&[2, 1, 1, 0]);
+        header.extend_from_slice(&[0; 8]);
+        // ET_REL, EM_X86_64
+        header.extend_from_slice(&1u16.to_le_bytes());
+        header.extend_from_slice(&62u16.to_le_bytes());
+        header.extend_from_slice(&1u32.to_le_bytes());
+        // No entry point and no program headers
+        header.extend_from_slice(&0u64.to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes());
+        header.extend_from_slice(&section_headers.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&0u16.to_le_bytes());
+        header.extend_from_slice(&(SECTION_HEADER_SIZE as u16).to_le_bytes());
+        header.extend_from_slice(&(headers.len() as u16).to_le_bytes());
+        // .shstrtab
+        header.extend_from_slice(&6u16.to_le_bytes());
+        out[..HEADER_SIZE as usize].copy_from_slice(&header);
+
+        out
+    }
+}
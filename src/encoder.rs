@@ -0,0 +1,657 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodeError {
+    /// Instructions the encoder has no machine code for, such as inline assembly outside of
+    /// the subset it understands
+    Unsupported(String),
+    InvalidOperand(String),
+    UndefinedLabel(String),
+}
+
+impl Display for EncodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncodeError::Unsupported(instr) => write!(f, "Can't encode `{instr}`"),
+            EncodeError::InvalidOperand(operand) => write!(f, "Invalid operand `{operand}`"),
+            EncodeError::UndefinedLabel(label) => write!(f, "Label {label} is never defined"),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+    /// `R_X86_64_PC32`, a 32 bit pc relative data reference
+    Pc32,
+    /// `R_X86_64_PLT32`, a 32 bit pc relative call
+    Plt32,
+    /// `R_X86_64_64`, the absolute address of a symbol loaded with movabs
+    Abs64,
+    /// `R_X86_64_32S`, a sign extended absolute address, requires a non PIE link
+    Abs32S,
+}
+
+/// A field in the code that the linker patches with the address of `symbol`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub offset: usize,
+    pub symbol: String,
+    pub kind: RelocationKind,
+    pub addend: i64,
+}
+
+/// Machine code for an instruction stream, references to symbols that aren't labels within
+/// the stream are left as relocations
+#[derive(Debug, Clone, Default)]
+pub struct EncodedCode {
+    pub code: Vec<u8>,
+    pub labels: HashMap<String, usize>,
+    pub relocations: Vec<Relocation>,
+}
+
+fn condition_code(operation: &CompareOperation, signed: bool) -> u8 {
+    match (operation, signed) {
+        (CompareOperation::EQ, _) => 0x4,
+        (CompareOperation::NEQ, _) => 0x5,
+        (CompareOperation::LT, false) => 0x2,
+        (CompareOperation::GTE, false) => 0x3,
+        (CompareOperation::LTE, false) => 0x6,
+        (CompareOperation::GT, false) => 0x7,
+        (CompareOperation::LT, true) => 0xC,
+        (CompareOperation::GTE, true) => 0xD,
+        (CompareOperation::LTE, true) => 0xE,
+        (CompareOperation::GT, true) => 0xF,
+    }
+}
+
+/// Instructions without operands that inline assembly can use
+const BARE_INSTRUCTIONS: &[(&str, &[u8])] = &[
+    ("nop", &[0x90]),
+    ("ret", &[0xC3]),
+    ("int3", &[0xCC]),
+    ("ud2", &[0x0F, 0x0B]),
+    ("pause", &[0xF3, 0x90]),
+    ("cpuid", &[0x0F, 0xA2]),
+    ("rdtsc", &[0x0F, 0x31]),
+    ("cdq", &[0x99]),
+    ("cqo", &[0x48, 0x99]),
+    ("lfence", &[0x0F, 0xAE, 0xE8]),
+    ("mfence", &[0x0F, 0xAE, 0xF0]),
+    ("sfence", &[0x0F, 0xAE, 0xF8]),
+];
+
+/// A register named in inline assembly, along with the size its name stands for
+fn named_register(name: &str) -> Option<(Register, Size)> {
+    let name = name.to_lowercase();
+    Register::ALL.into_iter().find_map(|register| {
+        [Size::Byte, Size::Word, Size::DoubleWord, Size::QuadWord]
+            .into_iter()
+            .find(|size| register.as_size(size).to_lowercase() == name)
+            .map(|size| (register, size))
+    })
+}
+
+/// `[base + index*scale + displacement]`, optionally sized with `DWORD` or `DWORD PTR`, and
+/// whether its registers are 32 bit. Without a size it takes `default_size`
+fn literal_memory(text: &str, default_size: Option<Size>) -> Option<(MemoryOperand, bool)> {
+    let (prefix, address) = text.strip_suffix(']')?.split_once('[')?;
+    let mut prefix = prefix.split_whitespace().map(|v| v.to_uppercase());
+    let size = match prefix.next() {
+        Some(name) => [Size::Byte, Size::Word, Size::DoubleWord, Size::QuadWord]
+            .into_iter()
+            .find(|size| size.name() == name)?,
+        None => default_size?,
+    };
+    if prefix.next().is_some_and(|v| v != "PTR") || prefix.next().is_some() {
+        return None;
+    }
+
+    let mut memory = MemoryOperand {
+        size,
+        base: None,
+        index: None,
+        displacement: 0,
+        symbol: None,
+    };
+    let mut register_sizes = vec![];
+    for term in address.replace('-', "+-").split('+').map(str::trim).filter(|v| !v.is_empty()) {
+        if let Some(value) = parse_int_literal(&term.replace(' ', "")) {
+            memory.displacement = memory.displacement.checked_add(i32::try_from(value).ok()?)?;
+            continue;
+        }
+        let (name, scale) = match term.split_once('*') {
+            Some((name, scale)) => (name.trim(), scale.trim().parse().ok()?),
+            None => (term, 1),
+        };
+        let (register, register_size) = named_register(name)?;
+        register_sizes.push(register_size);
+        if memory.base.is_none() && scale == 1 {
+            memory.base = Some(register);
+        } else if memory.index.is_none() {
+            memory.index = Some((register, scale));
+        } else {
+            return None;
+        }
+    }
+
+    let short = if register_sizes.iter().all(|v| *v == Size::QuadWord) {
+        false
+    } else if register_sizes.iter().all(|v| *v == Size::DoubleWord) {
+        true
+    } else {
+        return None;
+    };
+    Some((memory, short))
+}
+
+/// Byte registers that only exist with a REX prefix, without one they would mean AH-BH
+fn needs_rex(register: Register, size: Size) -> bool {
+    size == Size::Byte && matches!(register, Register::SP | Register::BP | Register::SI | Register::DI)
+}
+
+struct Encoder {
+    out: EncodedCode,
+    /// Offsets of rel32 fields pointing at labels, resolved once every label is known
+    fixups: Vec<(usize, String)>,
+    /// Like `fixups`, but symbols that aren't labels become relocations instead of errors
+    calls: Vec<(usize, String)>,
+}
+
+/// Encodes instructions into x86-64 machine code
+pub fn encode_x86(instructions: &[Instruction]) -> Result<EncodedCode, EncodeError> {
+    let mut encoder = Encoder {
+        out: EncodedCode::default(),
+        fixups: vec![],
+        calls: vec![],
+    };
+
+    for instruction in instructions {
+        encoder.instruction(instruction)?;
+    }
+
+    let mut out = encoder.out;
+    for (offset, label) in encoder.fixups {
+        let target = *out
+            .labels
+            .get(&label)
+            .ok_or(EncodeError::UndefinedLabel(label))?;
+        out.patch_rel32(offset, target);
+    }
+    for (offset, symbol) in encoder.calls {
+        match out.labels.get(&symbol) {
+            Some(&target) => out.patch_rel32(offset, target),
+            None => out.relocations.push(Relocation {
+                offset,
+                symbol,
+                kind: RelocationKind::Plt32,
+                addend: -4,
+            }),
+        }
+    }
+
+    Ok(out)
+}
+
+impl Encoder {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.out.code.extend_from_slice(bytes);
+    }
+
+    /// Immediates are at most 32 bits wide apart from movabs
+    fn check_immediate(value: i64, size: Size) -> Result<(), EncodeError> {
+        if size == Size::QuadWord && i32::try_from(value).is_err() {
+            return Err(EncodeError::InvalidOperand(format!(
+                "{value} doesn't fit in a 32 bit immediate"
+            )));
+        }
+        Ok(())
+    }
+
+    fn immediate(&mut self, value: i64, size: Size) {
+        let bytes = value.to_le_bytes();
+        let len = match size {
+            // 64 bit instructions take a sign extended 32 bit immediate
+            Size::QuadWord => 4,
+            size => size.get_bytes() as usize,
+        };
+        self.bytes(&bytes[..len]);
+    }
+
+    /// Emits `opcode` with a ModRM byte, `reg` is either a register or an opcode extension
+    fn modrm(
+        &mut self,
+        size: Size,
+        opcode: &[u8],
+        reg: u8,
+        reg_rex: bool,
+        rm: &MachineOperand,
+        trailing_immediate: usize,
+    ) -> Result<(), EncodeError> {
+        if size == Size::Word {
+            self.bytes(&[0x66]);
+        }
+
+        let mut rex = 0x40;
+        let mut force_rex = reg_rex;
+        if size == Size::QuadWord {
+            rex |= 0x08;
+        }
+        if reg >= 8 {
+            rex |= 0x04;
+        }
+        match rm {
+            MachineOperand::Register(register, rm_size) => {
+                if register.encoding() >= 8 {
+                    rex |= 0x01;
+                }
+                force_rex |= needs_rex(*register, *rm_size);
+            }
+            MachineOperand::Memory(memory) => {
+                if memory.base.is_some_and(|v| v.encoding() >= 8) {
+                    rex |= 0x01;
+                }
+                if memory.index.is_some_and(|v| v.0.encoding() >= 8) {
+                    rex |= 0x02;
+                }
+            }
+            _ => {}
+        }
+        if rex != 0x40 || force_rex {
+            self.bytes(&[rex]);
+        }
+        self.bytes(opcode);
+
+        let reg = (reg & 7) << 3;
+        match rm {
+            MachineOperand::Register(register, _) => {
+                self.bytes(&[0xC0 | reg | (register.encoding() & 7)]);
+            }
+            MachineOperand::Memory(memory) => self.memory(reg, memory, trailing_immediate)?,
+            MachineOperand::Immediate(_) | MachineOperand::Symbol(_) => {
+                return Err(EncodeError::InvalidOperand("immediate as r/m".to_string()))
+            }
+        }
+        Ok(())
+    }
+
+    /// The ModRM byte for a memory operand, plus SIB and displacement when they are needed
+    fn memory(
+        &mut self,
+        reg: u8,
+        memory: &MemoryOperand,
+        trailing_immediate: usize,
+    ) -> Result<(), EncodeError> {
+        let displacement = memory.displacement;
+        let index = match memory.index {
+            Some((Register::SP, _)) => {
+                return Err(EncodeError::InvalidOperand("RSP can't be an index".to_string()))
+            }
+            Some((index, scale)) => {
+                let scale = match scale {
+                    1 => 0,
+                    2 => 1,
+                    4 => 2,
+                    8 => 3,
+                    _ => return Err(EncodeError::InvalidOperand(format!("scale {scale}"))),
+                };
+                Some((scale << 6) | ((index.encoding() & 7) << 3))
+            }
+            None => None,
+        };
+
+        if let Some(symbol) = &memory.symbol {
+            if memory.base.is_some() || index.is_some() {
+                return Err(EncodeError::InvalidOperand(format!(
+                    "{symbol} can only be addressed relative to RIP"
+                )));
+            }
+            self.bytes(&[reg | 0x05]);
+            self.out.relocations.push(Relocation {
+                offset: self.out.code.len(),
+                symbol: symbol.clone(),
+                kind: RelocationKind::Pc32,
+                addend: displacement as i64 - 4 - trailing_immediate as i64,
+            });
+            self.bytes(&[0; 4]);
+            return Ok(());
+        }
+
+        let Some(base) = memory.base else {
+            // Absolute address, a SIB byte without a base
+            self.bytes(&[reg | 0x04, index.unwrap_or(0x20) | 0x05]);
+            self.bytes(&displacement.to_le_bytes());
+            return Ok(());
+        };
+
+        let base = base.encoding() & 7;
+        // RBP and R13 as a base always need a displacement
+        let (mode, disp_len) = if displacement == 0 && base != 5 {
+            (0x00, 0)
+        } else if i8::try_from(displacement).is_ok() {
+            (0x40, 1)
+        } else {
+            (0x80, 4)
+        };
+        match index {
+            Some(index) => self.bytes(&[mode | reg | 0x04, index | base]),
+            // RSP and R12 as a base need a SIB byte
+            None if base == 4 => self.bytes(&[mode | reg | 0x04, 0x24]),
+            None => self.bytes(&[mode | reg | base]),
+        }
+        self.bytes(&displacement.to_le_bytes()[..disp_len]);
+        Ok(())
+    }
+
+    fn operand_size(dst: &MachineOperand, src: &MachineOperand) -> Result<Size, EncodeError> {
+        match (dst.size(), src.size()) {
+            (Some(lhs), Some(rhs)) if lhs != rhs => Err(EncodeError::InvalidOperand(format!(
+                "operand sizes {} and {} differ",
+                lhs.name(),
+                rhs.name()
+            ))),
+            (lhs, rhs) => lhs
+                .or(rhs)
+                .ok_or_else(|| EncodeError::InvalidOperand("operand size is ambiguous".to_string())),
+        }
+    }
+
+    /// The classic two operand ALU encodings, `extension` is the /digit used with immediates
+    fn alu(
+        &mut self,
+        base: u8,
+        extension: u8,
+        dst: &MachineOperand,
+        src: &MachineOperand,
+    ) -> Result<(), EncodeError> {
+        let size = Self::operand_size(dst, src)?;
+        let byte = (size == Size::Byte) as u8;
+        match (dst, src) {
+            (_, MachineOperand::Register(register, reg_size)) => self.modrm(
+                size,
+                &[base + 1 - byte],
+                register.encoding(),
+                needs_rex(*register, *reg_size),
+                dst,
+                0,
+            ),
+            (MachineOperand::Register(register, reg_size), MachineOperand::Memory(_)) => self
+                .modrm(
+                    size,
+                    &[base + 3 - byte],
+                    register.encoding(),
+                    needs_rex(*register, *reg_size),
+                    src,
+                    0,
+                ),
+            (_, MachineOperand::Immediate(value)) => {
+                Self::check_immediate(*value, size)?;
+                if size == Size::Byte {
+                    self.modrm(size, &[0x80], extension, false, dst, 1)?;
+                    self.immediate(*value, Size::Byte);
+                } else if i8::try_from(*value).is_ok() {
+                    self.modrm(size, &[0x83], extension, false, dst, 1)?;
+                    self.immediate(*value, Size::Byte);
+                } else {
+                    let len = size.get_bytes().min(4) as usize;
+                    self.modrm(size, &[0x81], extension, false, dst, len)?;
+                    self.immediate(*value, size);
+                }
+                Ok(())
+            }
+            _ => Err(EncodeError::InvalidOperand(
+                "both operands are memory".to_string(),
+            )),
+        }
+    }
+
+    fn rel32_to_label(&mut self, label: &str) {
+        self.fixups.push((self.out.code.len(), label.to_string()));
+        self.bytes(&[0; 4]);
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) -> Result<(), EncodeError> {
+        match instruction {
+            Instruction::Label(name) => {
+                self.out.labels.insert(name.clone(), self.out.code.len());
+            }
+            Instruction::Return => self.bytes(&[0xC3]),
+            Instruction::Call(name) => {
+                self.bytes(&[0xE8]);
+                // Whether it is a label is only known once everything is encoded
+                self.calls.push((self.out.code.len(), name.clone()));
+                self.bytes(&[0; 4]);
+            }
+            // Tail calls jump to functions, which can be defined elsewhere
+            Instruction::Jump(label) if !label.starts_with('.') => {
+                self.bytes(&[0xE9]);
+                self.calls.push((self.out.code.len(), label.clone()));
+                self.bytes(&[0; 4]);
+            }
+            Instruction::Jump(label) => {
+                self.bytes(&[0xE9]);
+                self.rel32_to_label(label);
+            }
+            // Like push, the target is always 64 bit without REX.W
+            Instruction::JumpIndirect { target, .. } => {
+                self.modrm(Size::DoubleWord, &[0xFF], 4, false, target, 0)?
+            }
+            Instruction::JumpConditional {
+                label_destination,
+                conditional,
+                signed,
+            } => {
+                self.bytes(&[0x0F, 0x80 | condition_code(conditional, *signed)]);
+                self.rel32_to_label(label_destination);
+            }
+            Instruction::Move(dst, src) => {
+                let size = Self::operand_size(dst, src)?;
+                match (dst, src) {
+                    (MachineOperand::Register(register, Size::QuadWord), MachineOperand::Symbol(symbol)) => {
+                        let code = register.encoding();
+                        self.bytes(&[0x48 | (code >= 8) as u8, 0xB8 + (code & 7)]);
+                        self.out.relocations.push(Relocation {
+                            offset: self.out.code.len(),
+                            symbol: symbol.clone(),
+                            kind: RelocationKind::Abs64,
+                            addend: 0,
+                        });
+                        self.bytes(&[0; 8]);
+                    }
+                    (MachineOperand::Memory(_), MachineOperand::Symbol(symbol)) if size == Size::QuadWord => {
+                        self.modrm(size, &[0xC7], 0, false, dst, 4)?;
+                        self.out.relocations.push(Relocation {
+                            offset: self.out.code.len(),
+                            symbol: symbol.clone(),
+                            kind: RelocationKind::Abs32S,
+                            addend: 0,
+                        });
+                        self.bytes(&[0; 4]);
+                    }
+                    (MachineOperand::Register(register, _), MachineOperand::Immediate(value)) => {
+                        let code = register.encoding();
+                        if size == Size::QuadWord && i32::try_from(*value).is_err() {
+                            // movabs
+                            self.bytes(&[0x48 | (code >= 8) as u8, 0xB8 + (code & 7)]);
+                            self.bytes(&value.to_le_bytes());
+                        } else if size == Size::QuadWord {
+                            self.modrm(size, &[0xC7], 0, false, dst, 4)?;
+                            self.immediate(*value, size);
+                        } else {
+                            if size == Size::Word {
+                                self.bytes(&[0x66]);
+                            }
+                            if code >= 8 || needs_rex(*register, size) {
+                                self.bytes(&[0x40 | (code >= 8) as u8]);
+                            }
+                            let opcode = if size == Size::Byte { 0xB0 } else { 0xB8 };
+                            self.bytes(&[opcode + (code & 7)]);
+                            self.immediate(*value, size);
+                        }
+                    }
+                    (MachineOperand::Memory(_), MachineOperand::Immediate(value)) => {
+                        Self::check_immediate(*value, size)?;
+                        let opcode = if size == Size::Byte { 0xC6 } else { 0xC7 };
+                        let len = size.get_bytes().min(4) as usize;
+                        self.modrm(size, &[opcode], 0, false, dst, len)?;
+                        self.immediate(*value, size);
+                    }
+                    _ => self.alu(0x88, 0, dst, src)?,
+                }
+            }
+            Instruction::Add(dst, src) => self.alu(0x00, 0, dst, src)?,
+            Instruction::Sub(dst, src) => self.alu(0x28, 5, dst, src)?,
+            Instruction::Compare(lhs, rhs) => self.alu(0x38, 7, lhs, rhs)?,
+            Instruction::IntMultiply(dst, src) => {
+                let &MachineOperand::Register(register, size) = dst else {
+                    return Err(EncodeError::InvalidOperand("imul needs a register".to_string()));
+                };
+                match src {
+                    &MachineOperand::Immediate(value) if i8::try_from(value).is_ok() => {
+                        self.modrm(size, &[0x6B], register.encoding(), false, dst, 1)?;
+                        self.immediate(value, Size::Byte);
+                    }
+                    &MachineOperand::Immediate(value) => {
+                        Self::check_immediate(value, size)?;
+                        let len = size.get_bytes().min(4) as usize;
+                        self.modrm(size, &[0x69], register.encoding(), false, dst, len)?;
+                        self.immediate(value, size);
+                    }
+                    src => self.modrm(size, &[0x0F, 0xAF], register.encoding(), false, src, 0)?,
+                }
+            }
+            Instruction::LoadAddress(dst, src) => {
+                let &MachineOperand::Register(register, size) = dst else {
+                    return Err(EncodeError::InvalidOperand("lea needs a register".to_string()));
+                };
+                // A register source would encode as mod=11, which isn't a valid lea
+                if !src.is_memory() {
+                    return Err(EncodeError::InvalidOperand(format!("lea of {src}")));
+                }
+                self.modrm(size, &[0x8D], register.encoding(), false, src, 0)?;
+            }
+            Instruction::Push(src) => match src {
+                MachineOperand::Register(register, _) => {
+                    let code = register.encoding();
+                    if code >= 8 {
+                        self.bytes(&[0x41]);
+                    }
+                    self.bytes(&[0x50 + (code & 7)]);
+                }
+                MachineOperand::Immediate(value) => {
+                    Self::check_immediate(*value, Size::QuadWord)?;
+                    self.bytes(&[0x68]);
+                    self.immediate(*value, Size::DoubleWord);
+                }
+                // push and pop are 64 bit without needing REX.W
+                memory => self.modrm(Size::DoubleWord, &[0xFF], 6, false, memory, 0)?,
+            },
+            Instruction::Pop(dst) => match dst {
+                MachineOperand::Register(register, _) => {
+                    let code = register.encoding();
+                    if code >= 8 {
+                        self.bytes(&[0x41]);
+                    }
+                    self.bytes(&[0x58 + (code & 7)]);
+                }
+                memory => self.modrm(Size::DoubleWord, &[0x8F], 0, false, memory, 0)?,
+            },
+            Instruction::Multiply(dst, src) => {
+                return Err(EncodeError::Unsupported(format!("mul {dst}, {src}")))
+            }
+            Instruction::AsmLiteral(asm) => {
+                for line in asm.lines().map(str::trim).filter(|v| !v.is_empty()) {
+                    self.literal(line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A line of inline assembly in Intel syntax, limited to the instructions the encoder
+    /// already knows and a few common ones without operands
+    fn literal(&mut self, line: &str) -> Result<(), EncodeError> {
+        let unsupported = || EncodeError::Unsupported(line.to_string());
+        let (mnemonic, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let mnemonic = mnemonic.to_lowercase();
+        let texts: Vec<&str> = rest.split(',').map(str::trim).filter(|v| !v.is_empty()).collect();
+
+        if let Some((_, bytes)) = BARE_INSTRUCTIONS.iter().find(|v| v.0 == mnemonic) {
+            if !texts.is_empty() {
+                return Err(unsupported());
+            }
+            self.bytes(bytes);
+            return Ok(());
+        }
+
+        // Memory without a size is as wide as the register next to it
+        let register_size = texts.iter().find_map(|v| named_register(v)).map(|v| v.1);
+        let mut short_address = false;
+        let mut operands = vec![];
+        for text in &texts {
+            let operand = if let Some((register, size)) = named_register(text) {
+                MachineOperand::Register(register, size)
+            } else if let Some(value) = parse_int_literal(text) {
+                MachineOperand::Immediate(value)
+            } else {
+                let (memory, short) = literal_memory(text, register_size).ok_or_else(unsupported)?;
+                short_address |= short;
+                MachineOperand::Memory(memory)
+            };
+            operands.push(operand);
+        }
+        if short_address {
+            // Address size override, the registers of the address are 32 bit
+            self.bytes(&[0x67]);
+        }
+
+        let instruction = match (mnemonic.as_str(), operands.as_slice()) {
+            ("mov", [dst, src]) => Instruction::Move(dst.clone(), src.clone()),
+            ("add", [dst, src]) => Instruction::Add(dst.clone(), src.clone()),
+            ("sub", [dst, src]) => Instruction::Sub(dst.clone(), src.clone()),
+            ("cmp", [lhs, rhs]) => Instruction::Compare(lhs.clone(), rhs.clone()),
+            ("imul", [dst, src]) => Instruction::IntMultiply(dst.clone(), src.clone()),
+            ("lea", [dst, src @ MachineOperand::Memory(_)]) => {
+                Instruction::LoadAddress(dst.clone(), src.clone())
+            }
+            ("push", [operand]) if operand.size().is_none_or(|v| v == Size::QuadWord) => {
+                Instruction::Push(operand.clone())
+            }
+            ("pop", [operand]) if operand.size() == Some(Size::QuadWord) => Instruction::Pop(operand.clone()),
+            ("and", [dst, src]) => return self.alu(0x20, 4, dst, src),
+            ("or", [dst, src]) => return self.alu(0x08, 1, dst, src),
+            ("xor", [dst, src]) => return self.alu(0x30, 6, dst, src),
+            (
+                "xchg",
+                [other, MachineOperand::Register(register, size)] | [MachineOperand::Register(register, size), other],
+            ) if other.size() == Some(*size) => {
+                let byte = (*size == Size::Byte) as u8;
+                return self.modrm(*size, &[0x87 - byte], register.encoding(), needs_rex(*register, *size), other, 0);
+            }
+            ("inc" | "dec" | "not" | "neg", [operand]) => {
+                let size = operand.size().ok_or_else(unsupported)?;
+                let byte = (size == Size::Byte) as u8;
+                let (opcode, extension) = match mnemonic.as_str() {
+                    "inc" => (0xFF, 0),
+                    "dec" => (0xFF, 1),
+                    "not" => (0xF7, 2),
+                    _ => (0xF7, 3),
+                };
+                return self.modrm(size, &[opcode - byte], extension, false, operand, 0);
+            }
+            _ => return Err(unsupported()),
+        };
+        self.instruction(&instruction)
+    }
+}
+
+impl EncodedCode {
+    fn patch_rel32(&mut self, offset: usize, target: usize) {
+        let relative = target as i64 - (offset as i64 + 4);
+        self.code[offset..offset + 4].copy_from_slice(&(relative as i32).to_le_bytes());
+    }
+}
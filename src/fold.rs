@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+
+use crate::*;
+
+struct Folder {
+    functions: FunctionManager,
+    /// Innermost scope is last, lookups fall back to the enclosing ones
+    scopes: Vec<HashMap<String, OperandType>>,
+    return_type: OperandType,
+}
+
+/// Evaluates arithmetic on literals ahead of time, wrapping to the type the result is stored as,
+/// and applies identities such as `x + 0` and `x - x`.
+///
+/// `If` statements whose predicate is known are removed, or replaced by their body when it
+/// always runs. A `Switch` on a constant is replaced by the body it picks.
+pub fn fold_constants(operands: &[Operand], externs: &[ExternFunction]) -> Vec<Operand> {
+    let mut folder = Folder {
+        functions: FunctionManager::from_operands(operands, externs),
+        scopes: vec![HashMap::new()],
+        return_type: DEFAULT_TYPE,
+    };
+
+    let mut folded = vec![];
+    for operand in operands {
+        folder.operand(operand, &mut folded);
+    }
+    folded
+}
+
+/// The value of a literal
+fn constant(value: &Value) -> Option<i64> {
+    match value {
+        Value::Int(num) => parse_int_literal(num),
+        Value::Char(c) => Some(*c as i64),
+        _ => None,
+    }
+}
+
+/// Whether evaluating the value twice gives the same result without side effects
+fn is_pure(value: &Value) -> bool {
+    match value {
+        Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => is_pure(lhs) && is_pure(rhs),
+        Value::Reference(_)
+        | Value::Dereference(_)
+        | Value::Variable(_)
+        | Value::Char(_)
+        | Value::Int(_) => true,
+        // Every string literal gets its own address
+        Value::StringLiteral(_) | Value::FunctionCall(..) | Value::Null => false,
+    }
+}
+
+/// `lhs + rhs` or `lhs - rhs` wrapped to `ty`, unless a 64 bit result no longer fits in an
+/// immediate
+fn evaluate(lhs: i64, rhs: i64, subtract: bool, ty: &OperandType) -> Option<Value> {
+    let result = if subtract {
+        lhs.wrapping_sub(rhs)
+    } else {
+        lhs.wrapping_add(rhs)
+    };
+    let result = wrap_to_type(result, ty);
+    (ty.size() != Size::QuadWord || i32::try_from(result).is_ok())
+        .then(|| Value::Int(result.to_string()))
+}
+
+fn simplify(lhs: Value, rhs: Value, subtract: bool, ty: &OperandType) -> Value {
+    match (constant(&lhs), constant(&rhs)) {
+        (Some(a), Some(b)) => {
+            if let Some(value) = evaluate(a, b, subtract, ty) {
+                return value;
+            }
+        }
+        // x + 0 and x - 0
+        (_, Some(0)) => return lhs,
+        // 0 + x
+        (Some(0), _) if !subtract => return rhs,
+        _ => {}
+    }
+
+    // x - x
+    if subtract && lhs == rhs && is_pure(&lhs) {
+        return Value::Int("0".to_string());
+    }
+
+    // (x + a) + b becomes x + (a + b), and likewise for subtraction
+    if let (Value::Add(x, a) | Value::Sub(x, a), Some(b)) = (&lhs, constant(&rhs)) {
+        if let Some(a) = constant(a) {
+            let a = if matches!(lhs, Value::Sub(..)) { a.wrapping_neg() } else { a };
+            if let Some(offset) = evaluate(a, b, subtract, ty) {
+                return match constant(&offset) {
+                    Some(0) => *x.clone(),
+                    _ => Value::Add(x.clone(), Box::new(offset)),
+                };
+            }
+        }
+    }
+
+    if subtract {
+        Value::Sub(Box::new(lhs), Box::new(rhs))
+    } else {
+        Value::Add(Box::new(lhs), Box::new(rhs))
+    }
+}
+
+impl Folder {
+    fn lookup(&self, name: &str) -> Option<&OperandType> {
+        self.scopes.iter().rev().find_map(|v| v.get(name))
+    }
+
+    fn declare(&mut self, name: &str, ty: &OperandType) {
+        self.scopes
+            .last_mut()
+            .expect("There is always a scope")
+            .insert(name.to_string(), ty.clone());
+    }
+
+    /// The type a value has on its own, literals take the type of where they are used
+    fn value_type(&self, value: &Value) -> Option<OperandType> {
+        match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                self.value_type(lhs).or(self.value_type(rhs))
+            }
+            Value::Reference(name) => self
+                .lookup(name)
+                .map(|v| OperandType::Pointer(Box::new(v.clone()))),
+            Value::Dereference(name) => match self.lookup(name) {
+                Some(OperandType::Pointer(inner)) => Some(*inner.clone()),
+                _ => None,
+            },
+            Value::Variable(name) => self.lookup(name).cloned(),
+            Value::FunctionCall(name, _) => {
+                self.functions.get_function_type(name).map(|v| v.0.clone())
+            }
+            Value::Char(_) => Some(OperandType::Char),
+            Value::StringLiteral(_) => Some(OperandType::Pointer(Box::new(OperandType::Char))),
+            Value::Int(_) | Value::Null => None,
+        }
+    }
+
+    /// Folds `value`, wrapping to `expected` or to its own type when there is none
+    fn value(&self, value: &Value, expected: Option<&OperandType>) -> Value {
+        let ty = expected
+            .cloned()
+            .or(self.value_type(value))
+            .unwrap_or(DEFAULT_TYPE);
+
+        match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => simplify(
+                self.value(lhs, Some(&ty)),
+                self.value(rhs, Some(&ty)),
+                matches!(value, Value::Sub(..)),
+                &ty,
+            ),
+            Value::FunctionCall(name, parameters) => {
+                Value::FunctionCall(name.clone(), self.arguments(name, parameters))
+            }
+            _ => value.clone(),
+        }
+    }
+
+    fn arguments(&self, name: &str, parameters: &[Value]) -> Vec<Value> {
+        let types = self
+            .functions
+            .get_function_type(name)
+            .map(|v| v.1.clone())
+            .unwrap_or_default();
+        parameters
+            .iter()
+            .enumerate()
+            .map(|(i, value)| self.value(value, types.get(i)))
+            .collect()
+    }
+
+    /// Folds both sides of the predicate, along with its outcome when it is known
+    fn predicate(&self, predicate: &ComparePredicate) -> (ComparePredicate, Option<bool>) {
+        let ty = self
+            .value_type(&predicate.lhs)
+            .or(self.value_type(&predicate.rhs))
+            .unwrap_or(DEFAULT_TYPE);
+        let lhs = self.value(&predicate.lhs, Some(&ty));
+        let rhs = self.value(&predicate.rhs, Some(&ty));
+
+        let outcome = match (constant(&lhs), constant(&rhs)) {
+            (Some(a), Some(b)) => Some(compare(
+                predicate.operation,
+                wrap_to_type(a, &ty),
+                wrap_to_type(b, &ty),
+                &ty,
+            )),
+            // x == x
+            _ if lhs == rhs && is_pure(&lhs) => Some(matches!(
+                predicate.operation,
+                CompareOperation::EQ | CompareOperation::GTE | CompareOperation::LTE
+            )),
+            _ => None,
+        };
+
+        let predicate = ComparePredicate {
+            operation: predicate.operation,
+            lhs,
+            rhs,
+        };
+        (predicate, outcome)
+    }
+
+    /// Folds a nested body in its own scope, returning the variables still declared at its end
+    fn body(&mut self, operands: &[Operand]) -> (Vec<Operand>, Vec<String>) {
+        self.scopes.push(HashMap::new());
+        let mut body = vec![];
+        for operand in operands {
+            self.operand(operand, &mut body);
+        }
+        let scope = self.scopes.pop().expect("Pushed above");
+
+        let mut declared = vec![];
+        for operand in operands {
+            if let Operand::DeclareVariable(_, name, _) = operand {
+                if scope.contains_key(name) && !declared.contains(name) {
+                    declared.push(name.clone());
+                }
+            }
+        }
+        (body, declared)
+    }
+
+    /// A body can only take the place of its statement when it doesn't shadow anything
+    fn shadows(&self, operands: &[Operand]) -> bool {
+        operands.iter().any(|v| {
+            matches!(v, Operand::DeclareVariable(_, name, _) if self.lookup(name).is_some())
+        })
+    }
+
+    /// Folds a body that always runs straight into `out`
+    fn splice(&mut self, operands: &[Operand], out: &mut Vec<Operand>) {
+        let (mut body, declared) = self.body(operands);
+        // Its variables still go out of scope where the body would have ended
+        if !matches!(body.last(), Some(Operand::Return(_))) {
+            body.extend(declared.into_iter().map(Operand::DropVariable));
+        }
+        out.append(&mut body);
+    }
+
+    fn operand(&mut self, operand: &Operand, out: &mut Vec<Operand>) {
+        match operand {
+            Operand::DeclareVariable(ty, name, value) => {
+                let value = self.value(value, Some(ty));
+                self.declare(name, ty);
+                out.push(Operand::DeclareVariable(ty.clone(), name.clone(), value));
+            }
+            Operand::FunctionDecl(return_type, name, operands, parameters, hint) => {
+                self.scopes
+                    .push(parameters.iter().cloned().collect::<HashMap<String, OperandType>>());
+                self.return_type = return_type.clone();
+                let (body, _) = self.body(operands);
+                self.scopes.pop();
+
+                out.push(Operand::FunctionDecl(
+                    return_type.clone(),
+                    name.clone(),
+                    body,
+                    parameters.clone(),
+                    *hint,
+                ));
+            }
+            Operand::Add(ty, lhs, rhs) => {
+                out.push(Operand::Add(
+                    ty.clone(),
+                    self.value(lhs, Some(ty)),
+                    self.value(rhs, Some(ty)),
+                ));
+            }
+            Operand::Subtract(ty, lhs, rhs) => {
+                out.push(Operand::Subtract(
+                    ty.clone(),
+                    self.value(lhs, Some(ty)),
+                    self.value(rhs, Some(ty)),
+                ));
+            }
+            Operand::SetValue(lhs, value) => {
+                let ty = match lhs {
+                    Value::Variable(_) | Value::Dereference(_) => self.value_type(lhs),
+                    _ => None,
+                };
+                out.push(Operand::SetValue(lhs.clone(), self.value(value, ty.as_ref())));
+            }
+            Operand::DropVariable(name) => {
+                if let Some(scope) = self.scopes.iter_mut().rev().find(|v| v.contains_key(name)) {
+                    scope.remove(name);
+                }
+                out.push(operand.clone());
+            }
+            Operand::FunctionCall(name, parameters) => {
+                out.push(Operand::FunctionCall(name.clone(), self.arguments(name, parameters)));
+            }
+            Operand::If {
+                predicate,
+                main_body,
+            } => {
+                let (predicate, outcome) = self.predicate(predicate);
+                if outcome == Some(false) {
+                    return;
+                }
+
+                if outcome.is_some() && !self.shadows(main_body) {
+                    self.splice(main_body, out);
+                    return;
+                }
+                out.push(Operand::If {
+                    predicate,
+                    main_body: self.body(main_body).0,
+                });
+            }
+            Operand::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => {
+                let ty = self.value_type(scrutinee).unwrap_or(DEFAULT_TYPE);
+                let scrutinee = self.value(scrutinee, Some(&ty));
+
+                // A known scrutinee always runs the same body
+                if let Some(value) = constant(&scrutinee) {
+                    let value = wrap_to_type(value, &ty);
+                    let body = cases
+                        .iter()
+                        .find(|v| wrap_to_type(v.0, &ty) == value)
+                        .map_or(default, |v| &v.1);
+                    if !self.shadows(body) {
+                        self.splice(body, out);
+                        return;
+                    }
+                }
+                out.push(Operand::Switch {
+                    scrutinee,
+                    cases: cases.iter().map(|(case, v)| (*case, self.body(v).0)).collect(),
+                    default: self.body(default).0,
+                });
+            }
+            Operand::Return(Value::Null) => out.push(operand.clone()),
+            Operand::Return(value) => {
+                let return_type = self.return_type.clone();
+                out.push(Operand::Return(self.value(value, Some(&return_type))));
+            }
+            Operand::InlineAssembly(_) => out.push(operand.clone()),
+            Operand::ExtendedAssembly(asm) => {
+                let inputs = asm
+                    .inputs
+                    .iter()
+                    .map(|v| AsmOperand {
+                        constraint: v.constraint.clone(),
+                        value: self.value(&v.value, None),
+                    })
+                    .collect();
+                out.push(Operand::ExtendedAssembly(ExtendedAssembly {
+                    inputs,
+                    ..asm.clone()
+                }));
+            }
+        }
+    }
+}
@@ -20,6 +20,24 @@ impl FunctionManager {
         }
     }
 
+    /// Signatures of every function a program defines or declares as extern
+    pub fn from_operands(operands: &[Operand], externs: &[ExternFunction]) -> Self {
+        let mut functions = Self::new();
+        for function in externs {
+            functions.declare_function(&function.name, &function.return_type, &function.parameters);
+        }
+        for operand in operands {
+            if let Operand::FunctionDecl(return_type, name, _, parameters, ..) = operand {
+                functions.declare_function(
+                    name,
+                    return_type,
+                    &parameters.iter().map(|v| v.1.clone()).collect::<Vec<OperandType>>(),
+                );
+            }
+        }
+        functions
+    }
+
     pub fn get_function_type(&self, name: &str) -> Option<&(OperandType, Vec<OperandType>)> {
         self.functions.get(name)
     }
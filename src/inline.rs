@@ -0,0 +1,612 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use crate::*;
+
+/// Functions with at most this many operands, not counting the return, are inlined
+pub const DEFAULT_INLINE_THRESHOLD: usize = 8;
+
+/// Every call [`inline_functions`] replaced with the body of the callee
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InlineReport {
+    /// Caller and callee of every inlined call
+    pub calls: Vec<(String, String)>,
+}
+
+impl Display for InlineReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (caller, callee) in &self.calls {
+            writeln!(f, "in function {caller}: inlined call to {callee}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A function that can take the place of its calls
+#[derive(Debug, Clone)]
+struct Candidate {
+    return_type: OperandType,
+    parameters: Vec<(String, OperandType)>,
+    /// The body up to its return, with calls inside of it already inlined
+    body: Vec<Operand>,
+    result: Value,
+}
+
+struct Inliner<'a> {
+    declarations: HashMap<&'a str, &'a Operand>,
+    functions: FunctionManager,
+    threshold: usize,
+    recursive: HashSet<String>,
+    /// Bodies with their calls inlined, every function is only processed once
+    bodies: HashMap<String, Vec<Operand>>,
+    candidates: HashMap<String, Option<Candidate>>,
+    /// Every variable name in the program, renamed locals must not collide with any of them
+    taken: HashSet<String>,
+    next_id: usize,
+    function: String,
+    /// Innermost scope is last, lookups fall back to the enclosing ones
+    scopes: Vec<HashMap<String, OperandType>>,
+    /// Variables introduced to hold the result of an inlined call, they are never written again
+    results: HashSet<String>,
+    report: InlineReport,
+}
+
+/// Replaces calls to small functions with their body, renaming its variables so they can't
+/// collide with the caller's. Arguments and results are stored in new variables which are
+/// dropped once the calling operand is done with them.
+///
+/// Recursive functions, functions marked [`InlineHint::Never`] and functions with inline
+/// assembly or returns inside an `If` are never inlined. Functions marked
+/// [`InlineHint::Always`] are inlined regardless of `threshold`.
+pub fn inline_functions(
+    operands: &[Operand],
+    externs: &[ExternFunction],
+    threshold: usize,
+) -> (Vec<Operand>, InlineReport) {
+    let declarations: HashMap<&str, &Operand> = operands
+        .iter()
+        .filter_map(|v| match v {
+            Operand::FunctionDecl(_, name, ..) => Some((name.as_str(), v)),
+            _ => None,
+        })
+        .collect();
+
+    let names: Vec<&String> = operands
+        .iter()
+        .filter_map(|v| match v {
+            Operand::FunctionDecl(_, name, ..) => Some(name),
+            _ => None,
+        })
+        .collect();
+    let mut graph: HashMap<&str, HashSet<String>> = HashMap::new();
+    for operand in operands {
+        if let Operand::FunctionDecl(_, name, body, ..) = operand {
+            let mut callees = HashSet::new();
+            body_callees(body, &names, &mut callees);
+            graph.insert(name, callees);
+        }
+    }
+    let recursive = graph
+        .keys()
+        .filter(|name| reaches(&graph, name, name))
+        .map(|v| v.to_string())
+        .collect();
+
+    let mut taken = HashSet::new();
+    variable_names(operands, &mut taken);
+
+    let mut inliner = Inliner {
+        declarations,
+        functions: FunctionManager::from_operands(operands, externs),
+        threshold,
+        recursive,
+        bodies: HashMap::new(),
+        candidates: HashMap::new(),
+        taken,
+        next_id: 0,
+        function: String::new(),
+        scopes: vec![],
+        results: HashSet::new(),
+        report: InlineReport::default(),
+    };
+
+    let mut out = vec![];
+    for operand in operands {
+        match operand {
+            Operand::FunctionDecl(return_type, name, _, parameters, hint) => {
+                let body = inliner.processed(name);
+                out.push(Operand::FunctionDecl(
+                    return_type.clone(),
+                    name.clone(),
+                    body,
+                    parameters.clone(),
+                    *hint,
+                ));
+            }
+            _ => out.push(operand.clone()),
+        }
+    }
+    (out, inliner.report)
+}
+
+/// Whether `to` can be called, directly or not, from `from`
+fn reaches(graph: &HashMap<&str, HashSet<String>>, from: &str, to: &str) -> bool {
+    let mut visited = HashSet::new();
+    let mut pending: Vec<&str> = graph[from].iter().map(|v| v.as_str()).collect();
+    while let Some(name) = pending.pop() {
+        if name == to {
+            return true;
+        }
+        if visited.insert(name) {
+            if let Some(callees) = graph.get(name) {
+                pending.extend(callees.iter().map(|v| v.as_str()));
+            }
+        }
+    }
+    false
+}
+
+fn variable_names(body: &[Operand], names: &mut HashSet<String>) {
+    for operand in body {
+        match operand {
+            Operand::DeclareVariable(_, name, _) => {
+                names.insert(name.clone());
+            }
+            Operand::FunctionDecl(_, _, body, parameters, _) => {
+                names.extend(parameters.iter().map(|v| v.0.clone()));
+                variable_names(body, names);
+            }
+            _ => operand.bodies().into_iter().for_each(|v| variable_names(v, names)),
+        }
+    }
+}
+
+/// Number of operands in a body, including the ones nested in `If`s and `Switch`es
+fn size(body: &[Operand]) -> usize {
+    body.iter()
+        .map(|v| 1 + v.bodies().into_iter().map(size).sum::<usize>())
+        .sum()
+}
+
+/// Whether the body can be placed inside another function, it needs a single way out at the
+/// end since there is no way to jump out of the caller's operands
+fn can_inline(body: &[Operand], top_level: bool) -> bool {
+    body.iter().all(|v| match v {
+        Operand::InlineAssembly(_) | Operand::ExtendedAssembly(_) | Operand::FunctionDecl(..) => false,
+        Operand::Return(_) => top_level,
+        _ => v.bodies().into_iter().all(|v| can_inline(v, false)),
+    }) && (!top_level || body.iter().any(|v| matches!(v, Operand::Return(_))))
+}
+
+/// Literals, and variables nothing else writes to, read the same before and after a call
+fn is_stable(value: &Value, results: &HashSet<String>) -> bool {
+    match value {
+        Value::Int(_) | Value::Char(_) | Value::StringLiteral(_) | Value::Null => true,
+        Value::Variable(name) => results.contains(name),
+        _ => false,
+    }
+}
+
+fn rename_value(value: &Value, names: &HashMap<String, String>) -> Value {
+    let rename = |name: &String| names.get(name).unwrap_or(name).clone();
+    match value {
+        Value::Add(lhs, rhs) => Value::Add(
+            Box::new(rename_value(lhs, names)),
+            Box::new(rename_value(rhs, names)),
+        ),
+        Value::Sub(lhs, rhs) => Value::Sub(
+            Box::new(rename_value(lhs, names)),
+            Box::new(rename_value(rhs, names)),
+        ),
+        Value::Reference(name) => Value::Reference(rename(name)),
+        Value::Dereference(name) => Value::Dereference(rename(name)),
+        Value::Variable(name) => Value::Variable(rename(name)),
+        Value::FunctionCall(name, parameters) => Value::FunctionCall(
+            name.clone(),
+            parameters.iter().map(|v| rename_value(v, names)).collect(),
+        ),
+        Value::Char(_) | Value::Int(_) | Value::StringLiteral(_) | Value::Null => value.clone(),
+    }
+}
+
+fn rename_body(body: &[Operand], names: &HashMap<String, String>) -> Vec<Operand> {
+    let rename = |name: &String| names.get(name).unwrap_or(name).clone();
+    body.iter()
+        .map(|operand| match operand {
+            Operand::DeclareVariable(ty, name, value) => {
+                Operand::DeclareVariable(ty.clone(), rename(name), rename_value(value, names))
+            }
+            Operand::Add(ty, lhs, rhs) => {
+                Operand::Add(ty.clone(), rename_value(lhs, names), rename_value(rhs, names))
+            }
+            Operand::Subtract(ty, lhs, rhs) => {
+                Operand::Subtract(ty.clone(), rename_value(lhs, names), rename_value(rhs, names))
+            }
+            Operand::SetValue(lhs, value) => {
+                Operand::SetValue(rename_value(lhs, names), rename_value(value, names))
+            }
+            Operand::DropVariable(name) => Operand::DropVariable(rename(name)),
+            Operand::FunctionCall(name, parameters) => Operand::FunctionCall(
+                name.clone(),
+                parameters.iter().map(|v| rename_value(v, names)).collect(),
+            ),
+            Operand::If {
+                predicate,
+                main_body,
+            } => Operand::If {
+                predicate: ComparePredicate {
+                    operation: predicate.operation,
+                    lhs: rename_value(&predicate.lhs, names),
+                    rhs: rename_value(&predicate.rhs, names),
+                },
+                main_body: rename_body(main_body, names),
+            },
+            Operand::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => Operand::Switch {
+                scrutinee: rename_value(scrutinee, names),
+                cases: cases.iter().map(|(case, v)| (*case, rename_body(v, names))).collect(),
+                default: rename_body(default, names),
+            },
+            Operand::Return(value) => Operand::Return(rename_value(value, names)),
+            Operand::FunctionDecl(..) | Operand::InlineAssembly(_) | Operand::ExtendedAssembly(_) => {
+                operand.clone()
+            }
+        })
+        .collect()
+}
+
+impl<'a> Inliner<'a> {
+    fn lookup(&self, name: &str) -> Option<&OperandType> {
+        self.scopes.iter().rev().find_map(|v| v.get(name))
+    }
+
+    fn declare(&mut self, name: &str, ty: &OperandType) {
+        self.scopes
+            .last_mut()
+            .expect("There is always a scope")
+            .insert(name.to_string(), ty.clone());
+    }
+
+    /// The type a value has on its own, literals take the type of where they are used
+    fn value_type(&self, value: &Value) -> Option<OperandType> {
+        match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                self.value_type(lhs).or(self.value_type(rhs))
+            }
+            Value::Reference(name) => self
+                .lookup(name)
+                .map(|v| OperandType::Pointer(Box::new(v.clone()))),
+            Value::Dereference(name) => match self.lookup(name) {
+                Some(OperandType::Pointer(inner)) => Some(*inner.clone()),
+                _ => None,
+            },
+            Value::Variable(name) => self.lookup(name).cloned(),
+            Value::FunctionCall(name, _) => {
+                self.functions.get_function_type(name).map(|v| v.0.clone())
+            }
+            Value::Char(_) => Some(OperandType::Char),
+            Value::StringLiteral(_) => Some(OperandType::Pointer(Box::new(OperandType::Char))),
+            Value::Int(_) | Value::Null => None,
+        }
+    }
+
+    /// A name based on `name` that isn't used anywhere in the program
+    fn fresh(&mut self, name: &str) -> String {
+        loop {
+            self.next_id += 1;
+            let fresh = format!("{name}.inline{}", self.next_id);
+            if self.taken.insert(fresh.clone()) {
+                return fresh;
+            }
+        }
+    }
+
+    /// The body of a function with every call it makes to a candidate inlined
+    fn processed(&mut self, name: &str) -> Vec<Operand> {
+        if let Some(body) = self.bodies.get(name) {
+            return body.clone();
+        }
+
+        let declaration: &'a Operand = self.declarations[name];
+        let Operand::FunctionDecl(_, _, body, parameters, _) = declaration else {
+            unreachable!("Only declarations are stored")
+        };
+        let scopes = std::mem::replace(
+            &mut self.scopes,
+            vec![parameters.iter().cloned().collect()],
+        );
+        let function = std::mem::replace(&mut self.function, name.to_string());
+        let body = self.body(body);
+        self.scopes = scopes;
+        self.function = function;
+
+        self.bodies.insert(name.to_string(), body.clone());
+        body
+    }
+
+    fn candidate(&mut self, name: &str) -> Option<Candidate> {
+        if let Some(candidate) = self.candidates.get(name) {
+            return candidate.clone();
+        }
+
+        let candidate = self.find_candidate(name);
+        self.candidates.insert(name.to_string(), candidate.clone());
+        candidate
+    }
+
+    fn find_candidate(&mut self, name: &str) -> Option<Candidate> {
+        let declaration: &'a Operand = self.declarations.get(name)?;
+        let Operand::FunctionDecl(return_type, _, body, parameters, hint) = declaration else {
+            unreachable!("Only declarations are stored")
+        };
+        if *hint == InlineHint::Never || self.recursive.contains(name) || !can_inline(body, true) {
+            return None;
+        }
+
+        let mut body = self.processed(name);
+        let end = body.iter().position(|v| matches!(v, Operand::Return(_)))?;
+        body.truncate(end + 1);
+        let Some(Operand::Return(result)) = body.pop() else {
+            unreachable!("Found above")
+        };
+        if *hint == InlineHint::Auto && size(&body) > self.threshold {
+            return None;
+        }
+
+        Some(Candidate {
+            return_type: return_type.clone(),
+            parameters: parameters.clone(),
+            body,
+            result,
+        })
+    }
+
+    /// Whether evaluating the value runs the body of an inlined function
+    fn inlines(&mut self, value: &Value) -> bool {
+        match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => self.inlines(lhs) || self.inlines(rhs),
+            Value::FunctionCall(name, parameters) => {
+                self.candidate(name).is_some() || parameters.iter().any(|v| self.inlines(v))
+            }
+            _ => false,
+        }
+    }
+
+    /// Types of the parameters of a function, an argument past them has the type of its value
+    fn parameter_types(&self, name: &str) -> Vec<Option<OperandType>> {
+        self.functions
+            .get_function_type(name)
+            .map(|v| v.1.iter().cloned().map(Some).collect())
+            .unwrap_or_default()
+    }
+
+    /// Stores a value that was evaluated before an inlined call, so the call can't change it.
+    /// `ty` is the type the value is used as, which literals take on
+    fn spill(
+        &mut self,
+        value: Value,
+        ty: Option<OperandType>,
+        before: &mut Vec<Operand>,
+        after: &mut Vec<Operand>,
+    ) -> Value {
+        if is_stable(&value, &self.results) {
+            return value;
+        }
+
+        let ty = self.value_type(&value).or(ty).unwrap_or(DEFAULT_TYPE);
+        let name = self.fresh("spill");
+        self.declare(&name, &ty);
+        self.results.insert(name.clone());
+        before.push(Operand::DeclareVariable(ty, name.clone(), value));
+        after.push(Operand::DropVariable(name.clone()));
+        Value::Variable(name)
+    }
+
+    /// Evaluates values in order, keeping their order with respect to inlined calls. `types`
+    /// holds the type each value is used as, where it's known
+    fn values(
+        &mut self,
+        values: &[Value],
+        types: &[Option<OperandType>],
+        before: &mut Vec<Operand>,
+        after: &mut Vec<Operand>,
+    ) -> Vec<Value> {
+        let mut out = vec![];
+        for (i, value) in values.iter().enumerate() {
+            let ty = types.get(i).cloned().flatten();
+            let value = self.value(value, ty.as_ref(), before, after);
+            if values[i + 1..].iter().any(|v| self.inlines(v)) {
+                out.push(self.spill(value, ty, before, after));
+            } else {
+                out.push(value);
+            }
+        }
+        out
+    }
+
+    /// Rewrites a value used as `ty`, the bodies of inlined calls are put in `before` and the
+    /// drops of the variables they introduce in `after`
+    fn value(
+        &mut self,
+        value: &Value,
+        ty: Option<&OperandType>,
+        before: &mut Vec<Operand>,
+        after: &mut Vec<Operand>,
+    ) -> Value {
+        match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                // Both sides have the type of the whole
+                let ty = self.value_type(value).or(ty.cloned());
+                let mut values = self.values(&[*lhs.clone(), *rhs.clone()], &[ty.clone(), ty], before, after);
+                let rhs = Box::new(values.pop().expect("Two values"));
+                let lhs = Box::new(values.pop().expect("Two values"));
+                match value {
+                    Value::Add(..) => Value::Add(lhs, rhs),
+                    _ => Value::Sub(lhs, rhs),
+                }
+            }
+            Value::FunctionCall(name, parameters) => {
+                let types = self.parameter_types(name);
+                let parameters = self.values(parameters, &types, before, after);
+                match self.candidate(name) {
+                    Some(candidate) => self.expand(name, candidate, parameters, before, after),
+                    None => Value::FunctionCall(name.clone(), parameters),
+                }
+            }
+            _ => value.clone(),
+        }
+    }
+
+    /// Places the body of `candidate` in `before`, returning its result
+    fn expand(
+        &mut self,
+        name: &str,
+        candidate: Candidate,
+        arguments: Vec<Value>,
+        before: &mut Vec<Operand>,
+        after: &mut Vec<Operand>,
+    ) -> Value {
+        self.report.calls.push((self.function.clone(), name.to_string()));
+
+        let mut locals = HashSet::new();
+        variable_names(&candidate.body, &mut locals);
+        locals.extend(candidate.parameters.iter().map(|v| v.0.clone()));
+        let mut locals: Vec<String> = locals.into_iter().collect();
+        locals.sort();
+        let names: HashMap<String, String> = locals
+            .into_iter()
+            .map(|v| {
+                let fresh = self.fresh(&v);
+                (v, fresh)
+            })
+            .collect();
+
+        // Variables still declared once the body is done
+        let mut remaining = vec![];
+        for ((parameter, ty), argument) in candidate.parameters.iter().zip(arguments) {
+            before.push(Operand::DeclareVariable(ty.clone(), names[parameter].clone(), argument));
+            remaining.push(names[parameter].clone());
+        }
+        let body = rename_body(&candidate.body, &names);
+        for operand in &body {
+            match operand {
+                Operand::DeclareVariable(_, name, _) => remaining.push(name.clone()),
+                Operand::DropVariable(name) => remaining.retain(|v| v != name),
+                _ => {}
+            }
+        }
+        before.extend(body);
+
+        let result = match candidate.result {
+            Value::Null => Value::Null,
+            result => {
+                let variable = self.fresh(name);
+                self.declare(&variable, &candidate.return_type);
+                self.results.insert(variable.clone());
+                before.push(Operand::DeclareVariable(
+                    candidate.return_type.clone(),
+                    variable.clone(),
+                    rename_value(&result, &names),
+                ));
+                remaining.push(variable.clone());
+                Value::Variable(variable)
+            }
+        };
+        after.extend(remaining.into_iter().map(Operand::DropVariable));
+        result
+    }
+
+    fn body(&mut self, body: &[Operand]) -> Vec<Operand> {
+        let mut out = vec![];
+        for operand in body {
+            let mut before = vec![];
+            let mut after = vec![];
+            let operand = match operand {
+                Operand::DeclareVariable(ty, name, value) => {
+                    let value = self.value(value, Some(ty), &mut before, &mut after);
+                    self.declare(name, ty);
+                    Operand::DeclareVariable(ty.clone(), name.clone(), value)
+                }
+                Operand::SetValue(lhs, value) => {
+                    let ty = self.value_type(lhs);
+                    Operand::SetValue(lhs.clone(), self.value(value, ty.as_ref(), &mut before, &mut after))
+                }
+                Operand::FunctionCall(name, parameters) => {
+                    let types = self.parameter_types(name);
+                    let parameters = self.values(parameters, &types, &mut before, &mut after);
+                    match self.candidate(name) {
+                        Some(candidate) => {
+                            self.expand(name, candidate, parameters, &mut before, &mut after);
+                            out.extend(before);
+                            out.extend(after);
+                            continue;
+                        }
+                        None => Operand::FunctionCall(name.clone(), parameters),
+                    }
+                }
+                Operand::If {
+                    predicate,
+                    main_body,
+                } => {
+                    // Both sides are compared as the type of whichever has one
+                    let ty = self.value_type(&predicate.lhs).or(self.value_type(&predicate.rhs));
+                    let sides = [predicate.lhs.clone(), predicate.rhs.clone()];
+                    let mut sides = self.values(&sides, &[ty.clone(), ty], &mut before, &mut after);
+                    let rhs = sides.pop().expect("Two values");
+                    let lhs = sides.pop().expect("Two values");
+
+                    self.scopes.push(HashMap::new());
+                    let main_body = self.body(main_body);
+                    self.scopes.pop();
+                    Operand::If {
+                        predicate: ComparePredicate {
+                            operation: predicate.operation,
+                            lhs,
+                            rhs,
+                        },
+                        main_body,
+                    }
+                }
+                Operand::Switch {
+                    scrutinee,
+                    cases,
+                    default,
+                } => {
+                    let scrutinee = self.value(scrutinee, None, &mut before, &mut after);
+                    let mut body = |body: &[Operand]| {
+                        self.scopes.push(HashMap::new());
+                        let body = self.body(body);
+                        self.scopes.pop();
+                        body
+                    };
+                    Operand::Switch {
+                        scrutinee,
+                        cases: cases.iter().map(|(case, v)| (*case, body(v))).collect(),
+                        default: body(default),
+                    }
+                }
+                Operand::Return(value) => {
+                    let ty = self.functions.get_function_type(&self.function).map(|v| v.0.clone());
+                    let value = self.value(value, ty.as_ref(), &mut before, &mut after);
+                    out.extend(before);
+                    out.push(Operand::Return(value));
+                    continue;
+                }
+                Operand::DropVariable(name) => {
+                    if let Some(scope) = self.scopes.iter_mut().rev().find(|v| v.contains_key(name)) {
+                        scope.remove(name);
+                    }
+                    operand.clone()
+                }
+                _ => operand.clone(),
+            };
+            out.extend(before);
+            out.push(operand);
+            out.extend(after);
+        }
+        out
+    }
+}
@@ -0,0 +1,430 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::*;
+
+/// Bytes of simulated memory, static data sits at the bottom and the stack grows down from the top
+const MEMORY_SIZE: usize = 1 << 20;
+/// Address zero stays unmapped so null pointers fault
+const DATA_START: u64 = 16;
+/// Every interpreted call recurses on the host stack, this stays well inside a default 8MiB one
+const DEFAULT_MAX_CALL_DEPTH: usize = 500;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InterpretError {
+    UndefinedVariable(String),
+    UndefinedFunction(String),
+    ArityMismatch {
+        function: String,
+        expected: usize,
+        found: usize,
+    },
+    NotAPointer(String),
+    NotAnLvalue(Value),
+    InvalidInteger(String),
+    /// A read or write outside of the simulated memory
+    Segfault(u64),
+    StackOverflow,
+    NoReturn(String),
+    Unsupported(String),
+}
+
+impl Display for InterpretError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpretError::UndefinedVariable(name) => write!(f, "Variable {name} does not exist"),
+            InterpretError::UndefinedFunction(name) => write!(f, "Function {name} does not exist"),
+            InterpretError::ArityMismatch {
+                function,
+                expected,
+                found,
+            } => write!(f, "{function} takes {expected} arguments but {found} were given"),
+            InterpretError::NotAPointer(name) => write!(f, "Variable {name} is not a pointer"),
+            InterpretError::NotAnLvalue(value) => write!(f, "{value} can't be assigned to"),
+            InterpretError::InvalidInteger(num) => write!(f, "{num} is not a valid integer"),
+            InterpretError::Segfault(address) => write!(f, "Invalid memory access at {address:#x}"),
+            InterpretError::StackOverflow => write!(f, "Stack overflow"),
+            InterpretError::NoReturn(name) => write!(f, "Function {name} ended without returning"),
+            InterpretError::Unsupported(what) => write!(f, "{what} can't be interpreted"),
+        }
+    }
+}
+
+impl std::error::Error for InterpretError {}
+
+/// Wraps `value` to the width of `ty`, sign or zero extending it back to 64 bits
+pub fn wrap_to_type(value: i64, ty: &OperandType) -> i64 {
+    let bits = ty.size().get_bytes() as u32 * 8;
+    if bits == 64 {
+        return value;
+    }
+
+    let shift = 64 - bits;
+    if matches!(ty, OperandType::Int(_)) {
+        (value << shift) >> shift
+    } else {
+        ((value as u64) << shift >> shift) as i64
+    }
+}
+
+/// Type literals take on when nothing else decides it, matching `Value::size`
+pub(crate) const DEFAULT_TYPE: OperandType = OperandType::Int(Size::DoubleWord);
+
+type Function = (OperandType, Vec<(String, OperandType)>, Vec<Operand>);
+
+/// Executes `Operand` trees directly, used as the reference for what a program should compute
+pub struct Interpreter {
+    functions: HashMap<String, Function>,
+    memory: Vec<u8>,
+    data_end: u64,
+    stack_pointer: u64,
+    /// Innermost scope is last, each function call starts from a fresh list
+    scopes: Vec<HashMap<String, (u64, OperandType)>>,
+    return_type: OperandType,
+    depth: usize,
+    max_call_depth: usize,
+}
+
+enum Flow {
+    Continue,
+    Return(i64),
+}
+
+impl Interpreter {
+    pub fn new(operands: &[Operand]) -> Result<Self, InterpretError> {
+        let mut functions = HashMap::new();
+        for operand in operands {
+            match operand {
+                Operand::FunctionDecl(return_type, name, body, parameters, ..) => {
+                    functions.insert(
+                        name.clone(),
+                        (return_type.clone(), parameters.clone(), body.clone()),
+                    );
+                }
+                other => return Err(InterpretError::Unsupported(format!("Top level {other}"))),
+            }
+        }
+
+        Ok(Self {
+            functions,
+            memory: vec![0; MEMORY_SIZE],
+            data_end: DATA_START,
+            stack_pointer: MEMORY_SIZE as u64,
+            scopes: vec![],
+            return_type: DEFAULT_TYPE,
+            depth: 0,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+        })
+    }
+
+    /// Raise this when running on a thread with a bigger stack
+    pub fn set_max_call_depth(&mut self, depth: usize) {
+        self.max_call_depth = depth;
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Calls `name` with arguments that are wrapped to the parameter types, returning the
+    /// result wrapped to the return type
+    pub fn call(&mut self, name: &str, arguments: &[i64]) -> Result<i64, InterpretError> {
+        let (return_type, parameters, body) = self
+            .functions
+            .get(name)
+            .cloned()
+            .ok_or_else(|| InterpretError::UndefinedFunction(name.to_string()))?;
+
+        if parameters.len() != arguments.len() {
+            return Err(InterpretError::ArityMismatch {
+                function: name.to_string(),
+                expected: parameters.len(),
+                found: arguments.len(),
+            });
+        }
+        if self.depth >= self.max_call_depth {
+            return Err(InterpretError::StackOverflow);
+        }
+
+        let saved_scopes = std::mem::replace(&mut self.scopes, vec![HashMap::new()]);
+        let saved_stack_pointer = self.stack_pointer;
+        let saved_return_type = std::mem::replace(&mut self.return_type, return_type.clone());
+        self.depth += 1;
+
+        let result = (|| {
+            for ((parameter, ty), argument) in parameters.iter().zip(arguments) {
+                self.declare(parameter, ty, *argument)?;
+            }
+            match self.body(&body)? {
+                Flow::Return(value) => Ok(wrap_to_type(value, &return_type)),
+                Flow::Continue => Err(InterpretError::NoReturn(name.to_string())),
+            }
+        })();
+
+        self.depth -= 1;
+        self.return_type = saved_return_type;
+        self.stack_pointer = saved_stack_pointer;
+        self.scopes = saved_scopes;
+        result
+    }
+
+    pub fn read(&self, address: u64, size: Size) -> Result<u64, InterpretError> {
+        let bytes = size.get_bytes() as usize;
+        let start = address as usize;
+        if address < DATA_START || start + bytes > self.memory.len() {
+            return Err(InterpretError::Segfault(address));
+        }
+
+        let mut buffer = [0u8; 8];
+        buffer[..bytes].copy_from_slice(&self.memory[start..start + bytes]);
+        Ok(u64::from_le_bytes(buffer))
+    }
+
+    pub fn write(&mut self, address: u64, size: Size, value: u64) -> Result<(), InterpretError> {
+        let bytes = size.get_bytes() as usize;
+        let start = address as usize;
+        if address < DATA_START || start + bytes > self.memory.len() {
+            return Err(InterpretError::Segfault(address));
+        }
+
+        self.memory[start..start + bytes].copy_from_slice(&value.to_le_bytes()[..bytes]);
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Result<(u64, OperandType), InterpretError> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|v| v.get(name))
+            .cloned()
+            .ok_or_else(|| InterpretError::UndefinedVariable(name.to_string()))
+    }
+
+    fn declare(&mut self, name: &str, ty: &OperandType, value: i64) -> Result<(), InterpretError> {
+        let bytes = ty.size().get_bytes() as u64;
+        let address = (self.stack_pointer - bytes) / bytes * bytes;
+        if address < self.data_end {
+            return Err(InterpretError::StackOverflow);
+        }
+        self.stack_pointer = address;
+
+        self.write(address, ty.size(), value as u64)?;
+        self.scopes
+            .last_mut()
+            .expect("Functions always have a scope")
+            .insert(name.to_string(), (address, ty.clone()));
+        Ok(())
+    }
+
+    fn load(&self, address: u64, ty: &OperandType) -> Result<i64, InterpretError> {
+        Ok(wrap_to_type(self.read(address, ty.size())? as i64, ty))
+    }
+
+    fn body(&mut self, body: &[Operand]) -> Result<Flow, InterpretError> {
+        for operand in body {
+            if let Flow::Return(value) = self.operand(operand)? {
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Continue)
+    }
+
+    fn operand(&mut self, operand: &Operand) -> Result<Flow, InterpretError> {
+        match operand {
+            Operand::DeclareVariable(ty, name, value) => {
+                let value = self.value(value, Some(ty))?;
+                self.declare(name, ty, wrap_to_type(value, ty))?;
+            }
+            Operand::SetValue(lhs, value) => {
+                let (address, ty) = match lhs {
+                    Value::Variable(name) => self.lookup(name)?,
+                    Value::Dereference(name) => self.pointee(name)?,
+                    other => return Err(InterpretError::NotAnLvalue(other.clone())),
+                };
+                let value = self.value(value, Some(&ty))?;
+                self.write(address, ty.size(), value as u64)?;
+            }
+            Operand::FunctionCall(name, parameters) => {
+                self.function_call(name, parameters)?;
+            }
+            Operand::If {
+                predicate,
+                main_body,
+            } => {
+                if self.predicate(predicate)? {
+                    self.scopes.push(HashMap::new());
+                    let flow = self.body(main_body);
+                    self.scopes.pop();
+                    return flow;
+                }
+            }
+            Operand::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => {
+                let ty = self.value_type(scrutinee)?.unwrap_or(DEFAULT_TYPE);
+                let value = self.value(scrutinee, Some(&ty))?;
+                let body = cases
+                    .iter()
+                    .find(|v| wrap_to_type(v.0, &ty) == value)
+                    .map_or(default, |v| &v.1);
+                self.scopes.push(HashMap::new());
+                let flow = self.body(body);
+                self.scopes.pop();
+                return flow;
+            }
+            Operand::Return(Value::Null) => return Ok(Flow::Return(0)),
+            Operand::Return(value) => {
+                let return_type = self.return_type.clone();
+                return Ok(Flow::Return(self.value(value, Some(&return_type))?));
+            }
+            Operand::DropVariable(name) => {
+                self.lookup(name)?;
+                for scope in self.scopes.iter_mut().rev() {
+                    if scope.remove(name).is_some() {
+                        break;
+                    }
+                }
+            }
+            Operand::InlineAssembly(_) | Operand::ExtendedAssembly(_) => {
+                return Err(InterpretError::Unsupported("Inline assembly".to_string()))
+            }
+            Operand::FunctionDecl(_, name, ..) => {
+                return Err(InterpretError::Unsupported(format!("Nested function {name}")))
+            }
+            // These don't generate any code either
+            Operand::Add(..) | Operand::Subtract(..) => {}
+        }
+        Ok(Flow::Continue)
+    }
+
+    /// The address a pointer variable points to and the type stored there
+    fn pointee(&self, name: &str) -> Result<(u64, OperandType), InterpretError> {
+        let (address, ty) = self.lookup(name)?;
+        let OperandType::Pointer(inner) = ty else {
+            return Err(InterpretError::NotAPointer(name.to_string()));
+        };
+        Ok((self.read(address, Size::QuadWord)?, *inner))
+    }
+
+    pub fn predicate(&mut self, predicate: &ComparePredicate) -> Result<bool, InterpretError> {
+        let ty = self
+            .value_type(&predicate.lhs)?
+            .or(self.value_type(&predicate.rhs)?)
+            .unwrap_or(DEFAULT_TYPE);
+        let lhs = self.value(&predicate.lhs, Some(&ty))?;
+        let rhs = self.value(&predicate.rhs, Some(&ty))?;
+
+        Ok(compare(predicate.operation, lhs, rhs, &ty))
+    }
+
+    fn function_call(&mut self, name: &str, parameters: &[Value]) -> Result<i64, InterpretError> {
+        let types = self
+            .functions
+            .get(name)
+            .ok_or_else(|| InterpretError::UndefinedFunction(name.to_string()))?
+            .1
+            .iter()
+            .map(|v| v.1.clone())
+            .collect::<Vec<OperandType>>();
+
+        let mut arguments = vec![];
+        for (i, value) in parameters.iter().enumerate() {
+            arguments.push(self.value(value, types.get(i))?);
+        }
+        self.call(name, &arguments)
+    }
+
+    fn value_type(&self, value: &Value) -> Result<Option<OperandType>, InterpretError> {
+        Ok(match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                self.value_type(lhs)?.or(self.value_type(rhs)?)
+            }
+            Value::Reference(name) => Some(OperandType::Pointer(Box::new(self.lookup(name)?.1))),
+            Value::Dereference(name) => match self.lookup(name)?.1 {
+                OperandType::Pointer(inner) => Some(*inner),
+                _ => return Err(InterpretError::NotAPointer(name.clone())),
+            },
+            Value::Variable(name) => Some(self.lookup(name)?.1),
+            Value::FunctionCall(name, _) => Some(
+                self.functions
+                    .get(name)
+                    .ok_or_else(|| InterpretError::UndefinedFunction(name.clone()))?
+                    .0
+                    .clone(),
+            ),
+            Value::Char(_) => Some(OperandType::Char),
+            Value::StringLiteral(_) => Some(OperandType::Pointer(Box::new(OperandType::Char))),
+            Value::Int(_) | Value::Null => None,
+        })
+    }
+
+    /// Evaluates `value` and wraps it to `expected`, or to its own type when there is none
+    pub fn value(
+        &mut self,
+        value: &Value,
+        expected: Option<&OperandType>,
+    ) -> Result<i64, InterpretError> {
+        let own = self.value_type(value)?;
+        let ty = expected.or(own.as_ref()).unwrap_or(&DEFAULT_TYPE).clone();
+
+        let result = match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                let lhs = self.value(lhs, Some(&ty))?;
+                let rhs = self.value(rhs, Some(&ty))?;
+                if matches!(value, Value::Add(..)) {
+                    lhs.wrapping_add(rhs)
+                } else {
+                    lhs.wrapping_sub(rhs)
+                }
+            }
+            Value::Reference(name) => self.lookup(name)?.0 as i64,
+            Value::Dereference(name) => {
+                let (address, inner) = self.pointee(name)?;
+                self.load(address, &inner)?
+            }
+            Value::Variable(name) => {
+                let (address, ty) = self.lookup(name)?;
+                self.load(address, &ty)?
+            }
+            Value::Char(c) => *c as i64,
+            Value::Int(num) => {
+                parse_int_literal(num).ok_or_else(|| InterpretError::InvalidInteger(num.clone()))?
+            }
+            Value::StringLiteral(literal) => {
+                let address = self.data_end;
+                let bytes = literal.as_bytes();
+                if address as usize + bytes.len() + 1 > self.stack_pointer as usize {
+                    return Err(InterpretError::StackOverflow);
+                }
+                self.memory[address as usize..address as usize + bytes.len()]
+                    .copy_from_slice(bytes);
+                self.memory[address as usize + bytes.len()] = 0;
+                self.data_end += bytes.len() as u64 + 1;
+                address as i64
+            }
+            Value::FunctionCall(name, parameters) => self.function_call(name, parameters)?,
+            Value::Null => return Err(InterpretError::Unsupported("null value".to_string())),
+        };
+
+        Ok(wrap_to_type(result, &ty))
+    }
+}
+
+pub(crate) fn compare(operation: CompareOperation, lhs: i64, rhs: i64, ty: &OperandType) -> bool {
+    let ordering = if matches!(ty, OperandType::Int(_)) {
+        lhs.cmp(&rhs)
+    } else {
+        (lhs as u64).cmp(&(rhs as u64))
+    };
+
+    match operation {
+        CompareOperation::GT => ordering.is_gt(),
+        CompareOperation::GTE => ordering.is_ge(),
+        CompareOperation::LT => ordering.is_lt(),
+        CompareOperation::LTE => ordering.is_le(),
+        CompareOperation::EQ => ordering.is_eq(),
+        CompareOperation::NEQ => ordering.is_ne(),
+    }
+}
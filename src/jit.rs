@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::fmt::Display;
+
+use crate::*;
+
+const PROT_READ: i32 = 0x1;
+const PROT_WRITE: i32 = 0x2;
+const PROT_EXEC: i32 = 0x4;
+const MAP_PRIVATE: i32 = 0x02;
+const MAP_ANONYMOUS: i32 = 0x20;
+/// Keeps the mapping in the low 2GiB so `R_X86_64_32S` references still fit
+const MAP_32BIT: i32 = 0x40;
+
+const PAGE_SIZE: usize = 4096;
+/// `jmp [rip]` followed by the 8 byte target, host symbols are usually out of rel32 range
+const STUB_SIZE: usize = 16;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, offset: i64) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: i32) -> i32;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+#[derive(Debug)]
+pub enum JitError {
+    Encode(EncodeError),
+    /// A symbol that is neither compiled, a string define or passed in as a host symbol
+    UndefinedSymbol(String),
+    /// An absolute 32 bit reference to an address above 2GiB
+    OutOfRange(String),
+    Memory(std::io::Error),
+}
+
+impl Display for JitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JitError::Encode(e) => e.fmt(f),
+            JitError::UndefinedSymbol(name) => write!(f, "Symbol {name} is not defined"),
+            JitError::OutOfRange(name) => write!(f, "Address of {name} doesn't fit in 32 bits"),
+            JitError::Memory(e) => write!(f, "Unable to map executable memory: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for JitError {}
+
+impl From<EncodeError> for JitError {
+    fn from(value: EncodeError) -> Self {
+        JitError::Encode(value)
+    }
+}
+
+/// Compiled functions mapped into executable memory, unmapped when dropped
+pub struct JitModule {
+    memory: *mut u8,
+    len: usize,
+    functions: HashMap<String, usize>,
+}
+
+impl JitModule {
+    /// Address of a compiled function
+    pub fn get_raw(&self, name: &str) -> Option<*const u8> {
+        self.functions
+            .get(name)
+            .map(|offset| self.memory.wrapping_add(*offset) as *const u8)
+    }
+
+    /// A compiled function as `F`, which should be an `extern "C" fn` matching its signature.
+    ///
+    /// # Safety
+    /// `F` has to match the IR signature and the pointer must not outlive the module
+    pub unsafe fn get<F: Copy>(&self, name: &str) -> Option<F> {
+        assert_eq!(
+            std::mem::size_of::<F>(),
+            std::mem::size_of::<*const u8>(),
+            "F has to be a function pointer"
+        );
+        self.get_raw(name)
+            .map(|address| std::mem::transmute_copy::<*const u8, F>(&address))
+    }
+}
+
+impl Drop for JitModule {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.memory as *mut c_void, self.len);
+        }
+    }
+}
+
+fn map(len: usize) -> Result<*mut u8, JitError> {
+    let prot = PROT_READ | PROT_WRITE;
+    let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+    unsafe {
+        let mut memory = mmap(std::ptr::null_mut(), len, prot, flags | MAP_32BIT, -1, 0);
+        if memory as isize == -1 {
+            // Only absolute 32 bit references need the low mapping, they fail to resolve instead
+            memory = mmap(std::ptr::null_mut(), len, prot, flags, -1, 0);
+        }
+        if memory as isize == -1 {
+            return Err(JitError::Memory(std::io::Error::last_os_error()));
+        }
+        Ok(memory as *mut u8)
+    }
+}
+
+fn protect(memory: *mut u8, len: usize, prot: i32) -> Result<(), JitError> {
+    if len == 0 {
+        return Ok(());
+    }
+    if unsafe { mprotect(memory as *mut c_void, len, prot) } != 0 {
+        return Err(JitError::Memory(std::io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+impl Compiler {
+    /// Compiles every function into executable memory of this process.
+    ///
+    /// Calls between compiled functions are resolved directly, everything declared with
+    /// [`Compiler::declare_extern`] is looked up in `symbols` by name.
+    pub fn compile_jit(mut self, symbols: &[(&str, *const u8)]) -> Result<JitModule, JitError> {
+        if self.target != Target::X86_64 {
+            return Err(EncodeError::Unsupported(format!("{:?} in the JIT", self.target)).into());
+        }
+        self.optimize_operands();
+
+        let names = self.declare_functions();
+        self.codegen_operands();
+        let encoded = encode_x86(&self.compiled)?;
+
+        let mut rodata = vec![];
+        let mut data = HashMap::new();
+        for (name, value) in &self.string_defines {
+            data.insert(name.clone(), rodata.len());
+            rodata.extend_from_slice(&unescape(value));
+            rodata.push(0);
+        }
+        // Filled in with the absolute address of every entry once the code is mapped
+        let mut tables = vec![];
+        for (name, entries) in &self.jump_tables {
+            rodata.resize(rodata.len().next_multiple_of(8), 0);
+            data.insert(name.clone(), rodata.len());
+            tables.push((rodata.len(), entries));
+            rodata.resize(rodata.len() + entries.len() * 8, 0);
+        }
+
+        let host: HashMap<&str, *const u8> = symbols.iter().copied().collect();
+        let mut stubs = vec![];
+        for relocation in &encoded.relocations {
+            if !data.contains_key(&relocation.symbol) && !stubs.contains(&relocation.symbol) {
+                stubs.push(relocation.symbol.clone());
+            }
+        }
+
+        let stubs_start = encoded.code.len().next_multiple_of(STUB_SIZE);
+        let text_len = (stubs_start + stubs.len() * STUB_SIZE)
+            .next_multiple_of(PAGE_SIZE)
+            .max(PAGE_SIZE);
+        let len = text_len + rodata.len().next_multiple_of(PAGE_SIZE);
+        let memory = map(len)?;
+        let module = JitModule {
+            memory,
+            len,
+            functions: names
+                .into_iter()
+                .map(|name| {
+                    let offset = encoded.labels[&name];
+                    (name, offset)
+                })
+                .collect(),
+        };
+
+        // Safety: every write below stays within the `len` bytes just mapped
+        let image = unsafe { std::slice::from_raw_parts_mut(memory, len) };
+        image[..encoded.code.len()].copy_from_slice(&encoded.code);
+        image[text_len..text_len + rodata.len()].copy_from_slice(&rodata);
+
+        let mut addresses = HashMap::new();
+        for (i, name) in stubs.iter().enumerate() {
+            let target = *host
+                .get(name.as_str())
+                .ok_or_else(|| JitError::UndefinedSymbol(name.clone()))?;
+            let stub = stubs_start + i * STUB_SIZE;
+            image[stub..stub + 6].copy_from_slice(&[0xFF, 0x25, 0, 0, 0, 0]);
+            image[stub + 6..stub + 14].copy_from_slice(&(target as u64).to_le_bytes());
+            addresses.insert(name.clone(), (target as u64, memory as u64 + stub as u64));
+        }
+        for (offset, entries) in tables {
+            for (i, label) in entries.iter().enumerate() {
+                let target = *encoded
+                    .labels
+                    .get(label)
+                    .ok_or_else(|| EncodeError::UndefinedLabel(label.clone()))?;
+                let entry = text_len + offset + i * 8;
+                image[entry..entry + 8].copy_from_slice(&(memory as u64 + target as u64).to_le_bytes());
+            }
+        }
+        for (name, offset) in &data {
+            let address = memory as u64 + (text_len + offset) as u64;
+            addresses.insert(name.clone(), (address, address));
+        }
+
+        for relocation in &encoded.relocations {
+            let (absolute, near) = addresses[&relocation.symbol];
+            let place = memory as u64 + relocation.offset as u64;
+            let field = &mut image[relocation.offset..];
+            match relocation.kind {
+                RelocationKind::Pc32 | RelocationKind::Plt32 => {
+                    let value = near.wrapping_add(relocation.addend as u64).wrapping_sub(place) as i64;
+                    let value = i32::try_from(value)
+                        .map_err(|_| JitError::OutOfRange(relocation.symbol.clone()))?;
+                    field[..4].copy_from_slice(&value.to_le_bytes());
+                }
+                RelocationKind::Abs64 => {
+                    let value = absolute.wrapping_add(relocation.addend as u64);
+                    field[..8].copy_from_slice(&value.to_le_bytes());
+                }
+                RelocationKind::Abs32S => {
+                    let value = absolute.wrapping_add(relocation.addend as u64) as i64;
+                    let value = i32::try_from(value)
+                        .map_err(|_| JitError::OutOfRange(relocation.symbol.clone()))?;
+                    field[..4].copy_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+
+        // Never writable and executable at the same time
+        protect(memory, text_len, PROT_READ | PROT_EXEC)?;
+        protect(memory.wrapping_add(text_len), len - text_len, PROT_READ)?;
+
+        Ok(module)
+    }
+}
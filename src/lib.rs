@@ -24,3 +24,49 @@ pub use operand::*;
 
 mod assembly;
 pub use assembly::*;
+
+mod inline;
+pub use inline::*;
+
+mod fold;
+pub use fold::*;
+
+mod dead_code;
+pub use dead_code::*;
+
+mod ssa;
+pub use ssa::*;
+
+mod peephole;
+pub use peephole::*;
+
+mod encoder;
+pub use encoder::*;
+
+mod elf;
+pub use elf::*;
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+mod jit;
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub use jit::*;
+
+mod text;
+pub use text::*;
+
+mod target;
+pub use target::*;
+
+mod validate;
+pub use validate::*;
+
+mod interpreter;
+pub use interpreter::*;
+
+mod builder;
+pub use builder::*;
+
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "serde")]
+pub use serialize::*;
@@ -1,6 +1,11 @@
 use crate::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum CompareOperation
 {
     GT,
@@ -26,6 +31,20 @@ impl CompareOperation
         }.to_string()
     }
 
+    /// The suffix when both sides are compared as unsigned numbers
+    pub fn as_unsigned_suffix(&self) -> String
+    {
+        match self
+        {
+            CompareOperation::GT => "a",
+            CompareOperation::GTE => "ae",
+            CompareOperation::LT => "b",
+            CompareOperation::LTE => "be",
+            CompareOperation::EQ => "e",
+            CompareOperation::NEQ => "ne",
+        }.to_string()
+    }
+
     pub fn get_opposite(&self) -> Self
     {
         match self
@@ -40,7 +59,8 @@ impl CompareOperation
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ComparePredicate
 {
     pub operation : CompareOperation,
@@ -51,34 +71,52 @@ pub struct ComparePredicate
 pub fn if_statement(predicate : &ComparePredicate, main_body : &[Operand], compiler : &mut Compiler)
 {
     let ComparePredicate { operation, lhs, rhs } = predicate;
-    let _rhs= rhs;
-    let rhs = lhs;
-    let lhs = _rhs;
 
-    let op_size = lhs.size(compiler);
+    // Literal arithmetic on one side is done at the size of the other
+    let op_size = lhs.estimate_size(compiler).or(rhs.estimate_size(compiler)).unwrap_or(rhs.size(compiler));
+    let signed = lhs.estimate_type(compiler).or(rhs.estimate_type(compiler)).is_none_or(|v| v.is_signed());
 
-    let mut lhs_gen = rhs.codegen(compiler);
-    let rhs_gen = lhs.codegen_size(compiler, &op_size);
-
-    if lhs_gen.is_stack() && rhs_gen.is_stack() || lhs_gen.is_immediate()
+    let body_reads = read_registers(main_body, compiler);
+    compiler.with_live(&body_reads, |compiler|
     {
-        let new_location = Register::AX.as_gen(&op_size);
-        compiler.new_instruction(Instruction::Move(new_location.clone(), lhs_gen));
-        lhs_gen = new_location;
-    }
+        let rhs_reads = rhs.read_registers(compiler);
+        let mut lhs_gen = compiler.with_live(&rhs_reads, |c| lhs.codegen_size(c, &op_size));
 
-    compiler.new_instruction(Instruction::Compare(lhs_gen, rhs_gen));
+        if rhs.clobbers_accumulator()
+        {
+            // lhs may be in AX, or in a register a call in rhs overwrites
+            let lhs = compiler.preserve(lhs_gen, &op_size);
+            let rhs_gen = rhs.codegen_size(compiler, &op_size);
+            compiler.new_instruction(Instruction::Compare(lhs.operand.clone(), rhs_gen));
+            compiler.release(lhs);
+            return;
+        }
+
+        let rhs_gen = rhs.codegen_size(compiler, &op_size);
+        if lhs_gen.is_memory() && rhs_gen.is_memory() || lhs_gen.is_immediate()
+        {
+            let new_location = Register::AX.as_gen(&op_size);
+            compiler.new_instruction(Instruction::Move(new_location.clone(), lhs_gen));
+            lhs_gen = new_location;
+        }
+
+        let rhs_gen = compiler.fit_immediate(rhs_gen, &op_size);
+        compiler.new_instruction(Instruction::Compare(lhs_gen, rhs_gen));
+    });
 
     let id = compiler.fetch_id(".IF");
 
-    let jump_instr = Instruction::JumpConditional { label_destination: id.clone(), conditional: operation.get_opposite() };
+    let jump_instr = Instruction::JumpConditional { label_destination: id.clone(), conditional: operation.get_opposite(), signed };
 
     compiler.new_instruction(jump_instr);
 
-    main_body.iter().for_each(|v|
+    compiler.scope_manager.get_variable_manager().enter_block();
+    for (i, operand) in main_body.iter().enumerate()
     {
-        v.codegen(compiler);
-    });
+        let later = read_registers(&main_body[i + 1..], compiler);
+        compiler.with_live(&later, |c| operand.codegen(c));
+    }
+    compiler.scope_manager.get_variable_manager().leave_block();
 
     compiler.new_instruction(Instruction::Label(id));
-}
\ No newline at end of file
+}
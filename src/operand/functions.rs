@@ -1,5 +1,23 @@
+use std::collections::HashSet;
+
 use crate::*;
 
+/// Whether the inliner may replace calls to a function with its body
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum InlineHint {
+    /// Inlined when the body is small enough
+    #[default]
+    Auto,
+    /// Inlined regardless of its size
+    Always,
+    Never,
+}
+
 pub fn function_call(name: &str, parameters: &[Value], compiler: &mut Compiler) -> Size {
     let (return_type, params) = compiler
         .scope_manager
@@ -7,24 +25,165 @@ pub fn function_call(name: &str, parameters: &[Value], compiler: &mut Compiler)
         .expect("No Function Exists")
         .clone();
 
-    for (i, value) in parameters.iter().enumerate() {
-        let value = value.codegen_size(compiler, &params[i].size());
-        compiler.new_instruction(Instruction::Push(PARAMETER_REGISTERS[i].as_gen(&Size::QuadWord)));
-        compiler.new_instruction(Instruction::Move(
-            PARAMETER_REGISTERS[i].as_gen(&params[i].size()),
-            value,
-        ));
+    // The callee may overwrite any caller saved register, AX is never live as it holds the result
+    let mut saved: Vec<Register> = vec![];
+    for register in &compiler.live_registers {
+        if register.is_caller_saved() && !saved.contains(register) {
+            saved.push(*register);
+        }
     }
+    place_arguments(&params, parameters, &saved, compiler);
     compiler.new_instruction(Instruction::Call(name.to_string()));
-    let len = parameters.len();
-    for i in 1..=len {
-        let i = len - i;
-        compiler.new_instruction(Instruction::Pop(PARAMETER_REGISTERS[i].as_gen(&Size::QuadWord)));
+    if saved.len() % 2 == 1 {
+        compiler.new_instruction(Instruction::Add(
+            Register::SP.as_gen(&Size::QuadWord),
+            MachineOperand::Immediate(8),
+        ));
+    }
+    for register in saved.iter().rev() {
+        compiler.new_instruction(Instruction::Pop(register.as_gen(&Size::QuadWord)));
     }
 
     return_type.size()
 }
 
+/// Puts the arguments of a call in their registers. `saved` is pushed once the arguments are
+/// evaluated, padded to keep the stack 16 byte aligned
+fn place_arguments(types: &[OperandType], parameters: &[Value], saved: &[Register], compiler: &mut Compiler) {
+    let save = |compiler: &mut Compiler| {
+        for register in saved {
+            compiler.new_instruction(Instruction::Push(register.as_gen(&Size::QuadWord)));
+        }
+        if saved.len() % 2 == 1 {
+            compiler.new_instruction(Instruction::Sub(
+                Register::SP.as_gen(&Size::QuadWord),
+                MachineOperand::Immediate(8),
+            ));
+        }
+    };
+
+    // Arguments can go straight into their registers unless one reads a register an earlier
+    // argument replaced, or makes a call that would overwrite them
+    let mut direct = true;
+    for (i, value) in parameters.iter().enumerate() {
+        let reads = value.read_registers(compiler);
+        direct &= !value.has_call() && reads.iter().all(|v| !PARAMETER_REGISTERS[..i].contains(v));
+    }
+    if direct {
+        save(compiler);
+        for (i, value) in parameters.iter().enumerate() {
+            let size = types[i].size();
+            let value = value.codegen_size(compiler, &size);
+            compiler.new_instruction(Instruction::Move(PARAMETER_REGISTERS[i].as_gen(&size), value));
+        }
+        return;
+    }
+
+    // Otherwise every argument is stored before any register is overwritten
+    let mut slots = vec![];
+    for (i, value) in parameters.iter().enumerate() {
+        let size = types[i].size();
+        let later: Vec<Register> = parameters[i + 1..]
+            .iter()
+            .flat_map(|v| v.read_registers(compiler))
+            .collect();
+        let value = compiler.with_live(&later, |c| value.codegen_size(c, &size));
+        let name = compiler.fetch_id("argument ");
+        let (location, _) = compiler
+            .scope_manager
+            .get_variable_manager()
+            .allocate(&name, &types[i])
+            .expect("Unable to allocate variable");
+        m_set_variable(&size, &location.as_gen(&size), value, compiler);
+        slots.push((name, location.as_gen(&size)));
+    }
+    save(compiler);
+    for (i, (name, slot)) in slots.into_iter().enumerate() {
+        compiler.new_instruction(Instruction::Move(PARAMETER_REGISTERS[i].as_gen(&types[i].size()), slot));
+        compiler.scope_manager.get_variable_manager().deallocate(&name);
+    }
+}
+
+/// Stands in for the epilogue of every return until the registers the function saves are known
+const EPILOGUE_PLACEHOLDER: &str = "[EPILOGUE]";
+
+/// What a return needs to know about the function being generated
+#[derive(Clone)]
+pub(crate) struct ReturnContext {
+    return_type: OperandType,
+    /// Pointers to the frame would outlive a tail call reusing it
+    frame_escapes: bool,
+}
+
+/// Whether `return name(parameters)` can reuse the frame of the function returning it
+fn can_tail_call(context: &ReturnContext, name: &str, parameters: &[Value], compiler: &mut Compiler) -> bool {
+    let Some((callee_return_type, params)) = compiler.scope_manager.get_function(name) else {
+        return false;
+    };
+
+    params.len() == parameters.len()
+        && parameters.len() <= PARAMETER_REGISTERS.len()
+        && callee_return_type.size() == context.return_type.size()
+        && !context.frame_escapes
+}
+
+/// Puts the arguments of a tail call in their registers
+fn tail_call_arguments(name: &str, parameters: &[Value], compiler: &mut Compiler) {
+    let (_, params) = compiler
+        .scope_manager
+        .get_function(name)
+        .expect("Checked by can_tail_call")
+        .clone();
+
+    // Nothing is needed after the jump
+    place_arguments(&params, parameters, &[], compiler);
+}
+
+/// Callee saved registers used by a function's instructions or clobbered by its inline
+/// assembly, in a fixed order
+fn callee_saved(instructions: &[Instruction], clobbers: &[Register]) -> Vec<Register> {
+    let mut used: HashSet<Register> = instructions.iter().flat_map(|v| v.registers()).collect();
+    used.extend(clobbers);
+    Register::CALLEE_SAVED
+        .into_iter()
+        .filter(|v| used.contains(v))
+        .collect()
+}
+
+/// Returns from anywhere in the function, the epilogue is filled in by [`function_decl`]
+pub fn return_statement(value: &Value, compiler: &mut Compiler) {
+    let Some(context) = compiler.function.clone() else {
+        eprintln!("Return not paired with function.");
+        panic!();
+    };
+    let return_type = &context.return_type;
+
+    let tail_call = match value {
+        Value::FunctionCall(callee, parameters) if can_tail_call(&context, callee, parameters, compiler) => {
+            tail_call_arguments(callee, parameters, compiler);
+            Some(callee)
+        }
+        _ => None,
+    };
+
+    if tail_call.is_none() && *value != Value::Null {
+        let value = value.codegen_size(compiler, &return_type.size());
+
+        // Edge case where the return value is a maths expression
+        // Since all Maths Expressions are calculated using the AX register there is no need to move it...
+        if value != Register::AX.as_gen(&return_type.size()) {
+            compiler.new_instruction(Instruction::Move(Register::AX.as_gen(&return_type.size()), value));
+        }
+    }
+
+    compiler.new_instruction(Instruction::Label(EPILOGUE_PLACEHOLDER.to_string()));
+    match tail_call {
+        // The callee returns straight to our caller
+        Some(callee) => compiler.new_instruction(Instruction::Jump(callee.clone())),
+        None => compiler.new_instruction(Instruction::Return),
+    }
+}
+
 pub fn function_decl(
     return_type: &OperandType,
     name: &str,
@@ -34,6 +193,8 @@ pub fn function_decl(
 ) {
     compiler.scope_manager.enter_scope();
     compiler.new_instruction(Instruction::Label(name.to_string()));
+    let start = compiler.compiled.len();
+    compiler.asm_clobbers.clear();
     compiler.new_instruction(Instruction::Push(Register::BP.as_gen(&Size::QuadWord)));
     compiler.new_instruction(Instruction::Move(
         Register::BP.as_gen(&Size::QuadWord),
@@ -42,11 +203,19 @@ pub fn function_decl(
     compiler.new_instruction(Instruction::Label("[PLACEHOLDER]".to_string()));
     let placeholder_index = compiler.compiled.len() - 1;
 
+    let mut referenced = HashSet::new();
+    address_taken(operands, &mut referenced);
+
     for (i, param) in parameters.iter().enumerate() {
-        compiler
-            .scope_manager
-            .get_variable_manager()
-            .allocate_parameter(&param.0, &param.1, i);
+        let variables = compiler.scope_manager.get_variable_manager();
+        if !referenced.contains(&param.0) {
+            variables.allocate_parameter(&param.0, &param.1, i);
+            continue;
+        }
+        // A register has no address, so parameters that are referenced live on the stack
+        let (location, _) = variables.allocate(&param.0, &param.1).expect("Unable to allocate parameter");
+        let size = param.1.size();
+        compiler.new_instruction(Instruction::Move(location.as_gen(&size), PARAMETER_REGISTERS[i].as_gen(&size)));
     }
 
     if !operands.iter().any(|v| matches!(v, Operand::Return(_)))
@@ -54,40 +223,52 @@ pub fn function_decl(
         eprintln!("No return statement in function {name}!");
         panic!()
     }
+    compiler.function = Some(ReturnContext {
+        return_type: return_type.clone(),
+        frame_escapes: !referenced.is_empty(),
+    });
 
-    for op in operands {
+    for (i, op) in operands.iter().enumerate() {
         if let Operand::Return(value) = op {
-            if *value != Value::Null {
-                let value = value.codegen_size(compiler, &return_type.size());
-
-                // Edge case where the return value is a maths expression
-                // Since all Maths Expressions are calculated using the AX register there is no need to move it...
-                if value.inner() != Register::AX.as_size(&return_type.size()) {
-                    compiler.new_instruction(Instruction::Move(
-                        Register::AX.as_gen(&return_type.size()),
-                        value,
-                    ));
+            return_statement(value, compiler);
+            compiler.function = None;
+
+            let saved = callee_saved(&compiler.compiled[start..], &compiler.asm_clobbers);
+            let mut epilogue = vec![
+                Instruction::Move(Register::SP.as_gen(&Size::QuadWord), Register::BP.as_gen(&Size::QuadWord)),
+                Instruction::Pop(Register::BP.as_gen(&Size::QuadWord)),
+            ];
+            epilogue.extend(saved.iter().rev().map(|v| Instruction::Pop(v.as_gen(&Size::QuadWord))));
+            let body = compiler.compiled.split_off(start);
+            for instruction in body {
+                match instruction {
+                    Instruction::Label(label) if label == EPILOGUE_PLACEHOLDER => {
+                        compiler.compiled.extend(epilogue.iter().cloned())
+                    }
+                    instruction => compiler.new_instruction(instruction),
                 }
             }
 
-            let stack = compiler.scope_manager.get_variable_manager().used_stack();
+            // Keeps the stack 16 byte aligned at calls
+            let mut stack = compiler.scope_manager.get_variable_manager().used_stack().next_multiple_of(16);
+            if saved.len() % 2 == 1 {
+                stack += 8;
+            }
             if stack == 0 {
                 compiler.compiled.remove(placeholder_index);
             } else {
                 compiler.compiled[placeholder_index] = Instruction::Sub(
                     Register::SP.as_gen(&Size::QuadWord),
-                    ValueCodegen::Number(stack.to_string()),
+                    MachineOperand::Immediate(stack as i64),
                 );
             }
-            compiler.new_instruction(Instruction::Move(
-                Register::SP.as_gen(&Size::QuadWord),
-                Register::BP.as_gen(&Size::QuadWord),
-            ));
-            compiler.new_instruction(Instruction::Pop(Register::BP.as_gen(&Size::QuadWord)));
-            compiler.new_instruction(Instruction::Return);
+            // Saved before the frame is set up, so variables keep their offsets
+            let pushes = saved.iter().map(|v| Instruction::Push(v.as_gen(&Size::QuadWord)));
+            compiler.compiled.splice(start..start, pushes);
             return;
         } else {
-            op.codegen(compiler);
+            let later = read_registers(&operands[i + 1..], compiler);
+            compiler.with_live(&later, |c| op.codegen(c));
         }
     }
     compiler.scope_manager.leave_scope();
@@ -0,0 +1,363 @@
+use crate::*;
+
+/// Where a constraint places an inline assembly operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsmConstraint {
+    /// `r`, any general purpose register the compiler picks
+    Register,
+    /// `a`, `b`, `c`, `d`, `S`, `D` or a register named in braces such as `{r12}`
+    Fixed(Register),
+    /// `m`, the variable's stack slot
+    Memory,
+    /// `i`, a constant written straight into the template
+    Immediate,
+    /// A digit, the input goes in the same place as the output with that index
+    Tied(usize),
+}
+
+impl AsmConstraint {
+    /// Parses a constraint without its `=` or `+` modifier
+    pub fn parse(constraint: &str) -> Option<Self> {
+        let fixed = |register| Some(AsmConstraint::Fixed(register));
+        match constraint {
+            "r" => Some(AsmConstraint::Register),
+            "m" => Some(AsmConstraint::Memory),
+            "i" => Some(AsmConstraint::Immediate),
+            "a" => fixed(Register::AX),
+            "b" => fixed(Register::BX),
+            "c" => fixed(Register::CX),
+            "d" => fixed(Register::DX),
+            "S" => fixed(Register::SI),
+            "D" => fixed(Register::DI),
+            _ => {
+                if let Some(name) = constraint.strip_prefix('{').and_then(|v| v.strip_suffix('}')) {
+                    return Register::from_name(name)
+                        .filter(|v| !matches!(v, Register::SP | Register::BP))
+                        .map(AsmConstraint::Fixed);
+                }
+                constraint.parse().ok().map(AsmConstraint::Tied)
+            }
+        }
+    }
+}
+
+/// A value bound to a `%N` of an [`ExtendedAssembly`] template
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AsmOperand {
+    /// Where the value has to be, see [`AsmConstraint`]. Outputs start with `=` when the
+    /// assembly only writes them or `+` when it reads them as well
+    pub constraint: String,
+    pub value: Value,
+}
+
+impl AsmOperand {
+    pub fn new(constraint: &str, value: Value) -> Self {
+        Self {
+            constraint: constraint.to_string(),
+            value,
+        }
+    }
+
+    /// Whether the assembly reads the output's old value
+    pub fn is_read_write(&self) -> bool {
+        self.constraint.starts_with('+')
+    }
+
+    /// `None` when the constraint isn't supported
+    pub fn placement(&self) -> Option<AsmConstraint> {
+        AsmConstraint::parse(self.constraint.trim_start_matches(['=', '+']))
+    }
+}
+
+/// GCC style inline assembly. `%0`, `%1`... in the template are replaced by the outputs
+/// followed by the inputs, and `%%` by a single `%`.
+///
+/// Outputs have to be variables, they are written once the assembly ran. Registers in
+/// `clobbers` are saved around it when they are still needed, `memory` and `cc` are accepted
+/// but change nothing as variables are never cached in registers.
+///
+/// The builtin assembler and the JIT only encode the common instructions, anything else needs
+/// an external assembler.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ExtendedAssembly {
+    pub template: String,
+    pub outputs: Vec<AsmOperand>,
+    pub inputs: Vec<AsmOperand>,
+    pub clobbers: Vec<String>,
+}
+
+/// Clobbers that don't name a register
+const SPECIAL_CLOBBERS: &[&str] = &["memory", "cc"];
+
+/// Replaces every `%N` using `operand`, `%%` becomes `%` and any other `%` is kept
+fn expand(template: &str, mut operand: impl FnMut(usize) -> Option<String>) -> String {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        if chars.peek() == Some(&'%') {
+            chars.next();
+            out.push('%');
+            continue;
+        }
+
+        let mut digits = String::new();
+        while let Some(digit) = chars.next_if(|v| v.is_ascii_digit()) {
+            digits.push(digit);
+        }
+        match digits.parse().ok().and_then(&mut operand) {
+            Some(text) => out.push_str(&text),
+            None => {
+                out.push('%');
+                out.push_str(&digits);
+            }
+        }
+    }
+    out
+}
+
+impl ExtendedAssembly {
+    pub fn new(template: &str) -> Self {
+        Self {
+            template: template.to_string(),
+            outputs: vec![],
+            inputs: vec![],
+            clobbers: vec![],
+        }
+    }
+
+    pub fn operands(&self) -> impl Iterator<Item = &AsmOperand> {
+        self.outputs.iter().chain(&self.inputs)
+    }
+
+    /// Every `N` the template refers to as `%N`
+    pub fn references(&self) -> Vec<usize> {
+        let mut references = vec![];
+        expand(&self.template, |i| {
+            references.push(i);
+            None
+        });
+        references
+    }
+
+    /// The template with `%N` replaced by the nth of `operands`
+    pub fn substitute(&self, operands: &[String]) -> String {
+        expand(&self.template, |i| operands.get(i).cloned())
+    }
+
+    pub fn is_valid_clobber(clobber: &str) -> bool {
+        SPECIAL_CLOBBERS.contains(&clobber) || Register::from_name(clobber).is_some()
+    }
+
+    pub fn clobbered_registers(&self) -> Vec<Register> {
+        self.clobbers.iter().filter_map(|v| Register::from_name(v)).collect()
+    }
+}
+
+/// Registers given to `r` operands, the callee saved ones are only used once the others run out
+const OPERAND_REGISTERS: &[Register] = &[
+    Register::AX,
+    Register::CX,
+    Register::DX,
+    Register::SI,
+    Register::DI,
+    Register::R8,
+    Register::R9,
+    Register::R10,
+    Register::R11,
+    Register::BX,
+    Register::R12,
+    Register::R13,
+    Register::R14,
+    Register::R15,
+];
+
+fn temporary(size: &Size, compiler: &mut Compiler) -> (String, MachineOperand) {
+    let name = compiler.fetch_id("asm operand ");
+    let (location, _) = compiler
+        .scope_manager
+        .get_variable_manager()
+        .allocate(&name, &OperandType::Int(*size))
+        .expect("Unable to allocate variable");
+    (name, location.as_gen(size))
+}
+
+/// Where a variable lives, `None` for any other value
+fn variable_location(value: &Value, compiler: &mut Compiler) -> Option<MachineOperand> {
+    let Value::Variable(name) = value else {
+        return None;
+    };
+    let (location, ty) = compiler.scope_manager.get_variable_manager().expect(name);
+    Some(location.as_gen(&ty.size()))
+}
+
+pub fn extended_assembly(asm: &ExtendedAssembly, compiler: &mut Compiler) {
+    let operands: Vec<&AsmOperand> = asm.operands().collect();
+    let outputs = asm.outputs.len();
+    let clobbers = asm.clobbered_registers();
+    let constraints: Vec<AsmConstraint> = operands
+        .iter()
+        .map(|v| {
+            v.placement()
+                .unwrap_or_else(|| panic!("Unsupported inline assembly constraint {}", v.constraint))
+        })
+        .collect();
+
+    // Registers that already mean something while the assembly runs
+    let mut taken = vec![Register::SP, Register::BP];
+    taken.extend(&clobbers);
+    taken.extend(constraints.iter().filter_map(|v| match v {
+        AsmConstraint::Fixed(register) => Some(*register),
+        _ => None,
+    }));
+    taken.extend(compiler.scope_manager.get_variable_manager().registers());
+    taken.extend(&compiler.live_registers);
+
+    let mut temporaries = vec![];
+    let mut sizes: Vec<Size> = vec![];
+    let mut places: Vec<MachineOperand> = vec![];
+    for (i, operand) in operands.iter().enumerate() {
+        let size = match constraints[i] {
+            AsmConstraint::Tied(output) => sizes[output],
+            _ => operand.value.size(compiler),
+        };
+        let place = match constraints[i] {
+            AsmConstraint::Register => {
+                let register = OPERAND_REGISTERS
+                    .iter()
+                    .find(|v| !taken.contains(v))
+                    .expect("No register left for an inline assembly operand");
+                taken.push(*register);
+                register.as_gen(&size)
+            }
+            AsmConstraint::Fixed(register) => register.as_gen(&size),
+            AsmConstraint::Tied(output) => places[output].clone(),
+            AsmConstraint::Memory => match variable_location(&operand.value, compiler) {
+                Some(location) if location.is_memory() => location,
+                _ => {
+                    let (name, slot) = temporary(&size, compiler);
+                    temporaries.push(name);
+                    slot
+                }
+            },
+            AsmConstraint::Immediate => operand.value.codegen(compiler),
+        };
+        sizes.push(size);
+        places.push(place);
+    }
+
+    // Values that have to be in their place before the assembly runs
+    let mut loads = vec![];
+    for (i, operand) in operands.iter().enumerate() {
+        let written_only = i < outputs && !operand.is_read_write();
+        if !written_only
+            && constraints[i] != AsmConstraint::Immediate
+            && variable_location(&operand.value, compiler).as_ref() != Some(&places[i])
+        {
+            loads.push(i);
+        }
+    }
+
+    // Values that need code to compute, or read a register another operand is placed in, are
+    // evaluated before anything is placed
+    let destinations: Vec<Register> = places
+        .iter()
+        .filter_map(|v| match v {
+            MachineOperand::Register(register, _) => Some(*register),
+            _ => None,
+        })
+        .collect();
+    let mut sources: Vec<Option<MachineOperand>> = vec![None; operands.len()];
+    for (j, &i) in loads.iter().enumerate() {
+        let value = &operands[i].value;
+        let reads = value.read_registers(compiler);
+        let conflicts = reads
+            .iter()
+            .any(|v| destinations.contains(v) && places[i] != v.as_gen(&sizes[i]));
+        if !value.clobbers_accumulator() && !conflicts {
+            continue;
+        }
+
+        let later: Vec<Register> = loads[j + 1..]
+            .iter()
+            .flat_map(|&v| operands[v].value.read_registers(compiler))
+            .collect();
+        let value = compiler.with_live(&later, |c| value.codegen_size(c, &sizes[i]));
+        let (name, slot) = temporary(&sizes[i], compiler);
+        m_set_variable(&sizes[i], &slot, value, compiler);
+        temporaries.push(name);
+        sources[i] = Some(slot);
+    }
+
+    // Registers the assembly overwrites that still hold something needed afterwards, outputs
+    // are meant to be overwritten
+    let mut output_registers = vec![];
+    for output in &asm.outputs {
+        if let Some(MachineOperand::Register(register, _)) = variable_location(&output.value, compiler) {
+            output_registers.push(register);
+        }
+    }
+    let mut saved: Vec<Register> = vec![];
+    for register in &compiler.live_registers {
+        if (destinations.contains(register) || clobbers.contains(register))
+            && !output_registers.contains(register)
+            && !saved.contains(register)
+        {
+            saved.push(*register);
+        }
+    }
+    for register in &saved {
+        compiler.new_instruction(Instruction::Push(register.as_gen(&Size::QuadWord)));
+    }
+
+    for &i in &loads {
+        let source = match sources[i].take() {
+            Some(source) => source,
+            None => operands[i].value.codegen_size(compiler, &sizes[i]),
+        };
+        compiler.new_instruction(Instruction::Move(places[i].clone(), source));
+    }
+
+    let rendered: Vec<String> = places.iter().map(|v| v.render(compiler.syntax)).collect();
+    compiler.new_instruction(Instruction::AsmLiteral(asm.substitute(&rendered)));
+    compiler.asm_clobbers.extend(&clobbers);
+
+    let mut writes = vec![];
+    for (i, output) in asm.outputs.iter().enumerate() {
+        let location = variable_location(&output.value, compiler).expect("Outputs are variables");
+        if location != places[i] {
+            writes.push((location, places[i].clone()));
+        }
+    }
+    // Writing one output can't overwrite where another one still is
+    let overlapping = writes.iter().any(|(location, _)| {
+        writes
+            .iter()
+            .any(|(_, place)| matches!((location, place), (MachineOperand::Register(a, _), MachineOperand::Register(b, _)) if a == b))
+    });
+    if overlapping {
+        for (_, place) in writes.iter_mut().filter(|v| v.1.is_register()) {
+            let size = place.size().expect("Registers have a size");
+            let (name, slot) = temporary(&size, compiler);
+            compiler.new_instruction(Instruction::Move(slot.clone(), place.clone()));
+            temporaries.push(name);
+            *place = slot;
+        }
+    }
+    for (location, place) in writes {
+        let size = location.size().expect("Variables are registers or memory");
+        m_set_variable(&size, &location, place, compiler);
+    }
+
+    for register in saved.iter().rev() {
+        compiler.new_instruction(Instruction::Pop(register.as_gen(&Size::QuadWord)));
+    }
+    for name in temporaries {
+        compiler.scope_manager.get_variable_manager().deallocate(&name);
+    }
+}
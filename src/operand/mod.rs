@@ -7,9 +7,20 @@ pub use compare::*;
 mod variables;
 pub use variables::*;
 
+mod inline_assembly;
+pub use inline_assembly::*;
+
+mod switch;
+pub use switch::*;
+
 use crate::*;
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum OperandType {
     Undefined,
     Int(Size),
@@ -33,9 +44,19 @@ impl OperandType {
             _ => None,
         }
     }
+
+    /// Only `Int` compares as signed, chars and pointers are unsigned like `UInt`
+    pub fn is_signed(&self) -> bool {
+        matches!(self, OperandType::Int(_))
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Operand {
     DeclareVariable(OperandType, String, Value),
     FunctionDecl(
@@ -43,6 +64,7 @@ pub enum Operand {
         String,
         Vec<Operand>,
         Vec<(String, OperandType)>,
+        InlineHint,
     ),
     Add(OperandType, Value, Value),
     Subtract(OperandType, Value, Value),
@@ -51,17 +73,72 @@ pub enum Operand {
     DropVariable(String),
     FunctionCall(String, Vec<Value>),
     If { predicate : ComparePredicate, main_body : Vec<Operand> },
+    /// Runs the body of the case equal to `scrutinee`, or `default` when none is. Bodies don't
+    /// fall through into the next case
+    Switch { scrutinee : Value, cases : Vec<(i64, Vec<Operand>)>, default : Vec<Operand> },
     Return(Value),
     InlineAssembly(String),
+    ExtendedAssembly(ExtendedAssembly),
+}
+
+/// Registers of the variables a sequence of operands reads
+pub(crate) fn read_registers(operands: &[Operand], compiler: &mut Compiler) -> Vec<Register> {
+    operands.iter().flat_map(|v| v.read_registers(compiler)).collect()
 }
 
 impl Operand {
+    /// The bodies nested in an `If` or `Switch`, each runs in its own scope
+    pub fn bodies(&self) -> Vec<&[Operand]> {
+        match self {
+            Operand::If { main_body, .. } => vec![main_body],
+            Operand::Switch { cases, default, .. } => {
+                cases.iter().map(|v| v.1.as_slice()).chain([default.as_slice()]).collect()
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Registers of the variables the operand reads, inline assembly may read any of them
+    pub(crate) fn read_registers(&self, compiler: &mut Compiler) -> Vec<Register> {
+        let values = match self {
+            Operand::DeclareVariable(_, _, value) | Operand::Return(value) => vec![value],
+            Operand::Add(_, lhs, rhs) | Operand::Subtract(_, lhs, rhs) | Operand::SetValue(lhs, rhs) => {
+                vec![lhs, rhs]
+            }
+            Operand::FunctionCall(_, parameters) => parameters.iter().collect(),
+            Operand::If { predicate, main_body } => {
+                let mut registers = read_registers(main_body, compiler);
+                registers.extend(predicate.lhs.read_registers(compiler));
+                registers.extend(predicate.rhs.read_registers(compiler));
+                return registers;
+            }
+            Operand::Switch { scrutinee, .. } => {
+                let mut registers: Vec<Register> =
+                    self.bodies().into_iter().flat_map(|v| read_registers(v, compiler)).collect();
+                registers.extend(scrutinee.read_registers(compiler));
+                return registers;
+            }
+            Operand::InlineAssembly(_) => return compiler.scope_manager.get_variable_manager().registers(),
+            Operand::ExtendedAssembly(asm) => asm
+                .operands()
+                .enumerate()
+                .filter(|(i, v)| *i >= asm.outputs.len() || v.is_read_write())
+                .map(|v| &v.1.value)
+                .collect(),
+            Operand::FunctionDecl(..) | Operand::DropVariable(_) => vec![],
+        };
+        values.into_iter().flat_map(|v| v.read_registers(compiler)).collect()
+    }
+
     pub fn codegen(&self, compiler: &mut Compiler) {
         match self {
             Operand::If { predicate, main_body } =>
             {
                 if_statement(predicate, main_body, compiler);
             }
+            Operand::Switch { scrutinee, cases, default } => {
+                switch_statement(scrutinee, cases, default, compiler);
+            }
             Operand::DeclareVariable(ty, name, value) => {
                 variable_declaration(ty, name, value, compiler);
             }
@@ -71,15 +148,17 @@ impl Operand {
             Operand::InlineAssembly(asm) => {
                 compiler.new_instruction(Instruction::AsmLiteral(asm.clone()));
             }
+            Operand::ExtendedAssembly(asm) => {
+                extended_assembly(asm, compiler);
+            }
             Operand::FunctionCall(name, parameters) => {
                 function_call(name, parameters, compiler);
             }
-            Operand::FunctionDecl(return_type, name, operands, parameters) => {
+            Operand::FunctionDecl(return_type, name, operands, parameters, ..) => {
                 function_decl(return_type, name, operands, parameters, compiler);
             }
-            Operand::Return(_) => {
-                eprintln!("Return not paired with function.");
-                panic!();
+            Operand::Return(value) => {
+                return_statement(value, compiler);
             }
             Operand::DropVariable(name) => {
                 // This variable is no longer used anywhere
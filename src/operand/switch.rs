@@ -0,0 +1,189 @@
+use crate::*;
+
+/// Fewer cases than this are always found with compares
+const MIN_TABLE_CASES: usize = 4;
+/// A jump table has at most this many entries per case, the ones without a case go to the
+/// default
+const MAX_TABLE_SPREAD: i128 = 3;
+/// Ranges of this many cases or fewer are compared one after another instead of split again
+const LINEAR_CASES: usize = 3;
+
+/// A case value as the signed number a compare at `size` sees
+fn case_value(value: i64, size: &Size) -> i64 {
+    wrap_to_type(value, &OperandType::Int(*size))
+}
+
+fn jump(label: &str, conditional: CompareOperation) -> Instruction {
+    // Cases are sorted as signed numbers at the scrutinee's size, whatever its type
+    Instruction::JumpConditional {
+        label_destination: label.to_string(),
+        conditional,
+        signed: true,
+    }
+}
+
+/// Compares `value` with a case, a 64 bit case that doesn't fit in an immediate goes through R11
+fn compare(value: &MachineOperand, case: i64, compiler: &mut Compiler) {
+    let case = if i32::try_from(case).is_ok() {
+        MachineOperand::Immediate(case)
+    } else {
+        let register = Register::R11.as_gen(&Size::QuadWord);
+        compiler.new_instruction(Instruction::Move(register.clone(), MachineOperand::Immediate(case)));
+        register
+    };
+    compiler.new_instruction(Instruction::Compare(value.clone(), case));
+}
+
+/// Whether a table covering every value from the first case to the last is small enough.
+/// Cases are sorted, and the bounds have to fit in an immediate
+fn is_dense(cases: &[(i64, String)]) -> bool {
+    let (Some(first), Some(last)) = (cases.first(), cases.last()) else {
+        return false;
+    };
+    let entries = last.0 as i128 - first.0 as i128 + 1;
+    cases.len() >= MIN_TABLE_CASES
+        && entries <= cases.len() as i128 * MAX_TABLE_SPREAD
+        && i32::try_from(first.0).is_ok()
+        && i32::try_from(last.0).is_ok()
+}
+
+/// Checks the bounds, then jumps through a table in `.rodata` indexed by `value - first case`
+fn jump_table(value: &MachineOperand, cases: &[(i64, String)], default: &str, compiler: &mut Compiler) {
+    let MachineOperand::Register(register, size) = *value else {
+        unreachable!("Checked by switch_dispatch")
+    };
+    let (first, last) = (cases[0].0, cases[cases.len() - 1].0);
+
+    compiler.new_instruction(Instruction::Compare(value.clone(), MachineOperand::Immediate(first)));
+    compiler.new_instruction(jump(default, CompareOperation::LT));
+    compiler.new_instruction(Instruction::Compare(value.clone(), MachineOperand::Immediate(last)));
+    compiler.new_instruction(jump(default, CompareOperation::GT));
+    if first != 0 {
+        compiler.new_instruction(Instruction::Sub(value.clone(), MachineOperand::Immediate(first)));
+    }
+
+    // The index has to fill all of R11, byte and word moves keep the upper bits
+    if size < Size::DoubleWord {
+        compiler.new_instruction(Instruction::Move(
+            Register::R11.as_gen(&Size::DoubleWord),
+            MachineOperand::Immediate(0),
+        ));
+    }
+    compiler.new_instruction(Instruction::Move(Register::R11.as_gen(&size), value.clone()));
+
+    let mut entries = vec![default.to_string(); (last - first + 1) as usize];
+    for (case, label) in cases {
+        entries[(case - first) as usize] = label.clone();
+    }
+    let table = compiler.fetch_id("__jump_table");
+    let base = register.as_gen(&Size::QuadWord);
+    compiler.new_instruction(Instruction::LoadAddress(
+        base,
+        MachineOperand::Memory(MemoryOperand::symbol(Size::QuadWord, &table)),
+    ));
+    let mut entry = MemoryOperand::new(Size::QuadWord, register, 0);
+    entry.index = Some((Register::R11, 8));
+
+    let mut destinations = entries.clone();
+    destinations.sort();
+    destinations.dedup();
+    compiler.new_instruction(Instruction::JumpIndirect {
+        target: MachineOperand::Memory(entry),
+        destinations,
+    });
+    compiler.jump_tables.push((table, entries));
+}
+
+/// Halves the sorted cases with every compare, the last few are checked one by one
+fn binary_search(value: &MachineOperand, cases: &[(i64, String)], default: &str, compiler: &mut Compiler) {
+    if cases.len() <= LINEAR_CASES {
+        for (case, label) in cases {
+            compare(value, *case, compiler);
+            compiler.new_instruction(jump(label, CompareOperation::EQ));
+        }
+        compiler.new_instruction(Instruction::Jump(default.to_string()));
+        return;
+    }
+
+    let middle = cases.len() / 2;
+    let (case, label) = &cases[middle];
+    let lower = compiler.fetch_id(".SWL");
+    compare(value, *case, compiler);
+    compiler.new_instruction(jump(label, CompareOperation::EQ));
+    compiler.new_instruction(jump(&lower, CompareOperation::LT));
+    binary_search(value, &cases[middle + 1..], default, compiler);
+    compiler.new_instruction(Instruction::Label(lower));
+    binary_search(value, &cases[..middle], default, compiler);
+}
+
+/// Jumps to the label of the case equal to `value`, or to `default` when there is none.
+///
+/// `value` has to be in a register other than R11, both are overwritten. Dense cases use a
+/// bounds checked jump table, sparse ones a binary search.
+pub(crate) fn switch_dispatch(
+    value: &MachineOperand,
+    cases: &[(i64, String)],
+    default: &str,
+    compiler: &mut Compiler,
+) {
+    let MachineOperand::Register(register, size) = *value else {
+        panic!("The scrutinee of a switch has to be in a register")
+    };
+    assert_ne!(register, Register::R11, "R11 holds the jump table index");
+
+    let mut cases: Vec<(i64, String)> = cases
+        .iter()
+        .map(|(case, label)| (case_value(*case, &size), label.clone()))
+        .collect();
+    cases.sort_by_key(|v| v.0);
+
+    if is_dense(&cases) {
+        jump_table(value, &cases, default, compiler);
+    } else {
+        binary_search(value, &cases, default, compiler);
+    }
+}
+
+/// A case body in its own scope, ending with a jump past the switch
+fn case_body(body: &[Operand], end: &str, compiler: &mut Compiler) {
+    compiler.scope_manager.get_variable_manager().enter_block();
+    for (i, operand) in body.iter().enumerate() {
+        let later = read_registers(&body[i + 1..], compiler);
+        compiler.with_live(&later, |c| operand.codegen(c));
+    }
+    compiler.scope_manager.get_variable_manager().leave_block();
+    compiler.new_instruction(Instruction::Jump(end.to_string()));
+}
+
+pub fn switch_statement(
+    scrutinee: &Value,
+    cases: &[(i64, Vec<Operand>)],
+    default: &[Operand],
+    compiler: &mut Compiler,
+) {
+    let size = scrutinee.size(compiler);
+
+    let mut body_reads = read_registers(default, compiler);
+    for (_, body) in cases {
+        body_reads.extend(read_registers(body, compiler));
+    }
+    let value = compiler.with_live(&body_reads, |c| scrutinee.codegen_size(c, &size));
+    let accumulator = Register::AX.as_gen(&size);
+    compiler.new_instruction(Instruction::Move(accumulator.clone(), value));
+
+    let labels: Vec<(i64, String)> = cases
+        .iter()
+        .map(|(case, _)| (*case, compiler.fetch_id(".SW")))
+        .collect();
+    let default_label = compiler.fetch_id(".SWD");
+    let end = compiler.fetch_id(".SWE");
+    switch_dispatch(&accumulator, &labels, &default_label, compiler);
+
+    for ((_, body), (_, label)) in cases.iter().zip(labels) {
+        compiler.new_instruction(Instruction::Label(label));
+        case_body(body, &end, compiler);
+    }
+    compiler.new_instruction(Instruction::Label(default_label));
+    case_body(default, &end, compiler);
+    compiler.new_instruction(Instruction::Label(end));
+}
@@ -6,6 +6,9 @@ pub fn variable_declaration(
     value: &Value,
     compiler: &mut Compiler,
 ) {
+    // The value may read a variable this one shadows, so it's evaluated first. Literal
+    // arithmetic has no size of its own and is done at the size of the variable
+    let value = value.codegen_size(compiler, &ty.size());
     let (variable_information, ty) = compiler
         .scope_manager
         .get_variable_manager()
@@ -17,23 +20,43 @@ pub fn variable_declaration(
 
 pub fn set_value(dst: &Value, value: &Value, compiler: &mut Compiler) {
     let size = dst.size(compiler);
-    let loc = dst.codegen_lhs(compiler);
+    let reads = dst.read_registers(compiler);
+    let value = compiler.with_live(&reads, |c| value.codegen_size(c, &size));
 
+    // Writing through a pointer loads it into AX, which the value can't be in
+    if matches!(dst, Value::Dereference(_)) && !value.is_immediate() {
+        let value = compiler.preserve(value, &size);
+        let loc = dst.codegen_lhs(compiler);
+        m_set_variable(&size, &loc, value.operand.clone(), compiler);
+        compiler.release(value);
+        return;
+    }
 
+    let loc = dst.codegen_lhs(compiler);
     m_set_variable(&size, &loc, value, compiler);
 }
 
 /// Helper function
-fn m_set_variable(
+pub(crate) fn m_set_variable(
     ty: &Size,
-    variable_information: &ValueCodegen,
-    value: &Value,
+    variable_information: &MachineOperand,
+    value: MachineOperand,
     compiler: &mut Compiler,
 ) {
-    let value = value.codegen(compiler);
+    match (variable_information, &value) {
+        // No move stores a 64 bit immediate, so it's stored as two halves
+        (MachineOperand::Memory(memory), &MachineOperand::Immediate(value)) if *ty == Size::QuadWord && i32::try_from(value).is_err() => {
+            let low = MemoryOperand { size: Size::DoubleWord, ..memory.clone() };
+            let high = MemoryOperand { displacement: memory.displacement + 4, ..low.clone() };
+            compiler.new_instruction(Instruction::Move(MachineOperand::Memory(low), MachineOperand::Immediate(value as u32 as i64)));
+            compiler.new_instruction(Instruction::Move(MachineOperand::Memory(high), MachineOperand::Immediate((value >> 32) as u32 as i64)));
+            return;
+        }
+        _ => {}
+    }
 
-    if variable_information.is_stack() && value.is_stack() {
-        // Can't set stack offset to another stack offset
+    if variable_information.is_memory() && value.is_memory() {
+        // Can't move memory to memory
         compiler.new_instruction(Instruction::Move(Register::AX.as_gen(ty), value));
         compiler.new_instruction(Instruction::Move(
             variable_information.clone(),
@@ -0,0 +1,305 @@
+use std::collections::HashSet;
+
+use crate::*;
+
+/// Registers a caller expects to be unchanged after a call returns
+const CALLEE_SAVED: &[Register] = &[
+    Register::BX,
+    Register::SP,
+    Register::BP,
+    Register::R12,
+    Register::R13,
+    Register::R14,
+    Register::R15,
+];
+
+fn operand_uses(operand: &MachineOperand, register: Register) -> bool {
+    match operand {
+        MachineOperand::Register(other, _) => *other == register,
+        MachineOperand::Memory(memory) => {
+            memory.base == Some(register) || memory.index.is_some_and(|v| v.0 == register)
+        }
+        MachineOperand::Immediate(_) | MachineOperand::Symbol(_) => false,
+    }
+}
+
+/// Registers used to compute a memory address are read even when the memory is written
+fn address_uses(operand: &MachineOperand, register: Register) -> bool {
+    operand.is_memory() && operand_uses(operand, register)
+}
+
+/// Whether the instruction writes all 64 bits of `register` without reading it first, 32 bit
+/// writes zero the upper half so they count as well
+fn overwrites(instruction: &Instruction, register: Register) -> bool {
+    match instruction {
+        Instruction::Move(MachineOperand::Register(dst, size), src)
+        | Instruction::LoadAddress(MachineOperand::Register(dst, size), src) => {
+            *dst == register && *size >= Size::DoubleWord && !operand_uses(src, register)
+        }
+        Instruction::Pop(MachineOperand::Register(dst, _)) => *dst == register,
+        _ => false,
+    }
+}
+
+/// Whether the instruction may read `register`, anything unknown counts as a read
+fn reads(instruction: &Instruction, register: Register) -> bool {
+    match instruction {
+        Instruction::Move(dst, src) | Instruction::LoadAddress(dst, src) => {
+            operand_uses(src, register)
+                || address_uses(dst, register)
+                // Byte and word writes keep the rest of the register
+                || matches!(dst, MachineOperand::Register(r, size) if *r == register && *size < Size::DoubleWord)
+        }
+        Instruction::Add(lhs, rhs)
+        | Instruction::Sub(lhs, rhs)
+        | Instruction::IntMultiply(lhs, rhs)
+        | Instruction::Multiply(lhs, rhs)
+        | Instruction::Compare(lhs, rhs) => {
+            operand_uses(lhs, register) || operand_uses(rhs, register)
+        }
+        Instruction::Push(src) | Instruction::JumpIndirect { target: src, .. } => {
+            operand_uses(src, register)
+        }
+        Instruction::Pop(dst) => address_uses(dst, register),
+        Instruction::Label(_) | Instruction::Jump(_) | Instruction::JumpConditional { .. } => false,
+        Instruction::Return | Instruction::Call(_) | Instruction::AsmLiteral(_) => true,
+    }
+}
+
+fn find_label(instructions: &[Instruction], label: &str) -> Option<usize> {
+    instructions
+        .iter()
+        .position(|v| matches!(v, Instruction::Label(l) if l == label))
+}
+
+/// Whether the value in `register` after `start` is never read again on any path
+fn is_dead_after(instructions: &[Instruction], start: usize, register: Register) -> bool {
+    let mut pending = vec![start + 1];
+    let mut visited = HashSet::new();
+
+    while let Some(mut i) = pending.pop() {
+        loop {
+            if !visited.insert(i) {
+                break;
+            }
+            match instructions.get(i) {
+                // Falling off the end of the code
+                None => return false,
+                // Only the return value and callee saved registers matter to the caller
+                Some(Instruction::Return) => {
+                    if register == Register::AX || CALLEE_SAVED.contains(&register) {
+                        return false;
+                    }
+                    break;
+                }
+                Some(Instruction::Jump(label)) => {
+                    let Some(target) = find_label(instructions, label) else {
+                        return false;
+                    };
+                    pending.push(target);
+                    break;
+                }
+                Some(Instruction::JumpConditional {
+                    label_destination, ..
+                }) => {
+                    let Some(target) = find_label(instructions, label_destination) else {
+                        return false;
+                    };
+                    pending.push(target);
+                }
+                Some(Instruction::JumpIndirect {
+                    target,
+                    destinations,
+                }) => {
+                    if operand_uses(target, register) {
+                        return false;
+                    }
+                    for label in destinations {
+                        let Some(target) = find_label(instructions, label) else {
+                            return false;
+                        };
+                        pending.push(target);
+                    }
+                    break;
+                }
+                Some(instruction) if reads(instruction, register) => return false,
+                Some(instruction) if overwrites(instruction, register) => break,
+                Some(_) => {}
+            }
+            i += 1;
+        }
+    }
+    true
+}
+
+/// Finds the push matching the pop at `pop` within straight line code
+fn matching_push(instructions: &[Instruction], pop: usize) -> Option<usize> {
+    let mut depth = 0;
+    for i in (0..pop).rev() {
+        match &instructions[i] {
+            Instruction::Push(_) if depth > 0 => depth -= 1,
+            Instruction::Push(_) => return Some(i),
+            Instruction::Pop(_) => depth += 1,
+            Instruction::Label(_)
+            | Instruction::Jump(_)
+            | Instruction::JumpIndirect { .. }
+            | Instruction::JumpConditional { .. }
+            | Instruction::Return
+            | Instruction::AsmLiteral(_) => return None,
+            // Anything that addresses the stack pointer would see it shift
+            instruction if reads(instruction, Register::SP) && !matches!(instruction, Instruction::Call(_)) => {
+                return None
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn is_zero(operand: &MachineOperand) -> bool {
+    *operand == MachineOperand::Immediate(0)
+}
+
+/// Instructions that may look at the flags left by the previous one
+fn reads_flags(instruction: Option<&Instruction>) -> bool {
+    matches!(
+        instruction,
+        Some(Instruction::JumpConditional { .. } | Instruction::AsmLiteral(_))
+    )
+}
+
+/// A single rewrite at `i`, returning whether anything changed
+fn rewrite(instructions: &mut Vec<Instruction>, i: usize, referenced: &HashSet<String>) -> bool {
+    let next = instructions.get(i + 1).cloned();
+    match (&instructions[i], &next) {
+        // mov x, x, narrower moves into a register zero or keep its upper bits
+        (Instruction::Move(dst, src), _) if dst == src && dst.size() == Some(Size::QuadWord) => {
+            instructions.remove(i);
+            true
+        }
+        // mov a, b followed by mov b, a, the second one has nothing to do unless the first
+        // changed the address of b or the second zero extends b
+        (Instruction::Move(a, b), Some(Instruction::Move(c, d)))
+            if a == d
+                && b == c
+                && !matches!(a, MachineOperand::Register(register, _) if operand_uses(b, *register))
+                && !(c.is_register() && c.size() == Some(Size::DoubleWord)) =>
+        {
+            instructions.remove(i + 1);
+            true
+        }
+        // mov r, a followed by mov r, b, the first value is never seen
+        (Instruction::Move(MachineOperand::Register(register, _), _), Some(second))
+            if overwrites(second, *register) =>
+        {
+            instructions.remove(i);
+            true
+        }
+        // add x, 0 and sub x, 0
+        (Instruction::Add(_, value) | Instruction::Sub(_, value), _)
+            if is_zero(value) && !reads_flags(next.as_ref()) =>
+        {
+            instructions.remove(i);
+            true
+        }
+        (Instruction::IntMultiply(_, MachineOperand::Immediate(1)), _)
+            if !reads_flags(next.as_ref()) =>
+        {
+            instructions.remove(i);
+            true
+        }
+        // Comparisons nothing branches on
+        (Instruction::Compare(..), _) if !reads_flags(next.as_ref()) => {
+            instructions.remove(i);
+            true
+        }
+        // push r followed by pop r
+        (Instruction::Push(a), Some(Instruction::Pop(b))) if a == b && a.is_register() => {
+            instructions.drain(i..i + 2);
+            true
+        }
+        // A jump to the very next instruction
+        (
+            Instruction::Jump(label_destination)
+            | Instruction::JumpConditional {
+                label_destination, ..
+            },
+            Some(Instruction::Label(label)),
+        ) if label_destination == label => {
+            instructions.remove(i);
+            true
+        }
+        // Nothing reaches code between an unconditional jump and the next label, inline
+        // assembly may define a label of its own though
+        (Instruction::Jump(_) | Instruction::JumpIndirect { .. } | Instruction::Return, Some(next))
+            if !matches!(next, Instruction::Label(_) | Instruction::AsmLiteral(_)) =>
+        {
+            instructions.remove(i + 1);
+            true
+        }
+        // Local labels nothing jumps to, inline assembly might still mention them
+        (Instruction::Label(label), _)
+            if label.starts_with('.')
+                && !referenced.contains(label)
+                && !instructions
+                    .iter()
+                    .any(|v| matches!(v, Instruction::AsmLiteral(asm) if asm.contains(label.as_str()))) =>
+        {
+            instructions.remove(i);
+            true
+        }
+        // Saving a register around a call when its value isn't needed afterwards
+        (Instruction::Pop(MachineOperand::Register(register, Size::QuadWord)), _)
+            if is_dead_after(instructions, i, *register) =>
+        {
+            let register = *register;
+            match matching_push(instructions, i) {
+                Some(push)
+                    if instructions[push] == Instruction::Push(register.as_gen(&Size::QuadWord)) =>
+                {
+                    instructions.remove(i);
+                    instructions.remove(push);
+                    true
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Removes redundant instructions, like self moves, reloading a value that was just stored,
+/// saving registers nobody reads afterwards and jumps to the next instruction.
+///
+/// Only looks at short windows of straight line code, so it never changes what a function does.
+pub fn peephole(mut instructions: Vec<Instruction>) -> Vec<Instruction> {
+    loop {
+        let referenced: HashSet<String> = instructions
+            .iter()
+            .flat_map(|v| match v {
+                Instruction::Jump(label_destination)
+                | Instruction::JumpConditional {
+                    label_destination, ..
+                } => vec![label_destination.clone()],
+                Instruction::JumpIndirect { destinations, .. } => destinations.clone(),
+                _ => vec![],
+            })
+            .collect();
+
+        let mut changed = false;
+        let mut i = 0;
+        while i < instructions.len() {
+            if rewrite(&mut instructions, i, &referenced) {
+                changed = true;
+                // Removing an instruction can line up a new pattern with the previous one
+                i = i.saturating_sub(1);
+            } else {
+                i += 1;
+            }
+        }
+
+        if !changed {
+            return instructions;
+        }
+    }
+}
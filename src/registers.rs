@@ -1,4 +1,4 @@
-use crate::{Size, ValueCodegen};
+use crate::{MachineOperand, MemoryOperand, Size};
 
 #[repr(usize)]
 #[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
@@ -22,20 +22,107 @@ pub enum Register {
 }
 
 impl Register {
-    pub fn as_ptr(&self) -> ValueCodegen {
-        ValueCodegen::Pointer(format!("QWORD [{}]", self.as_qword()))
+    pub const ALL: [Register; 16] = [
+        Register::AX,
+        Register::BX,
+        Register::CX,
+        Register::DX,
+        Register::SI,
+        Register::DI,
+        Register::SP,
+        Register::BP,
+        Register::R8,
+        Register::R9,
+        Register::R10,
+        Register::R11,
+        Register::R12,
+        Register::R13,
+        Register::R14,
+        Register::R15,
+    ];
+
+    /// Registers a called function may overwrite, the caller saves the ones it still needs
+    pub const CALLER_SAVED: [Register; 9] = [
+        Register::AX,
+        Register::CX,
+        Register::DX,
+        Register::SI,
+        Register::DI,
+        Register::R8,
+        Register::R9,
+        Register::R10,
+        Register::R11,
+    ];
+
+    pub fn is_caller_saved(&self) -> bool {
+        Self::CALLER_SAVED.contains(self)
+    }
+
+    /// Registers a function has to restore before returning, other than the frame pointer
+    pub const CALLEE_SAVED: [Register; 5] = [
+        Register::BX,
+        Register::R12,
+        Register::R13,
+        Register::R14,
+        Register::R15,
+    ];
+
+    pub fn is_callee_saved(&self) -> bool {
+        Self::CALLEE_SAVED.contains(self)
+    }
+
+    /// The register a name of any size refers to, ignoring case
+    pub fn from_name(name: &str) -> Option<Register> {
+        let name = name.to_lowercase();
+        let sizes = [Size::Byte, Size::Word, Size::DoubleWord, Size::QuadWord];
+        let found = Register::ALL
+            .into_iter()
+            .find(|v| sizes.iter().any(|size| v.as_size(size).to_lowercase() == name));
+        found.or(match name.as_str() {
+            "ah" => Some(Register::AX),
+            "bh" => Some(Register::BX),
+            "ch" => Some(Register::CX),
+            "dh" => Some(Register::DX),
+            _ => None,
+        })
+    }
+
+    /// The register number used in ModRM and opcode encodings, 8 and above need a REX prefix
+    pub fn encoding(&self) -> u8 {
+        match self {
+            Register::AX => 0,
+            Register::CX => 1,
+            Register::DX => 2,
+            Register::BX => 3,
+            Register::SP => 4,
+            Register::BP => 5,
+            Register::SI => 6,
+            Register::DI => 7,
+            Register::R8 => 8,
+            Register::R9 => 9,
+            Register::R10 => 10,
+            Register::R11 => 11,
+            Register::R12 => 12,
+            Register::R13 => 13,
+            Register::R14 => 14,
+            Register::R15 => 15,
+        }
+    }
+
+    pub fn as_ptr(&self) -> MachineOperand {
+        self.as_deref(&Size::QuadWord)
     }
 
-    pub fn as_deref(&self, size: &Size) -> ValueCodegen {
-        ValueCodegen::Pointer(format!("{} [{}]", size.name(), self.as_qword()))
+    pub fn as_deref(&self, size: &Size) -> MachineOperand {
+        MachineOperand::Memory(MemoryOperand::new(*size, *self, 0))
     }
 
     pub fn as_index(&self) -> usize {
         unsafe { std::mem::transmute(self) }
     }
 
-    pub fn as_gen(&self, size: &Size) -> ValueCodegen {
-        ValueCodegen::Register(self.as_size(size))
+    pub fn as_gen(&self, size: &Size) -> MachineOperand {
+        MachineOperand::Register(*self, *size)
     }
 
     pub fn as_byte(&self) -> String
@@ -48,7 +48,7 @@ impl ScopeManager {
     {
         self.global_scope
             .functions
-            .get_function_type(&name.as_ref().to_string())
+            .get_function_type(name.as_ref())
     }
 
     pub fn get_variable_manager(&mut self) -> &mut VariableManager {
@@ -0,0 +1,62 @@
+//! JSON form of the IR, enabled with the `serde` feature.
+//!
+//! A module is stored as `{ "version": 2, "operands": [...] }`. Every enum is externally
+//! tagged with its `snake_case` variant name (`lowercase` for [`CompareOperation`]),
+//! tuple variants hold their fields as an array and unit variants are bare strings:
+//!
+//! ```json
+//! { "version": 2, "operands": [
+//!     { "function_decl": [
+//!         { "int": "double_word" }, "add",
+//!         [ { "return": { "add": [ { "variable": "a" }, { "variable": "b" } ] } } ],
+//!         [ ["a", { "int": "double_word" }], ["b", { "int": "double_word" }] ],
+//!         "auto"
+//!     ] }
+//! ] }
+//! ```
+//!
+//! Any change to that layout bumps [`IR_SCHEMA_VERSION`], and older versions are rejected
+//! rather than misread.
+
+use serde::{Deserialize, Serialize};
+
+use crate::*;
+
+pub const IR_SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IrModule {
+    pub version: u32,
+    pub operands: Vec<Operand>,
+}
+
+impl IrModule {
+    pub fn new(operands: Vec<Operand>) -> Self {
+        Self {
+            version: IR_SCHEMA_VERSION,
+            operands,
+        }
+    }
+}
+
+pub fn to_json(operands: &[Operand]) -> String {
+    serde_json::to_string_pretty(&IrModule::new(operands.to_vec()))
+        .expect("The IR always serializes")
+}
+
+/// Just the version, read before the rest so an older layout isn't reported as a shape error
+#[derive(Deserialize)]
+struct SchemaVersion {
+    version: u32,
+}
+
+pub fn from_json(json: &str) -> Result<Vec<Operand>, serde_json::Error> {
+    let SchemaVersion { version } = serde_json::from_str(json)?;
+    if version != IR_SCHEMA_VERSION {
+        return Err(serde::de::Error::custom(format!(
+            "Unsupported IR schema version {version}, expected {IR_SCHEMA_VERSION}"
+        )));
+    }
+    let module: IrModule = serde_json::from_str(json)?;
+    Ok(module.operands)
+}
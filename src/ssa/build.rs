@@ -0,0 +1,469 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SsaError {
+    UndefinedVariable(String),
+    UndefinedFunction(String),
+    NotAPointer(String),
+    MissingReturn(String),
+    /// Something SSA form doesn't model, like inline assembly that addresses the stack by hand
+    Unsupported(String),
+}
+
+impl Display for SsaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SsaError::UndefinedVariable(name) => write!(f, "Variable {name} does not exist"),
+            SsaError::UndefinedFunction(name) => write!(f, "Function {name} does not exist"),
+            SsaError::NotAPointer(name) => write!(f, "Can't dereference {name}, not a pointer"),
+            SsaError::MissingReturn(name) => write!(f, "No return statement in function {name}"),
+            SsaError::Unsupported(what) => write!(f, "{what} can't be converted to SSA"),
+        }
+    }
+}
+
+impl std::error::Error for SsaError {}
+
+/// Converts every function of a program into SSA form
+pub fn build_ssa(
+    operands: &[Operand],
+    externs: &[ExternFunction],
+) -> Result<Vec<SsaFunction>, SsaError> {
+    let functions = FunctionManager::from_operands(operands, externs);
+    operands
+        .iter()
+        .filter_map(|operand| match operand {
+            Operand::FunctionDecl(return_type, name, body, parameters, ..) => Some(SsaFunction::build(
+                &functions,
+                return_type,
+                name,
+                body,
+                parameters,
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+enum Variable {
+    /// Index into the definitions, its value is whatever was assigned last
+    Value(usize),
+    /// The address of its stack slot
+    Memory(ValueId),
+}
+
+struct Builder<'a> {
+    functions: &'a FunctionManager,
+    function: SsaFunction,
+    predecessors: Vec<Vec<BlockId>>,
+    /// The value each variable has at the end of a block, filled in as blocks are built
+    definitions: HashMap<(BlockId, usize), ValueId>,
+    /// Innermost scope is last, lookups fall back to the enclosing ones
+    scopes: Vec<HashMap<String, (Variable, OperandType)>>,
+    variables: usize,
+    /// `None` after a return, anything that follows can't run
+    current: Option<BlockId>,
+    /// Variables that have their address taken
+    in_memory: HashSet<String>,
+}
+
+impl SsaFunction {
+    /// Converts a single function, `functions` has the signatures of everything it may call
+    pub fn build(
+        functions: &FunctionManager,
+        return_type: &OperandType,
+        name: &str,
+        body: &[Operand],
+        parameters: &[(String, OperandType)],
+    ) -> Result<Self, SsaError> {
+        let mut in_memory = HashSet::new();
+        address_taken(body, &mut in_memory);
+
+        let mut builder = Builder {
+            functions,
+            function: SsaFunction {
+                name: name.to_string(),
+                return_type: return_type.clone(),
+                parameters: parameters.to_vec(),
+                values: vec![],
+                blocks: vec![],
+            },
+            predecessors: vec![],
+            definitions: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            variables: 0,
+            current: None,
+            in_memory,
+        };
+        builder.current = Some(builder.new_block());
+
+        for (i, (name, ty)) in parameters.iter().enumerate() {
+            let value = builder.emit(SsaOp::Parameter(i), ty);
+            builder.declare(name, ty, value);
+        }
+        for operand in body {
+            builder.operand(operand)?;
+        }
+        if builder.current.is_some() {
+            return Err(SsaError::MissingReturn(name.to_string()));
+        }
+
+        Ok(builder.function)
+    }
+}
+
+impl Builder<'_> {
+    fn new_block(&mut self) -> BlockId {
+        self.function.blocks.push(BasicBlock {
+            instructions: vec![],
+            terminator: Terminator::Return(None),
+        });
+        self.predecessors.push(vec![]);
+        BlockId(self.function.blocks.len() - 1)
+    }
+
+    fn current(&self) -> BlockId {
+        self.current.expect("Only reachable code is built")
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        let block = self.current();
+        for successor in terminator.successors() {
+            self.predecessors[successor.0].push(block);
+        }
+        self.function.blocks[block.0].terminator = terminator;
+        self.current = None;
+    }
+
+    fn emit(&mut self, op: SsaOp, ty: &OperandType) -> ValueId {
+        let id = ValueId(self.function.values.len());
+        self.function.values.push(SsaValue { op, ty: ty.clone() });
+        let block = self.current();
+        self.function.blocks[block.0].instructions.push(id);
+        id
+    }
+
+    fn lookup(&self, name: &str) -> Result<(Variable, OperandType), SsaError> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|v| v.get(name))
+            .cloned()
+            .ok_or_else(|| SsaError::UndefinedVariable(name.to_string()))
+    }
+
+    fn declare(&mut self, name: &str, ty: &OperandType, value: ValueId) {
+        let variable = if self.in_memory.contains(name) {
+            let slot = self.emit(
+                SsaOp::StackSlot(name.to_string()),
+                &OperandType::Pointer(Box::new(ty.clone())),
+            );
+            self.emit(
+                SsaOp::Store {
+                    address: slot,
+                    value,
+                },
+                &OperandType::Undefined,
+            );
+            Variable::Memory(slot)
+        } else {
+            self.variables += 1;
+            self.definitions
+                .insert((self.current(), self.variables), value);
+            Variable::Value(self.variables)
+        };
+        self.scopes
+            .last_mut()
+            .expect("There is always a scope")
+            .insert(name.to_string(), (variable, ty.clone()));
+    }
+
+    /// The value a variable has at the end of `block`, placing phis where control flow merges
+    fn read_variable(
+        &mut self,
+        name: &str,
+        variable: usize,
+        ty: &OperandType,
+        block: BlockId,
+    ) -> Result<ValueId, SsaError> {
+        if let Some(value) = self.definitions.get(&(block, variable)) {
+            return Ok(*value);
+        }
+
+        // Every predecessor is complete by the time a block is built as there are no loops
+        let predecessors = self.predecessors[block.0].clone();
+        let value = match predecessors.as_slice() {
+            [] => return Err(SsaError::UndefinedVariable(name.to_string())),
+            [predecessor] => self.read_variable(name, variable, ty, *predecessor)?,
+            _ => {
+                let mut incoming = vec![];
+                for predecessor in predecessors {
+                    incoming.push((
+                        predecessor,
+                        self.read_variable(name, variable, ty, predecessor)?,
+                    ));
+                }
+
+                if incoming.iter().all(|v| v.1 == incoming[0].1) {
+                    incoming[0].1
+                } else {
+                    let id = ValueId(self.function.values.len());
+                    self.function.values.push(SsaValue {
+                        op: SsaOp::Phi(incoming),
+                        ty: ty.clone(),
+                    });
+                    let instructions = &mut self.function.blocks[block.0].instructions;
+                    let first = instructions
+                        .iter()
+                        .position(|v| !matches!(self.function.values[v.0].op, SsaOp::Phi(_)))
+                        .unwrap_or(instructions.len());
+                    instructions.insert(first, id);
+                    id
+                }
+            }
+        };
+
+        self.definitions.insert((block, variable), value);
+        Ok(value)
+    }
+
+    fn read(&mut self, name: &str) -> Result<(ValueId, OperandType), SsaError> {
+        let (variable, ty) = self.lookup(name)?;
+        let value = match variable {
+            Variable::Value(variable) => self.read_variable(name, variable, &ty, self.current())?,
+            Variable::Memory(slot) => self.emit(SsaOp::Load(slot), &ty),
+        };
+        Ok((value, ty))
+    }
+
+    fn write(&mut self, name: &str, value: ValueId) -> Result<(), SsaError> {
+        match self.lookup(name)?.0 {
+            Variable::Value(variable) => {
+                self.definitions.insert((self.current(), variable), value);
+            }
+            Variable::Memory(slot) => {
+                self.emit(
+                    SsaOp::Store {
+                        address: slot,
+                        value,
+                    },
+                    &OperandType::Undefined,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn pointee(&mut self, name: &str) -> Result<(ValueId, OperandType), SsaError> {
+        match self.read(name)? {
+            (address, OperandType::Pointer(inner)) => Ok((address, *inner)),
+            _ => Err(SsaError::NotAPointer(name.to_string())),
+        }
+    }
+
+    /// The type a value has on its own, literals take the type of where they are used
+    fn value_type(&self, value: &Value) -> Result<Option<OperandType>, SsaError> {
+        Ok(match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                self.value_type(lhs)?.or(self.value_type(rhs)?)
+            }
+            Value::Reference(name) => Some(OperandType::Pointer(Box::new(self.lookup(name)?.1))),
+            Value::Dereference(name) => match self.lookup(name)?.1 {
+                OperandType::Pointer(inner) => Some(*inner),
+                _ => return Err(SsaError::NotAPointer(name.clone())),
+            },
+            Value::Variable(name) => Some(self.lookup(name)?.1),
+            Value::FunctionCall(name, _) => Some(self.signature(name)?.0),
+            Value::Char(_) => Some(OperandType::Char),
+            Value::StringLiteral(_) => Some(OperandType::Pointer(Box::new(OperandType::Char))),
+            Value::Int(_) | Value::Null => None,
+        })
+    }
+
+    fn signature(&self, name: &str) -> Result<(OperandType, Vec<OperandType>), SsaError> {
+        self.functions
+            .get_function_type(name)
+            .cloned()
+            .ok_or_else(|| SsaError::UndefinedFunction(name.to_string()))
+    }
+
+    /// Computes `value` as `expected`, or as its own type when there is none
+    fn value(
+        &mut self,
+        value: &Value,
+        expected: Option<&OperandType>,
+    ) -> Result<ValueId, SsaError> {
+        let ty = match expected {
+            Some(ty) => ty.clone(),
+            None => self.value_type(value)?.unwrap_or(DEFAULT_TYPE),
+        };
+
+        Ok(match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                let lhs = self.value(lhs, Some(&ty))?;
+                let rhs = self.value(rhs, Some(&ty))?;
+                let op = if matches!(value, Value::Add(..)) {
+                    SsaOp::Add(lhs, rhs)
+                } else {
+                    SsaOp::Sub(lhs, rhs)
+                };
+                self.emit(op, &ty)
+            }
+            Value::Reference(name) => match self.lookup(name)?.0 {
+                Variable::Memory(slot) => slot,
+                Variable::Value(_) => unreachable!("Referenced variables always live in memory"),
+            },
+            Value::Dereference(name) => {
+                let (address, inner) = self.pointee(name)?;
+                self.emit(SsaOp::Load(address), &inner)
+            }
+            Value::Variable(name) => self.read(name)?.0,
+            Value::Char(c) => self.emit(SsaOp::Constant(*c as i64), &ty),
+            // Anything that isn't a number names a symbol
+            Value::Int(num) => match parse_int_literal(num) {
+                Some(constant) => self.emit(SsaOp::Constant(constant), &ty),
+                None => self.emit(SsaOp::Symbol(num.clone()), &ty),
+            },
+            Value::StringLiteral(literal) => self.emit(SsaOp::StringLiteral(literal.clone()), &ty),
+            Value::FunctionCall(name, parameters) => self.call(name, parameters)?,
+            Value::Null => {
+                return Err(SsaError::Unsupported(
+                    "null outside of a return".to_string(),
+                ))
+            }
+        })
+    }
+
+    fn call(&mut self, name: &str, parameters: &[Value]) -> Result<ValueId, SsaError> {
+        let (return_type, types) = self.signature(name)?;
+        let mut arguments = vec![];
+        for (i, value) in parameters.iter().enumerate() {
+            arguments.push(self.value(value, types.get(i))?);
+        }
+        Ok(self.emit(SsaOp::Call(name.to_string(), arguments), &return_type))
+    }
+
+    fn operand(&mut self, operand: &Operand) -> Result<(), SsaError> {
+        if self.current.is_none() {
+            // Nothing after a return runs
+            return Ok(());
+        }
+
+        match operand {
+            Operand::DeclareVariable(ty, name, value) => {
+                let value = self.value(value, Some(ty))?;
+                self.declare(name, ty, value);
+            }
+            Operand::SetValue(Value::Variable(name), value) => {
+                let ty = self.lookup(name)?.1;
+                let value = self.value(value, Some(&ty))?;
+                self.write(name, value)?;
+            }
+            Operand::SetValue(Value::Dereference(name), value) => {
+                let (address, ty) = self.pointee(name)?;
+                let value = self.value(value, Some(&ty))?;
+                self.emit(SsaOp::Store { address, value }, &OperandType::Undefined);
+            }
+            Operand::SetValue(lhs, _) => {
+                return Err(SsaError::Unsupported(format!("Assigning to {lhs}")));
+            }
+            Operand::FunctionCall(name, parameters) => {
+                self.call(name, parameters)?;
+            }
+            Operand::If {
+                predicate,
+                main_body,
+            } => {
+                let ty = match self.value_type(&predicate.lhs)? {
+                    Some(ty) => ty,
+                    None => self.value_type(&predicate.rhs)?.unwrap_or(DEFAULT_TYPE),
+                };
+                let lhs = self.value(&predicate.lhs, Some(&ty))?;
+                let rhs = self.value(&predicate.rhs, Some(&ty))?;
+
+                let then = self.new_block();
+                let merge = self.new_block();
+                self.terminate(Terminator::Branch {
+                    operation: predicate.operation,
+                    lhs,
+                    rhs,
+                    then,
+                    otherwise: merge,
+                });
+
+                self.current = Some(then);
+                self.scopes.push(HashMap::new());
+                for operand in main_body {
+                    self.operand(operand)?;
+                }
+                self.scopes.pop();
+                if self.current.is_some() {
+                    self.terminate(Terminator::Jump(merge));
+                }
+                self.current = Some(merge);
+            }
+            Operand::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => {
+                let ty = self.value_type(scrutinee)?.unwrap_or(DEFAULT_TYPE);
+                let value = self.value(scrutinee, Some(&ty))?;
+
+                let merge = self.new_block();
+                let targets: Vec<(i64, BlockId)> =
+                    cases.iter().map(|v| (v.0, self.new_block())).collect();
+                let otherwise = if default.is_empty() { merge } else { self.new_block() };
+                self.terminate(Terminator::Switch {
+                    value,
+                    cases: targets.clone(),
+                    default: otherwise,
+                });
+
+                let mut bodies: Vec<(&[Operand], BlockId)> =
+                    cases.iter().zip(&targets).map(|(case, target)| (case.1.as_slice(), target.1)).collect();
+                if otherwise != merge {
+                    bodies.push((default, otherwise));
+                }
+                for (body, block) in bodies {
+                    self.current = Some(block);
+                    self.scopes.push(HashMap::new());
+                    for operand in body {
+                        self.operand(operand)?;
+                    }
+                    self.scopes.pop();
+                    if self.current.is_some() {
+                        self.terminate(Terminator::Jump(merge));
+                    }
+                }
+                // Every body may have returned
+                self.current = (!self.predecessors[merge.0].is_empty()).then_some(merge);
+            }
+            Operand::Return(Value::Null) => self.terminate(Terminator::Return(None)),
+            Operand::Return(value) => {
+                let return_type = self.function.return_type.clone();
+                let value = self.value(value, Some(&return_type))?;
+                self.terminate(Terminator::Return(Some(value)));
+            }
+            Operand::DropVariable(name) => {
+                self.lookup(name)?;
+                if let Some(scope) = self.scopes.iter_mut().rev().find(|v| v.contains_key(name)) {
+                    scope.remove(name);
+                }
+            }
+            Operand::InlineAssembly(_) | Operand::ExtendedAssembly(_) => {
+                return Err(SsaError::Unsupported("Inline assembly".to_string()));
+            }
+            Operand::FunctionDecl(_, name, ..) => {
+                return Err(SsaError::Unsupported(format!("Nested function {name}")));
+            }
+            // Neither generates any code
+            Operand::Add(..) | Operand::Subtract(..) => {}
+        }
+        Ok(())
+    }
+}
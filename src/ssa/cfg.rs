@@ -0,0 +1,168 @@
+use crate::*;
+
+/// The control flow graph of an [`SsaFunction`] along with its dominator tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cfg {
+    pub predecessors: Vec<Vec<BlockId>>,
+    pub successors: Vec<Vec<BlockId>>,
+    /// Every block reachable from the entry, in reverse postorder
+    pub reverse_postorder: Vec<BlockId>,
+    /// The entry is its own immediate dominator, unreachable blocks have none
+    pub immediate_dominators: Vec<Option<BlockId>>,
+}
+
+impl Cfg {
+    pub fn new(function: &SsaFunction) -> Self {
+        let successors: Vec<Vec<BlockId>> = function
+            .blocks
+            .iter()
+            .map(|v| v.terminator.successors())
+            .collect();
+        let mut predecessors = vec![vec![]; function.blocks.len()];
+        for (i, targets) in successors.iter().enumerate() {
+            for target in targets {
+                if !predecessors[target.0].contains(&BlockId(i)) {
+                    predecessors[target.0].push(BlockId(i));
+                }
+            }
+        }
+
+        let mut cfg = Cfg {
+            predecessors,
+            successors,
+            reverse_postorder: vec![],
+            immediate_dominators: vec![None; function.blocks.len()],
+        };
+        cfg.reverse_postorder = cfg.postorder();
+        cfg.reverse_postorder.reverse();
+        cfg.compute_dominators();
+        cfg
+    }
+
+    fn postorder(&self) -> Vec<BlockId> {
+        let mut order = vec![];
+        let mut visited = vec![false; self.successors.len()];
+        // Each entry is a block and how many of its successors were visited already
+        let mut stack = vec![(SsaFunction::ENTRY, 0)];
+        visited[SsaFunction::ENTRY.0] = true;
+
+        while let Some((block, next)) = stack.pop() {
+            match self.successors[block.0].get(next) {
+                Some(&successor) => {
+                    stack.push((block, next + 1));
+                    if !visited[successor.0] {
+                        visited[successor.0] = true;
+                        stack.push((successor, 0));
+                    }
+                }
+                None => order.push(block),
+            }
+        }
+        order
+    }
+
+    /// "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy
+    fn compute_dominators(&mut self) {
+        let mut order = vec![usize::MAX; self.successors.len()];
+        for (i, block) in self.reverse_postorder.iter().enumerate() {
+            order[block.0] = i;
+        }
+
+        self.immediate_dominators[SsaFunction::ENTRY.0] = Some(SsaFunction::ENTRY);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in self.reverse_postorder.iter().skip(1) {
+                let mut processed = self.predecessors[block.0]
+                    .iter()
+                    .filter(|v| self.immediate_dominators[v.0].is_some());
+                let Some(&first) = processed.next() else {
+                    continue;
+                };
+
+                let mut dominator = first;
+                for &predecessor in processed {
+                    dominator = self.intersect(&order, predecessor, dominator);
+                }
+                if self.immediate_dominators[block.0] != Some(dominator) {
+                    self.immediate_dominators[block.0] = Some(dominator);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    fn intersect(&self, order: &[usize], mut a: BlockId, mut b: BlockId) -> BlockId {
+        while a != b {
+            while order[a.0] > order[b.0] {
+                a = self.immediate_dominators[a.0].expect("Processed blocks have a dominator");
+            }
+            while order[b.0] > order[a.0] {
+                b = self.immediate_dominators[b.0].expect("Processed blocks have a dominator");
+            }
+        }
+        a
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        self.immediate_dominators[block.0].is_some()
+    }
+
+    /// The immediate dominator of a block, `None` for the entry and unreachable blocks
+    pub fn immediate_dominator(&self, block: BlockId) -> Option<BlockId> {
+        self.immediate_dominators[block.0].filter(|v| *v != block)
+    }
+
+    /// Whether every path from the entry to `block` goes through `dominator`
+    pub fn dominates(&self, dominator: BlockId, block: BlockId) -> bool {
+        if !self.is_reachable(block) {
+            return false;
+        }
+
+        let mut current = block;
+        loop {
+            if current == dominator {
+                return true;
+            }
+            match self.immediate_dominator(current) {
+                Some(parent) => current = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// The blocks a block immediately dominates, its children in the dominator tree
+    pub fn dominated_by(&self, block: BlockId) -> Vec<BlockId> {
+        self.reverse_postorder
+            .iter()
+            .copied()
+            .filter(|v| self.immediate_dominator(*v) == Some(block))
+            .collect()
+    }
+
+    /// The blocks where the dominance of each block ends, which is where phis for values
+    /// defined in it are needed
+    pub fn dominance_frontiers(&self) -> Vec<Vec<BlockId>> {
+        let mut frontiers = vec![vec![]; self.successors.len()];
+        for &block in &self.reverse_postorder {
+            if self.predecessors[block.0].len() < 2 {
+                continue;
+            }
+
+            let dominator = self.immediate_dominators[block.0];
+            for &predecessor in &self.predecessors[block.0] {
+                let mut runner = Some(predecessor);
+                while let Some(current) = runner.filter(|v| Some(*v) != dominator) {
+                    if !self.is_reachable(current) {
+                        break;
+                    }
+                    if !frontiers[current.0].contains(&block) {
+                        frontiers[current.0].push(block);
+                    }
+                    runner = self.immediate_dominator(current);
+                }
+            }
+        }
+        frontiers
+    }
+}
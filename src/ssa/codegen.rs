@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+
+use crate::*;
+
+/// Scratch registers, neither is used to pass arguments
+const PRIMARY: Register = Register::AX;
+const SECONDARY: Register = Register::R11;
+
+struct Lowering<'a> {
+    function: &'a SsaFunction,
+    compiler: &'a mut Compiler,
+    labels: HashMap<BlockId, String>,
+    /// Frame offset of every value that is kept in memory
+    slots: HashMap<ValueId, i32>,
+    strings: HashMap<ValueId, String>,
+}
+
+impl SsaFunction {
+    /// Lowers the function back into x86-64 instructions, giving every value its own stack slot
+    pub fn codegen(&self, compiler: &mut Compiler) {
+        let cfg = Cfg::new(self);
+        let mut lowering = Lowering {
+            function: self,
+            compiler,
+            labels: HashMap::new(),
+            slots: HashMap::new(),
+            strings: HashMap::new(),
+        };
+
+        let mut frame: u32 = 0;
+        for &block in &cfg.reverse_postorder {
+            let label = match block {
+                SsaFunction::ENTRY => self.name.clone(),
+                _ => lowering.compiler.fetch_id(".BB"),
+            };
+            lowering.labels.insert(block, label);
+
+            for &id in &self.block(block).instructions {
+                let value = self.value(id);
+                let size = match (&value.op, &value.ty) {
+                    (SsaOp::StackSlot(_), OperandType::Pointer(inner)) => inner.size(),
+                    (SsaOp::StringLiteral(literal), _) => {
+                        let label = lowering.compiler.fetch_id("__string");
+                        lowering
+                            .compiler
+                            .string_defines
+                            .push((label.clone(), literal.replace('\n', "\\n")));
+                        lowering.strings.insert(id, label);
+                        continue;
+                    }
+                    (SsaOp::Constant(_) | SsaOp::Symbol(_) | SsaOp::Store { .. }, _) => continue,
+                    (_, ty) => ty.size(),
+                };
+                let bytes = size.get_bytes() as u32;
+                frame = (frame + bytes).next_multiple_of(bytes);
+                lowering.slots.insert(id, -(frame as i32));
+            }
+        }
+        // Keeps the stack 16 byte aligned at calls
+        let frame = frame.next_multiple_of(16);
+
+        let quad = |register: Register| register.as_gen(&Size::QuadWord);
+        lowering.emit(Instruction::Label(self.name.clone()));
+        lowering.emit(Instruction::Push(quad(Register::BP)));
+        lowering.emit(Instruction::Move(quad(Register::BP), quad(Register::SP)));
+        if frame > 0 {
+            lowering.emit(Instruction::Sub(
+                quad(Register::SP),
+                MachineOperand::Immediate(frame as i64),
+            ));
+        }
+
+        for (i, &block) in cfg.reverse_postorder.iter().enumerate() {
+            if block != SsaFunction::ENTRY {
+                lowering.emit(Instruction::Label(lowering.labels[&block].clone()));
+            }
+            let tail_call = lowering.tail_call(block);
+            for &id in &self.block(block).instructions {
+                // Its arguments are set up by the terminator instead
+                if Some(id) != tail_call {
+                    lowering.instruction(id);
+                }
+            }
+            lowering.phi_copies(block);
+            lowering.terminator(block, cfg.reverse_postorder.get(i + 1).copied());
+        }
+    }
+}
+
+impl Lowering<'_> {
+    fn emit(&mut self, instruction: Instruction) {
+        self.compiler.new_instruction(instruction);
+    }
+
+    /// The call whose result a block returns, if the caller's frame can be dropped before it
+    fn tail_call(&self, block: BlockId) -> Option<ValueId> {
+        let block = self.function.block(block);
+        let Terminator::Return(Some(value)) = block.terminator else {
+            return None;
+        };
+        let SsaOp::Call(_, arguments) = &self.function.value(value).op else {
+            return None;
+        };
+        // Pointers to the frame would outlive it
+        let frame_escapes = self
+            .function
+            .values
+            .iter()
+            .any(|v| matches!(v.op, SsaOp::StackSlot(_)));
+
+        (block.instructions.last() == Some(&value)
+            && arguments.len() <= PARAMETER_REGISTERS.len()
+            && self.size(value) == self.function.return_type.size()
+            && !frame_escapes)
+            .then_some(value)
+    }
+
+    fn size(&self, id: ValueId) -> Size {
+        self.function.value(id).ty.size()
+    }
+
+    fn slot(&self, id: ValueId, size: Size) -> MachineOperand {
+        MachineOperand::Memory(MemoryOperand::new(size, Register::BP, self.slots[&id]))
+    }
+
+    /// Where a value can be read from directly, `None` when it has to be computed first
+    fn location(&self, id: ValueId) -> Option<MachineOperand> {
+        match &self.function.value(id).op {
+            SsaOp::Constant(value) => Some(MachineOperand::Immediate(*value)),
+            SsaOp::Symbol(name) => Some(MachineOperand::Symbol(name.clone())),
+            SsaOp::StringLiteral(_) => Some(MachineOperand::Symbol(self.strings[&id].clone())),
+            SsaOp::StackSlot(_) => None,
+            _ => Some(self.slot(id, self.size(id))),
+        }
+    }
+
+    /// Puts a value into `register`, returning the register at the value's size
+    fn load(&mut self, register: Register, id: ValueId) -> MachineOperand {
+        let size = self.size(id);
+        let destination = register.as_gen(&size);
+        match self.location(id) {
+            Some(source) => self.emit(Instruction::Move(destination.clone(), source)),
+            None => {
+                let SsaOp::StackSlot(_) = self.function.value(id).op else {
+                    unreachable!("Only stack slots have no location")
+                };
+                let OperandType::Pointer(inner) = &self.function.value(id).ty else {
+                    unreachable!("Stack slots are pointers")
+                };
+                let slot = self.slot(id, inner.size());
+                self.emit(Instruction::LoadAddress(destination.clone(), slot));
+            }
+        }
+        destination
+    }
+
+    /// A value as the source operand of an instruction, using `register` if it can't be used
+    /// directly
+    fn source(&mut self, register: Register, id: ValueId) -> MachineOperand {
+        match self.location(id) {
+            Some(MachineOperand::Immediate(value)) if i32::try_from(value).is_ok() => {
+                MachineOperand::Immediate(value)
+            }
+            Some(memory @ MachineOperand::Memory(_)) => memory,
+            _ => self.load(register, id),
+        }
+    }
+
+    fn instruction(&mut self, id: ValueId) {
+        let value = self.function.value(id);
+        let size = value.ty.size();
+        match &value.op {
+            SsaOp::Parameter(i) => {
+                let parameter = PARAMETER_REGISTERS[*i].as_gen(&size);
+                self.emit(Instruction::Move(self.slot(id, size), parameter));
+            }
+            SsaOp::Add(lhs, rhs) | SsaOp::Sub(lhs, rhs) => {
+                let destination = self.load(PRIMARY, *lhs);
+                let source = self.source(SECONDARY, *rhs);
+                if matches!(value.op, SsaOp::Add(..)) {
+                    self.emit(Instruction::Add(destination.clone(), source));
+                } else {
+                    self.emit(Instruction::Sub(destination.clone(), source));
+                }
+                self.emit(Instruction::Move(self.slot(id, size), destination));
+            }
+            SsaOp::Load(address) => {
+                self.load(PRIMARY, *address);
+                let value = PRIMARY.as_gen(&size);
+                self.emit(Instruction::Move(value.clone(), PRIMARY.as_deref(&size)));
+                self.emit(Instruction::Move(self.slot(id, size), value));
+            }
+            SsaOp::Store { address, value } => {
+                let size = self.size(*value);
+                // The address needs the primary register, so the value can't stay in memory
+                let source = match self.location(*value) {
+                    Some(MachineOperand::Immediate(value)) if i32::try_from(value).is_ok() => {
+                        MachineOperand::Immediate(value)
+                    }
+                    _ => self.load(SECONDARY, *value),
+                };
+                self.load(PRIMARY, *address);
+                self.emit(Instruction::Move(PRIMARY.as_deref(&size), source));
+            }
+            SsaOp::Call(name, arguments) => {
+                for (i, argument) in arguments.iter().enumerate() {
+                    self.load(PARAMETER_REGISTERS[i], *argument);
+                }
+                self.emit(Instruction::Call(name.clone()));
+                self.emit(Instruction::Move(
+                    self.slot(id, size),
+                    PRIMARY.as_gen(&size),
+                ));
+            }
+            // Phis are written by their predecessors, the rest have no code of their own
+            SsaOp::Phi(_)
+            | SsaOp::Constant(_)
+            | SsaOp::Symbol(_)
+            | SsaOp::StringLiteral(_)
+            | SsaOp::StackSlot(_) => {}
+        }
+    }
+
+    /// Writes the values the successors' phis take when coming from `block`
+    fn phi_copies(&mut self, block: BlockId) {
+        for successor in self.function.block(block).terminator.successors() {
+            for &phi in &self.function.block(successor).instructions {
+                let SsaOp::Phi(incoming) = &self.function.value(phi).op else {
+                    break;
+                };
+                let Some(&(_, value)) = incoming.iter().find(|v| v.0 == block) else {
+                    continue;
+                };
+                let register = self.load(PRIMARY, value);
+                self.emit(Instruction::Move(self.slot(phi, self.size(phi)), register));
+            }
+        }
+    }
+
+    fn terminator(&mut self, block: BlockId, next: Option<BlockId>) {
+        match &self.function.block(block).terminator {
+            Terminator::Jump(target) => {
+                if next != Some(*target) {
+                    self.emit(Instruction::Jump(self.labels[target].clone()));
+                }
+            }
+            Terminator::Branch {
+                operation,
+                lhs,
+                rhs,
+                then,
+                otherwise,
+            } => {
+                let signed = self.function.value(*lhs).ty.is_signed();
+                let lhs = self.load(PRIMARY, *lhs);
+                let rhs = self.source(SECONDARY, *rhs);
+                self.emit(Instruction::Compare(lhs, rhs));
+
+                let jump =
+                    |label: &String, conditional: CompareOperation| Instruction::JumpConditional {
+                        label_destination: label.clone(),
+                        conditional,
+                        signed,
+                    };
+                if next == Some(*then) {
+                    self.emit(jump(&self.labels[otherwise], operation.get_opposite()));
+                } else {
+                    self.emit(jump(&self.labels[then], *operation));
+                    if next != Some(*otherwise) {
+                        self.emit(Instruction::Jump(self.labels[otherwise].clone()));
+                    }
+                }
+            }
+            Terminator::Switch {
+                value,
+                cases,
+                default,
+            } => {
+                let value = self.load(PRIMARY, *value);
+                let cases: Vec<(i64, String)> =
+                    cases.iter().map(|(case, block)| (*case, self.labels[block].clone())).collect();
+                let default = self.labels[default].clone();
+                switch_dispatch(&value, &cases, &default, self.compiler);
+            }
+            Terminator::Return(value) => {
+                let tail_call = self.tail_call(block);
+                match (value, tail_call) {
+                    (_, Some(call)) => {
+                        let SsaOp::Call(_, arguments) = &self.function.value(call).op else {
+                            unreachable!("Checked by tail_call")
+                        };
+                        for (i, argument) in arguments.iter().enumerate() {
+                            self.load(PARAMETER_REGISTERS[i], *argument);
+                        }
+                    }
+                    (Some(value), None) => {
+                        self.load(PRIMARY, *value);
+                    }
+                    (None, None) => {}
+                }
+                let quad = |register: Register| register.as_gen(&Size::QuadWord);
+                self.emit(Instruction::Move(quad(Register::SP), quad(Register::BP)));
+                self.emit(Instruction::Pop(quad(Register::BP)));
+                match tail_call.map(|v| &self.function.value(v).op) {
+                    // The callee returns straight to our caller
+                    Some(SsaOp::Call(name, _)) => self.emit(Instruction::Jump(name.clone())),
+                    _ => self.emit(Instruction::Return),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+mod build;
+pub use build::*;
+
+mod cfg;
+pub use cfg::*;
+
+mod codegen;
+
+use std::fmt::Display;
+
+use crate::*;
+
+/// An SSA value, defined exactly once by the instruction at the same index of
+/// [`SsaFunction::values`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SsaOp {
+    Constant(i64),
+    /// The address of a symbol, such as a function or string define
+    Symbol(String),
+    StringLiteral(String),
+    /// The nth argument the function was called with
+    Parameter(usize),
+    /// The address of a variable that had its address taken, so it has to live in memory
+    StackSlot(String),
+    Add(ValueId, ValueId),
+    Sub(ValueId, ValueId),
+    /// Reads the value's type from an address
+    Load(ValueId),
+    Store {
+        address: ValueId,
+        value: ValueId,
+    },
+    Call(String, Vec<ValueId>),
+    /// Picks the value coming from whichever predecessor ran last
+    Phi(Vec<(BlockId, ValueId)>),
+}
+
+impl SsaOp {
+    /// Whether the instruction produces a value other instructions can use
+    pub fn has_result(&self) -> bool {
+        !matches!(self, SsaOp::Store { .. })
+    }
+
+    /// Values read by the instruction
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            SsaOp::Add(lhs, rhs) | SsaOp::Sub(lhs, rhs) => vec![*lhs, *rhs],
+            SsaOp::Load(address) => vec![*address],
+            SsaOp::Store { address, value } => vec![*address, *value],
+            SsaOp::Call(_, arguments) => arguments.clone(),
+            SsaOp::Phi(incoming) => incoming.iter().map(|v| v.1).collect(),
+            SsaOp::Constant(_)
+            | SsaOp::Symbol(_)
+            | SsaOp::StringLiteral(_)
+            | SsaOp::Parameter(_)
+            | SsaOp::StackSlot(_) => vec![],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SsaValue {
+    pub op: SsaOp,
+    pub ty: OperandType,
+}
+
+/// How control leaves a basic block
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    Branch {
+        operation: CompareOperation,
+        lhs: ValueId,
+        rhs: ValueId,
+        then: BlockId,
+        otherwise: BlockId,
+    },
+    /// Goes to the block of the case equal to `value`, or to `default`
+    Switch {
+        value: ValueId,
+        cases: Vec<(i64, BlockId)>,
+        default: BlockId,
+    },
+    Return(Option<ValueId>),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![*then, *otherwise],
+            Terminator::Switch { cases, default, .. } => {
+                cases.iter().map(|v| v.1).chain([*default]).collect()
+            }
+            Terminator::Return(_) => vec![],
+        }
+    }
+
+    pub fn operands(&self) -> Vec<ValueId> {
+        match self {
+            Terminator::Branch { lhs, rhs, .. } => vec![*lhs, *rhs],
+            Terminator::Switch { value, .. } => vec![*value],
+            Terminator::Return(Some(value)) => vec![*value],
+            Terminator::Jump(_) | Terminator::Return(None) => vec![],
+        }
+    }
+}
+
+/// Straight line code, phis always come first
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    pub instructions: Vec<ValueId>,
+    pub terminator: Terminator,
+}
+
+/// A function in SSA form, its entry is the first block
+#[derive(Debug, Clone, PartialEq)]
+pub struct SsaFunction {
+    pub name: String,
+    pub return_type: OperandType,
+    pub parameters: Vec<(String, OperandType)>,
+    pub values: Vec<SsaValue>,
+    pub blocks: Vec<BasicBlock>,
+}
+
+impl SsaFunction {
+    pub const ENTRY: BlockId = BlockId(0);
+
+    pub fn value(&self, id: ValueId) -> &SsaValue {
+        &self.values[id.0]
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id.0]
+    }
+}
+
+impl Display for ValueId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl Display for BlockId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl Display for SsaOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SsaOp::Constant(value) => write!(f, "const {value}"),
+            SsaOp::Symbol(name) => write!(f, "symbol {name}"),
+            SsaOp::StringLiteral(literal) => write!(f, "string {literal:?}"),
+            SsaOp::Parameter(i) => write!(f, "param {i}"),
+            SsaOp::StackSlot(name) => write!(f, "slot {name}"),
+            SsaOp::Add(lhs, rhs) => write!(f, "add {lhs}, {rhs}"),
+            SsaOp::Sub(lhs, rhs) => write!(f, "sub {lhs}, {rhs}"),
+            SsaOp::Load(address) => write!(f, "load {address}"),
+            SsaOp::Store { address, value } => write!(f, "store {address}, {value}"),
+            SsaOp::Call(name, arguments) => {
+                let arguments = arguments.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+                write!(f, "call {name}({})", arguments.join(", "))
+            }
+            SsaOp::Phi(incoming) => {
+                let incoming = incoming
+                    .iter()
+                    .map(|(block, value)| format!("[{block}: {value}]"))
+                    .collect::<Vec<_>>();
+                write!(f, "phi {}", incoming.join(", "))
+            }
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jump {target}"),
+            Terminator::Branch {
+                operation,
+                lhs,
+                rhs,
+                then,
+                otherwise,
+            } => write!(f, "branch {lhs} {operation} {rhs}, {then}, {otherwise}"),
+            Terminator::Switch {
+                value,
+                cases,
+                default,
+            } => {
+                write!(f, "switch {value}")?;
+                for (case, block) in cases {
+                    write!(f, ", {case}: {block}")?;
+                }
+                write!(f, ", default: {default}")
+            }
+            Terminator::Return(Some(value)) => write!(f, "return {value}"),
+            Terminator::Return(None) => write!(f, "return"),
+        }
+    }
+}
+
+impl Display for SsaFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parameters = self
+            .parameters
+            .iter()
+            .map(|(name, ty)| format!("{ty} {name}"))
+            .collect::<Vec<_>>();
+        writeln!(
+            f,
+            "fn {} {}({}) {{",
+            self.return_type,
+            self.name,
+            parameters.join(", ")
+        )?;
+        for (i, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}:", BlockId(i))?;
+            for id in &block.instructions {
+                let value = self.value(*id);
+                if value.op.has_result() {
+                    writeln!(f, "    {id}: {} = {}", value.ty, value.op)?;
+                } else {
+                    writeln!(f, "    {}", value.op)?;
+                }
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        write!(f, "}}")
+    }
+}
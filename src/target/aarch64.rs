@@ -0,0 +1,220 @@
+use crate::*;
+
+/// AAPCS64: arguments in X0-X7, X29 as the frame pointer and X30 as the link register
+pub struct AArch64;
+
+/// Unscaled loads and stores only reach 256 bytes below the frame pointer
+const MAX_UNSCALED_OFFSET: u32 = 256;
+
+impl AArch64 {
+    fn w(reg: &str) -> String {
+        reg.replacen('x', "w", 1)
+    }
+
+    /// Loads and stores take 32 bit registers for anything up to a word
+    fn sized(reg: &str, size: Size) -> String {
+        match size {
+            Size::QuadWord => reg.to_string(),
+            _ => Self::w(reg),
+        }
+    }
+
+    /// Address operand for a frame slot, going through `x16` when it is out of range
+    fn local(out: &mut Vec<String>, offset: u32) -> String {
+        if offset <= MAX_UNSCALED_OFFSET {
+            format!("[x29, #-{offset}]")
+        } else {
+            out.push(format!("sub x16, x29, #{offset}"));
+            "[x16]".to_string()
+        }
+    }
+
+    fn load_mnemonic(size: Size, signed: bool, unscaled: bool) -> String {
+        let base = match (size, signed) {
+            (Size::Byte, true) => "ldrsb",
+            (Size::Byte, false) => "ldrb",
+            (Size::Word, true) => "ldrsh",
+            (Size::Word, false) => "ldrh",
+            (Size::DoubleWord, true) => "ldrsw",
+            (Size::DoubleWord, false) | (Size::QuadWord, _) => "ldr",
+        };
+        if unscaled {
+            base.replacen("ldr", "ldur", 1)
+        } else {
+            base.to_string()
+        }
+    }
+
+    fn load_register(reg: &str, size: Size, signed: bool) -> String {
+        match (size, signed) {
+            // Sign extending loads write the full 64 bit register
+            (Size::QuadWord, _) | (_, true) => reg.to_string(),
+            _ => Self::w(reg),
+        }
+    }
+
+    fn store_mnemonic(size: Size, unscaled: bool) -> String {
+        let base = match size {
+            Size::Byte => "strb",
+            Size::Word => "strh",
+            Size::DoubleWord | Size::QuadWord => "str",
+        };
+        if unscaled {
+            base.replacen("str", "stur", 1)
+        } else {
+            base.to_string()
+        }
+    }
+
+    fn condition(operation: CompareOperation, signed: bool) -> &'static str {
+        match (operation, signed) {
+            (CompareOperation::GT, true) => "gt",
+            (CompareOperation::GTE, true) => "ge",
+            (CompareOperation::LT, true) => "lt",
+            (CompareOperation::LTE, true) => "le",
+            (CompareOperation::GT, false) => "hi",
+            (CompareOperation::GTE, false) => "hs",
+            (CompareOperation::LT, false) => "lo",
+            (CompareOperation::LTE, false) => "ls",
+            (CompareOperation::EQ, _) => "eq",
+            (CompareOperation::NEQ, _) => "ne",
+        }
+    }
+}
+
+impl Isa for AArch64 {
+    const ACCUMULATOR: &'static str = "x9";
+    const SCRATCH: &'static str = "x10";
+    const PARAMETER_REGISTERS: &'static [&'static str] =
+        &["x0", "x1", "x2", "x3", "x4", "x5", "x6", "x7"];
+    const RETURN_REGISTER: &'static str = "x0";
+    const STACK_ALIGNMENT: u32 = 16;
+
+    fn prologue(out: &mut Vec<String>, frame_size: u32) {
+        out.push("stp x29, x30, [sp, #-16]!".to_string());
+        out.push("mov x29, sp".to_string());
+        if frame_size > 0 {
+            out.push(format!("sub sp, sp, #{frame_size}"));
+        }
+    }
+
+    fn epilogue(out: &mut Vec<String>) {
+        out.push("mov sp, x29".to_string());
+        out.push("ldp x29, x30, [sp], #16".to_string());
+        out.push("ret".to_string());
+    }
+
+    fn load_immediate(out: &mut Vec<String>, dst: &str, value: i64) {
+        if (0..=0xffff).contains(&value) {
+            out.push(format!("mov {dst}, #{value}"));
+        } else {
+            // The assembler places the constant in a literal pool
+            out.push(format!("ldr {dst}, ={value}"));
+        }
+    }
+
+    fn load_symbol_address(out: &mut Vec<String>, dst: &str, symbol: &str) {
+        out.push(format!("adrp {dst}, {symbol}"));
+        out.push(format!("add {dst}, {dst}, :lo12:{symbol}"));
+    }
+
+    fn load_local_address(out: &mut Vec<String>, dst: &str, offset: u32) {
+        out.push(format!("sub {dst}, x29, #{offset}"));
+    }
+
+    fn load_local(out: &mut Vec<String>, dst: &str, offset: u32, size: Size, signed: bool) {
+        let address = Self::local(out, offset);
+        let unscaled = address.starts_with("[x29");
+        out.push(format!(
+            "{} {}, {address}",
+            Self::load_mnemonic(size, signed, unscaled),
+            Self::load_register(dst, size, signed)
+        ));
+    }
+
+    fn store_local(out: &mut Vec<String>, src: &str, offset: u32, size: Size) {
+        let address = Self::local(out, offset);
+        let unscaled = address.starts_with("[x29");
+        out.push(format!(
+            "{} {}, {address}",
+            Self::store_mnemonic(size, unscaled),
+            Self::sized(src, size)
+        ));
+    }
+
+    fn load_indirect(out: &mut Vec<String>, dst: &str, address: &str, size: Size, signed: bool) {
+        out.push(format!(
+            "{} {}, [{address}]",
+            Self::load_mnemonic(size, signed, false),
+            Self::load_register(dst, size, signed)
+        ));
+    }
+
+    fn store_indirect(out: &mut Vec<String>, src: &str, address: &str, size: Size) {
+        out.push(format!(
+            "{} {}, [{address}]",
+            Self::store_mnemonic(size, false),
+            Self::sized(src, size)
+        ));
+    }
+
+    fn move_register(out: &mut Vec<String>, dst: &str, src: &str) {
+        if dst != src {
+            out.push(format!("mov {dst}, {src}"));
+        }
+    }
+
+    fn push(out: &mut Vec<String>, src: &str) {
+        // SP has to stay 16 byte aligned
+        out.push(format!("str {src}, [sp, #-16]!"));
+    }
+
+    fn pop(out: &mut Vec<String>, dst: &str) {
+        out.push(format!("ldr {dst}, [sp], #16"));
+    }
+
+    fn add(out: &mut Vec<String>, dst: &str, rhs: &str) {
+        out.push(format!("add {dst}, {dst}, {rhs}"));
+    }
+
+    fn sub(out: &mut Vec<String>, dst: &str, rhs: &str) {
+        out.push(format!("sub {dst}, {dst}, {rhs}"));
+    }
+
+    fn extend(out: &mut Vec<String>, reg: &str, size: Size, signed: bool) {
+        let w = Self::w(reg);
+        match (size, signed) {
+            (Size::QuadWord, _) => {}
+            (Size::Byte, true) => out.push(format!("sxtb {reg}, {w}")),
+            (Size::Word, true) => out.push(format!("sxth {reg}, {w}")),
+            (Size::DoubleWord, true) => out.push(format!("sxtw {reg}, {w}")),
+            (Size::Byte, false) => out.push(format!("uxtb {w}, {w}")),
+            (Size::Word, false) => out.push(format!("uxth {w}, {w}")),
+            // Writing the 32 bit register clears the upper half
+            (Size::DoubleWord, false) => out.push(format!("mov {w}, {w}")),
+        }
+    }
+
+    fn branch_unless(
+        out: &mut Vec<String>,
+        operation: CompareOperation,
+        lhs: &str,
+        rhs: &str,
+        signed: bool,
+        label: &str,
+    ) {
+        out.push(format!("cmp {lhs}, {rhs}"));
+        out.push(format!(
+            "b.{} {label}",
+            Self::condition(operation.get_opposite(), signed)
+        ));
+    }
+
+    fn jump(out: &mut Vec<String>, label: &str) {
+        out.push(format!("b {label}"));
+    }
+
+    fn call(out: &mut Vec<String>, name: &str) {
+        out.push(format!("bl {name}"));
+    }
+}
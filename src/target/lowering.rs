@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use crate::*;
+
+struct FunctionLowering<'a, I: Isa> {
+    functions: &'a HashMap<String, (OperandType, Vec<OperandType>)>,
+    strings: &'a mut Vec<(String, String)>,
+    /// Innermost scope is last, lookups fall back to the enclosing ones
+    scopes: Vec<HashMap<String, (u32, OperandType)>>,
+    frame_size: u32,
+    return_type: OperandType,
+    return_label: String,
+    label_id: &'a mut usize,
+    out: Vec<String>,
+    isa: PhantomData<I>,
+}
+
+/// Lowers a whole program for a target described by `I`
+pub(crate) fn lower_module<I: Isa>(
+    operands: &[Operand],
+    string_defines: &[(String, String)],
+    externs: &[ExternFunction],
+) -> String {
+    let mut functions = HashMap::new();
+    for function in externs {
+        functions.insert(
+            function.name.clone(),
+            (function.return_type.clone(), function.parameters.clone()),
+        );
+    }
+    let mut defined = vec![];
+    for operand in operands {
+        if let Operand::FunctionDecl(return_type, name, _, parameters, ..) = operand {
+            defined.push(name.clone());
+            functions.insert(
+                name.clone(),
+                (return_type.clone(), parameters.iter().map(|v| v.1.clone()).collect()),
+            );
+        }
+    }
+
+    let mut strings = string_defines.to_vec();
+    let mut label_id = 0;
+    let mut text = String::new();
+
+    for operand in operands {
+        match operand {
+            Operand::FunctionDecl(return_type, name, body, parameters, ..) => {
+                let lowering = FunctionLowering::<I> {
+                    functions: &functions,
+                    strings: &mut strings,
+                    scopes: vec![],
+                    frame_size: 0,
+                    return_type: return_type.clone(),
+                    return_label: I::local_label(&format!("{name}_return")),
+                    label_id: &mut label_id,
+                    out: vec![],
+                    isa: PhantomData,
+                };
+                text.push_str(&lowering.function(name, body, parameters));
+            }
+            Operand::InlineAssembly(asm) => {
+                text.push_str(asm);
+                text.push('\n');
+            }
+            _ => {
+                eprintln!("{operand} is not allowed outside of a function");
+                panic!()
+            }
+        }
+    }
+
+    let mut data = String::new();
+    for (label, value) in &strings {
+        data.push_str(&I::string_data(label, value));
+    }
+
+    let globals = defined
+        .iter()
+        .map(|v| format!(".globl {v}\n"))
+        .collect::<Vec<String>>();
+    let mut globals = globals;
+    globals.sort();
+
+    format!(
+        "{}.section .rodata\n{data}\n.text\n{}{text}.section .note.GNU-stack,\"\",@progbits\n",
+        I::preamble(),
+        globals.concat()
+    )
+}
+
+impl<I: Isa> FunctionLowering<'_, I> {
+    fn emit_label(&mut self, label: &str) {
+        self.out.push(format!("{label}:"));
+    }
+
+    fn new_label(&mut self, prefix: &str) -> String {
+        *self.label_id += 1;
+        I::local_label(&format!("{prefix}{}", self.label_id))
+    }
+
+    fn lookup(&self, name: &str) -> (u32, OperandType) {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|v| v.get(name))
+            .cloned()
+            .unwrap_or_else(|| panic!("Variable {name} does not exist."))
+    }
+
+    fn allocate(&mut self, name: &str, ty: &OperandType) -> u32 {
+        let bytes = ty.size().get_bytes() as u32;
+        // Keep every slot naturally aligned
+        self.frame_size = (self.frame_size + bytes).next_multiple_of(bytes);
+        let offset = self.frame_size;
+        self.scopes
+            .last_mut()
+            .expect("Variables are only allocated inside functions")
+            .insert(name.to_string(), (offset, ty.clone()));
+        offset
+    }
+
+    fn function(
+        mut self,
+        name: &str,
+        body: &[Operand],
+        parameters: &[(String, OperandType)],
+    ) -> String {
+        if parameters.len() > I::PARAMETER_REGISTERS.len() {
+            eprintln!("Function {name} has too many parameters");
+            panic!()
+        }
+
+        self.scopes.push(HashMap::new());
+        for (i, (parameter, ty)) in parameters.iter().enumerate() {
+            let offset = self.allocate(parameter, ty);
+            I::store_local(&mut self.out, I::PARAMETER_REGISTERS[i], offset, ty.size());
+        }
+
+        self.body(body);
+
+        // Falling off the end of a function returns whatever is in the return register
+        let return_label = self.return_label.clone();
+        self.emit_label(&return_label);
+        I::epilogue(&mut self.out);
+
+        let mut prologue = vec![format!("{name}:")];
+        I::prologue(
+            &mut prologue,
+            self.frame_size.next_multiple_of(I::STACK_ALIGNMENT),
+        );
+
+        let mut function = String::new();
+        for line in prologue.iter().chain(&self.out) {
+            if !line.ends_with(':') {
+                function.push('\t');
+            }
+            function.push_str(line);
+            function.push('\n');
+        }
+        function
+    }
+
+    fn body(&mut self, body: &[Operand]) {
+        for operand in body {
+            self.operand(operand);
+        }
+    }
+
+    fn operand(&mut self, operand: &Operand) {
+        match operand {
+            Operand::DeclareVariable(ty, name, value) => {
+                self.value(value);
+                let offset = self.allocate(name, ty);
+                I::store_local(&mut self.out, I::ACCUMULATOR, offset, ty.size());
+            }
+            Operand::SetValue(lhs, value) => {
+                self.value(value);
+                match lhs {
+                    Value::Variable(name) => {
+                        let (offset, ty) = self.lookup(name);
+                        I::store_local(&mut self.out, I::ACCUMULATOR, offset, ty.size());
+                    }
+                    Value::Dereference(name) => {
+                        let (offset, ty) = self.lookup(name);
+                        let size = ty.deref_size().expect("Not a pointer");
+                        I::load_local(&mut self.out, I::SCRATCH, offset, Size::QuadWord, false);
+                        I::store_indirect(&mut self.out, I::ACCUMULATOR, I::SCRATCH, size);
+                    }
+                    _ => {
+                        eprintln!("Can't be lhs operand");
+                        panic!()
+                    }
+                }
+            }
+            Operand::FunctionCall(name, parameters) => {
+                self.call(name, parameters);
+            }
+            Operand::If {
+                predicate,
+                main_body,
+            } => {
+                let ty = self
+                    .value_type(&predicate.lhs)
+                    .or(self.value_type(&predicate.rhs));
+                let signed = ty.as_ref().map(OperandType::is_signed).unwrap_or(true);
+
+                self.value(&predicate.lhs);
+                I::push(&mut self.out, I::ACCUMULATOR);
+                self.value(&predicate.rhs);
+                I::move_register(&mut self.out, I::SCRATCH, I::ACCUMULATOR);
+                I::pop(&mut self.out, I::ACCUMULATOR);
+
+                let label = self.new_label("IF");
+                I::branch_unless(
+                    &mut self.out,
+                    predicate.operation,
+                    I::ACCUMULATOR,
+                    I::SCRATCH,
+                    signed,
+                    &label,
+                );
+                self.scopes.push(HashMap::new());
+                self.body(main_body);
+                self.scopes.pop();
+                self.emit_label(&label);
+            }
+            Operand::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => {
+                let ty = self.value_type(scrutinee).unwrap_or(OperandType::Int(Size::DoubleWord));
+                let signed = ty.is_signed();
+
+                // Kept in a slot of its own as the bodies in between can use every register, the
+                // end label is unique so it names the slot as well
+                let end = self.new_label("SWITCH_END");
+                self.value(scrutinee);
+                self.scopes.push(HashMap::new());
+                let offset = self.allocate(&end, &ty);
+                I::store_local(&mut self.out, I::ACCUMULATOR, offset, ty.size());
+
+                for (case, body) in cases {
+                    let next = self.new_label("CASE");
+                    I::load_local(&mut self.out, I::ACCUMULATOR, offset, ty.size(), signed);
+                    I::load_immediate(&mut self.out, I::SCRATCH, wrap_to_type(*case, &ty));
+                    I::branch_unless(
+                        &mut self.out,
+                        CompareOperation::EQ,
+                        I::ACCUMULATOR,
+                        I::SCRATCH,
+                        signed,
+                        &next,
+                    );
+                    self.scopes.push(HashMap::new());
+                    self.body(body);
+                    self.scopes.pop();
+                    I::jump(&mut self.out, &end);
+                    self.emit_label(&next);
+                }
+                self.scopes.push(HashMap::new());
+                self.body(default);
+                self.scopes.pop();
+                self.emit_label(&end);
+                self.scopes.pop();
+            }
+            Operand::Return(value) => {
+                if *value != Value::Null {
+                    self.value(value);
+                    let ty = self.return_type.clone();
+                    I::extend(&mut self.out, I::ACCUMULATOR, ty.size(), ty.is_signed());
+                    I::move_register(&mut self.out, I::RETURN_REGISTER, I::ACCUMULATOR);
+                }
+                let label = self.return_label.clone();
+                I::jump(&mut self.out, &label);
+            }
+            Operand::DropVariable(name) => {
+                for scope in self.scopes.iter_mut().rev() {
+                    if scope.remove(name).is_some() {
+                        break;
+                    }
+                }
+            }
+            Operand::InlineAssembly(asm) => self.out.push(asm.clone()),
+            Operand::ExtendedAssembly(_) => {
+                eprintln!("Inline assembly with operands is only supported on x86_64");
+                panic!()
+            }
+            Operand::FunctionDecl(..) => {
+                eprintln!("Functions can't be declared inside other functions");
+                panic!()
+            }
+            Operand::Add(..) | Operand::Subtract(..) => {}
+        }
+    }
+
+    fn value_type(&self, value: &Value) -> Option<OperandType> {
+        match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                self.value_type(lhs).or(self.value_type(rhs))
+            }
+            Value::Reference(name) => Some(OperandType::Pointer(Box::new(self.lookup(name).1))),
+            Value::Dereference(name) => match self.lookup(name).1 {
+                OperandType::Pointer(inner) => Some(*inner),
+                _ => None,
+            },
+            Value::Variable(name) => Some(self.lookup(name).1),
+            Value::FunctionCall(name, _) => self.functions.get(name).map(|v| v.0.clone()),
+            Value::Char(_) => Some(OperandType::Char),
+            Value::StringLiteral(_) => Some(OperandType::Pointer(Box::new(OperandType::Char))),
+            Value::Int(_) | Value::Null => None,
+        }
+    }
+
+    fn call(&mut self, name: &str, parameters: &[Value]) {
+        let (_, types) = self
+            .functions
+            .get(name)
+            .cloned()
+            .expect("No Function Exists");
+        if parameters.len() > I::PARAMETER_REGISTERS.len() {
+            eprintln!("Too many arguments in call to {name}");
+            panic!()
+        }
+
+        for (value, ty) in parameters.iter().zip(&types) {
+            self.value(value);
+            I::extend(&mut self.out, I::ACCUMULATOR, ty.size(), ty.is_signed());
+            I::push(&mut self.out, I::ACCUMULATOR);
+        }
+        for i in (0..parameters.len()).rev() {
+            I::pop(&mut self.out, I::PARAMETER_REGISTERS[i]);
+        }
+        I::call(&mut self.out, name);
+        I::move_register(&mut self.out, I::ACCUMULATOR, I::RETURN_REGISTER);
+    }
+
+    /// Evaluates `value` into the accumulator
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                self.value(lhs);
+                I::push(&mut self.out, I::ACCUMULATOR);
+                self.value(rhs);
+                I::move_register(&mut self.out, I::SCRATCH, I::ACCUMULATOR);
+                I::pop(&mut self.out, I::ACCUMULATOR);
+                if matches!(value, Value::Add(..)) {
+                    I::add(&mut self.out, I::ACCUMULATOR, I::SCRATCH);
+                } else {
+                    I::sub(&mut self.out, I::ACCUMULATOR, I::SCRATCH);
+                }
+
+                let ty = self
+                    .value_type(value)
+                    .unwrap_or(OperandType::Int(Size::DoubleWord));
+                I::extend(&mut self.out, I::ACCUMULATOR, ty.size(), ty.is_signed());
+            }
+            Value::Reference(name) => {
+                let (offset, _) = self.lookup(name);
+                I::load_local_address(&mut self.out, I::ACCUMULATOR, offset);
+            }
+            Value::Dereference(name) => {
+                let (offset, ty) = self.lookup(name);
+                let OperandType::Pointer(inner) = ty else {
+                    panic!("Not a pointer")
+                };
+                I::load_local(&mut self.out, I::SCRATCH, offset, Size::QuadWord, false);
+                I::load_indirect(
+                    &mut self.out,
+                    I::ACCUMULATOR,
+                    I::SCRATCH,
+                    inner.size(),
+                    inner.is_signed(),
+                );
+            }
+            Value::Variable(name) => {
+                let (offset, ty) = self.lookup(name);
+                I::load_local(&mut self.out, I::ACCUMULATOR, offset, ty.size(), ty.is_signed());
+            }
+            Value::Char(c) => I::load_immediate(&mut self.out, I::ACCUMULATOR, *c as i64),
+            Value::Int(num) => {
+                let value = parse_int_literal(num)
+                    .unwrap_or_else(|| panic!("{num} is not a valid integer"));
+                I::load_immediate(&mut self.out, I::ACCUMULATOR, value);
+            }
+            Value::StringLiteral(literal) => {
+                let label = self.new_label("STR");
+                self.strings.push((label.clone(), literal.clone()));
+                I::load_symbol_address(&mut self.out, I::ACCUMULATOR, &label);
+            }
+            Value::FunctionCall(name, parameters) => self.call(name, parameters),
+            Value::Null => panic!(),
+        }
+    }
+}
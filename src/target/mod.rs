@@ -0,0 +1,135 @@
+mod lowering;
+pub(crate) use lowering::*;
+
+mod aarch64;
+pub use aarch64::*;
+
+mod riscv64;
+pub use riscv64::*;
+
+mod wasm;
+pub use wasm::*;
+
+use crate::*;
+
+/// Architecture the compiler generates code for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Target {
+    /// Lowered through [`Instruction`], honours [`AssemblySyntax`]
+    #[default]
+    X86_64,
+    /// Lowered through the [`Isa`] stack frame lowering, GNU `as` syntax
+    AArch64,
+    /// Lowered through the [`Isa`] stack frame lowering, GNU `as` syntax
+    RiscV64,
+    /// WebAssembly text through [`compile_wat`], arguments are passed as wasm parameters
+    Wasm32,
+}
+
+impl Target {
+    /// Registers used for the first arguments of a call, in order
+    pub fn parameter_registers(&self) -> Vec<String> {
+        match self {
+            Target::X86_64 => PARAMETER_REGISTERS
+                .iter()
+                .map(|v| v.as_qword())
+                .collect(),
+            Target::AArch64 => AArch64::PARAMETER_REGISTERS
+                .iter()
+                .map(|v| v.to_string())
+                .collect(),
+            Target::RiscV64 => RiscV64::PARAMETER_REGISTERS
+                .iter()
+                .map(|v| v.to_string())
+                .collect(),
+            Target::Wasm32 => vec![],
+        }
+    }
+
+    /// Empty for WebAssembly, which returns on the operand stack
+    pub fn return_register(&self) -> String {
+        match self {
+            Target::X86_64 => Register::AX.as_qword(),
+            Target::AArch64 => AArch64::RETURN_REGISTER.to_string(),
+            Target::RiscV64 => RiscV64::RETURN_REGISTER.to_string(),
+            Target::Wasm32 => String::new(),
+        }
+    }
+
+    /// Alignment of the stack pointer at call sites
+    pub fn stack_alignment(&self) -> u32 {
+        match self {
+            Target::X86_64 => 16,
+            Target::AArch64 => AArch64::STACK_ALIGNMENT,
+            Target::RiscV64 => RiscV64::STACK_ALIGNMENT,
+            Target::Wasm32 => 16,
+        }
+    }
+}
+
+/// Instruction selection for targets lowered by [`lower_module`].
+///
+/// Every variable lives in a stack slot addressed from the frame pointer, expressions are
+/// evaluated into [`Isa::ACCUMULATOR`] with intermediate results spilled to the stack, and
+/// values are kept sign or zero extended to 64 bits in registers.
+pub trait Isa {
+    /// Holds the result of every expression
+    const ACCUMULATOR: &'static str;
+    /// Holds the rhs of binary operations and addresses being dereferenced
+    const SCRATCH: &'static str;
+    const PARAMETER_REGISTERS: &'static [&'static str];
+    const RETURN_REGISTER: &'static str;
+    const STACK_ALIGNMENT: u32;
+
+    /// Prefix of labels that don't end up in the symbol table
+    fn local_label(name: &str) -> String {
+        format!(".L{name}")
+    }
+
+    /// Directives before any code, e.g. selecting the architecture
+    fn preamble() -> String {
+        String::new()
+    }
+
+    /// Sets up a frame of `frame_size` bytes below the frame pointer
+    fn prologue(out: &mut Vec<String>, frame_size: u32);
+    /// Tears the frame down and returns to the caller
+    fn epilogue(out: &mut Vec<String>);
+
+    fn load_immediate(out: &mut Vec<String>, dst: &str, value: i64);
+    fn load_symbol_address(out: &mut Vec<String>, dst: &str, symbol: &str);
+    /// Loads the address `offset` bytes below the frame pointer
+    fn load_local_address(out: &mut Vec<String>, dst: &str, offset: u32);
+    fn load_local(out: &mut Vec<String>, dst: &str, offset: u32, size: Size, signed: bool);
+    fn store_local(out: &mut Vec<String>, src: &str, offset: u32, size: Size);
+    fn load_indirect(out: &mut Vec<String>, dst: &str, address: &str, size: Size, signed: bool);
+    fn store_indirect(out: &mut Vec<String>, src: &str, address: &str, size: Size);
+
+    fn move_register(out: &mut Vec<String>, dst: &str, src: &str);
+    fn push(out: &mut Vec<String>, src: &str);
+    fn pop(out: &mut Vec<String>, dst: &str);
+
+    /// `dst = dst + rhs`
+    fn add(out: &mut Vec<String>, dst: &str, rhs: &str);
+    /// `dst = dst - rhs`
+    fn sub(out: &mut Vec<String>, dst: &str, rhs: &str);
+    /// Truncates `reg` to `size` and extends it back to 64 bits
+    fn extend(out: &mut Vec<String>, reg: &str, size: Size, signed: bool);
+
+    /// Branches to `label` when `lhs operation rhs` does not hold
+    fn branch_unless(
+        out: &mut Vec<String>,
+        operation: CompareOperation,
+        lhs: &str,
+        rhs: &str,
+        signed: bool,
+        label: &str,
+    );
+    fn jump(out: &mut Vec<String>, label: &str);
+    fn call(out: &mut Vec<String>, name: &str);
+
+    fn string_data(label: &str, value: &str) -> String {
+        let value = value.replace('"', "\\\"").replace('\n', "\\n");
+        format!("{label}:\n\t.asciz \"{value}\"\n")
+    }
+}
@@ -0,0 +1,186 @@
+use crate::*;
+
+/// RV64IM with the standard calling convention: arguments in a0-a7, s0 as the frame pointer
+pub struct RiscV64;
+
+/// Loads and stores take a signed 12 bit offset
+const MAX_IMMEDIATE_OFFSET: u32 = 2048;
+
+impl RiscV64 {
+    /// Address operand for a frame slot, going through `t2` when it is out of range
+    fn local(out: &mut Vec<String>, offset: u32) -> String {
+        if offset <= MAX_IMMEDIATE_OFFSET {
+            format!("-{offset}(s0)")
+        } else {
+            out.push(format!("li t2, {offset}"));
+            out.push("sub t2, s0, t2".to_string());
+            "0(t2)".to_string()
+        }
+    }
+
+    fn load_mnemonic(size: Size, signed: bool) -> &'static str {
+        match (size, signed) {
+            (Size::Byte, true) => "lb",
+            (Size::Byte, false) => "lbu",
+            (Size::Word, true) => "lh",
+            (Size::Word, false) => "lhu",
+            (Size::DoubleWord, true) => "lw",
+            (Size::DoubleWord, false) => "lwu",
+            (Size::QuadWord, _) => "ld",
+        }
+    }
+
+    fn store_mnemonic(size: Size) -> &'static str {
+        match size {
+            Size::Byte => "sb",
+            Size::Word => "sh",
+            Size::DoubleWord => "sw",
+            Size::QuadWord => "sd",
+        }
+    }
+
+    fn branch(operation: CompareOperation, signed: bool) -> &'static str {
+        match (operation, signed) {
+            (CompareOperation::GT, true) => "bgt",
+            (CompareOperation::GTE, true) => "bge",
+            (CompareOperation::LT, true) => "blt",
+            (CompareOperation::LTE, true) => "ble",
+            (CompareOperation::GT, false) => "bgtu",
+            (CompareOperation::GTE, false) => "bgeu",
+            (CompareOperation::LT, false) => "bltu",
+            (CompareOperation::LTE, false) => "bleu",
+            (CompareOperation::EQ, _) => "beq",
+            (CompareOperation::NEQ, _) => "bne",
+        }
+    }
+}
+
+impl Isa for RiscV64 {
+    const ACCUMULATOR: &'static str = "t0";
+    const SCRATCH: &'static str = "t1";
+    const PARAMETER_REGISTERS: &'static [&'static str] =
+        &["a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7"];
+    const RETURN_REGISTER: &'static str = "a0";
+    const STACK_ALIGNMENT: u32 = 16;
+
+    fn prologue(out: &mut Vec<String>, frame_size: u32) {
+        out.push("addi sp, sp, -16".to_string());
+        out.push("sd ra, 8(sp)".to_string());
+        out.push("sd s0, 0(sp)".to_string());
+        out.push("mv s0, sp".to_string());
+        if frame_size >= MAX_IMMEDIATE_OFFSET {
+            out.push(format!("li t2, {frame_size}"));
+            out.push("sub sp, sp, t2".to_string());
+        } else if frame_size > 0 {
+            out.push(format!("addi sp, sp, -{frame_size}"));
+        }
+    }
+
+    fn epilogue(out: &mut Vec<String>) {
+        out.push("mv sp, s0".to_string());
+        out.push("ld ra, 8(sp)".to_string());
+        out.push("ld s0, 0(sp)".to_string());
+        out.push("addi sp, sp, 16".to_string());
+        out.push("ret".to_string());
+    }
+
+    fn load_immediate(out: &mut Vec<String>, dst: &str, value: i64) {
+        out.push(format!("li {dst}, {value}"));
+    }
+
+    fn load_symbol_address(out: &mut Vec<String>, dst: &str, symbol: &str) {
+        out.push(format!("lla {dst}, {symbol}"));
+    }
+
+    fn load_local_address(out: &mut Vec<String>, dst: &str, offset: u32) {
+        if offset <= MAX_IMMEDIATE_OFFSET {
+            out.push(format!("addi {dst}, s0, -{offset}"));
+        } else {
+            out.push(format!("li {dst}, {offset}"));
+            out.push(format!("sub {dst}, s0, {dst}"));
+        }
+    }
+
+    fn load_local(out: &mut Vec<String>, dst: &str, offset: u32, size: Size, signed: bool) {
+        let address = Self::local(out, offset);
+        out.push(format!("{} {dst}, {address}", Self::load_mnemonic(size, signed)));
+    }
+
+    fn store_local(out: &mut Vec<String>, src: &str, offset: u32, size: Size) {
+        let address = Self::local(out, offset);
+        out.push(format!("{} {src}, {address}", Self::store_mnemonic(size)));
+    }
+
+    fn load_indirect(out: &mut Vec<String>, dst: &str, address: &str, size: Size, signed: bool) {
+        out.push(format!("{} {dst}, 0({address})", Self::load_mnemonic(size, signed)));
+    }
+
+    fn store_indirect(out: &mut Vec<String>, src: &str, address: &str, size: Size) {
+        out.push(format!("{} {src}, 0({address})", Self::store_mnemonic(size)));
+    }
+
+    fn move_register(out: &mut Vec<String>, dst: &str, src: &str) {
+        if dst != src {
+            out.push(format!("mv {dst}, {src}"));
+        }
+    }
+
+    fn push(out: &mut Vec<String>, src: &str) {
+        // SP has to stay 16 byte aligned
+        out.push("addi sp, sp, -16".to_string());
+        out.push(format!("sd {src}, 0(sp)"));
+    }
+
+    fn pop(out: &mut Vec<String>, dst: &str) {
+        out.push(format!("ld {dst}, 0(sp)"));
+        out.push("addi sp, sp, 16".to_string());
+    }
+
+    fn add(out: &mut Vec<String>, dst: &str, rhs: &str) {
+        out.push(format!("add {dst}, {dst}, {rhs}"));
+    }
+
+    fn sub(out: &mut Vec<String>, dst: &str, rhs: &str) {
+        out.push(format!("sub {dst}, {dst}, {rhs}"));
+    }
+
+    fn extend(out: &mut Vec<String>, reg: &str, size: Size, signed: bool) {
+        // RV64IM has no dedicated extension instructions besides sext.w
+        let shift = 64 - size.get_bytes() as u32 * 8;
+        match (size, signed) {
+            (Size::QuadWord, _) => {}
+            (Size::DoubleWord, true) => out.push(format!("sext.w {reg}, {reg}")),
+            (Size::Byte, false) => out.push(format!("andi {reg}, {reg}, 255")),
+            (_, true) => {
+                out.push(format!("slli {reg}, {reg}, {shift}"));
+                out.push(format!("srai {reg}, {reg}, {shift}"));
+            }
+            (_, false) => {
+                out.push(format!("slli {reg}, {reg}, {shift}"));
+                out.push(format!("srli {reg}, {reg}, {shift}"));
+            }
+        }
+    }
+
+    fn branch_unless(
+        out: &mut Vec<String>,
+        operation: CompareOperation,
+        lhs: &str,
+        rhs: &str,
+        signed: bool,
+        label: &str,
+    ) {
+        // Conditional branches only reach 4KiB, so hop over an unconditional jump instead
+        out.push(format!("{} {lhs}, {rhs}, 1f", Self::branch(operation, signed)));
+        out.push(format!("j {label}"));
+        out.push("1:".to_string());
+    }
+
+    fn jump(out: &mut Vec<String>, label: &str) {
+        out.push(format!("j {label}"));
+    }
+
+    fn call(out: &mut Vec<String>, name: &str) {
+        out.push(format!("call {name}"));
+    }
+}
@@ -0,0 +1,608 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::*;
+
+/// Where static data such as string literals starts in linear memory
+const DATA_START: u32 = 1024;
+const PAGE_SIZE: u32 = 65536;
+/// Pages reserved for the shadow stack that address-taken variables live on
+const STACK_PAGES: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ValType {
+    I32,
+    I64,
+}
+
+impl ValType {
+    fn of(ty: &OperandType) -> Self {
+        match ty {
+            // Linear memory addresses are 32 bits wide
+            OperandType::Pointer(_) => ValType::I32,
+            _ if ty.size() == Size::QuadWord => ValType::I64,
+            _ => ValType::I32,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+        }
+    }
+}
+
+fn memory_suffix(ty: &OperandType) -> String {
+    let sign = if ty.is_signed() { "s" } else { "u" };
+    match ty {
+        OperandType::Pointer(_) => String::new(),
+        _ => match ty.size() {
+            Size::Byte => format!("8_{sign}"),
+            Size::Word => format!("16_{sign}"),
+            Size::DoubleWord | Size::QuadWord => String::new(),
+        },
+    }
+}
+
+#[derive(Clone)]
+enum Storage {
+    Local(String),
+    /// Offset into the function's frame in linear memory
+    Memory(u32),
+}
+
+struct WasmFunction<'a> {
+    functions: &'a HashMap<String, (OperandType, Vec<OperandType>)>,
+    data: &'a mut Vec<(u32, String)>,
+    data_end: &'a mut u32,
+    address_taken: HashSet<String>,
+    scopes: Vec<HashMap<String, (Storage, OperandType)>>,
+    locals: Vec<(String, ValType)>,
+    frame_size: u32,
+    return_type: OperandType,
+    depth: usize,
+    out: Vec<String>,
+}
+
+/// Lowers a program to a WebAssembly text module.
+///
+/// Every function is exported, variables become locals unless their address is taken, in
+/// which case they live in a frame on a shadow stack in linear memory, and pointers are 32
+/// bit offsets into the exported memory.
+pub fn compile_wat(operands: &[Operand]) -> String {
+    compile_wat_with_externs(operands, &[])
+}
+
+/// Like [`compile_wat`], externs are imported from the `env` module
+pub fn compile_wat_with_externs(operands: &[Operand], externs: &[ExternFunction]) -> String {
+    let mut functions = HashMap::new();
+    let mut imports = String::new();
+    for function in externs {
+        functions.insert(
+            function.name.clone(),
+            (function.return_type.clone(), function.parameters.clone()),
+        );
+
+        let mut signature = String::new();
+        for ty in &function.parameters {
+            signature.push_str(&format!(" (param {})", ValType::of(ty).name()));
+        }
+        signature.push_str(&format!(" (result {})", ValType::of(&function.return_type).name()));
+        imports.push_str(&format!(
+            "  (import \"env\" \"{0}\" (func ${0}{signature}))\n",
+            function.name
+        ));
+    }
+    for operand in operands {
+        if let Operand::FunctionDecl(return_type, name, _, parameters, ..) = operand {
+            functions.insert(
+                name.clone(),
+                (return_type.clone(), parameters.iter().map(|v| v.1.clone()).collect()),
+            );
+        }
+    }
+
+    let mut data = vec![];
+    let mut data_end = DATA_START;
+    let mut funcs = String::new();
+
+    for operand in operands {
+        match operand {
+            Operand::FunctionDecl(return_type, name, body, parameters, ..) => {
+                let mut address_taken = HashSet::new();
+                find_references(body, &mut address_taken);
+
+                let function = WasmFunction {
+                    functions: &functions,
+                    data: &mut data,
+                    data_end: &mut data_end,
+                    address_taken,
+                    scopes: vec![],
+                    locals: vec![],
+                    frame_size: 0,
+                    return_type: return_type.clone(),
+                    depth: 2,
+                    out: vec![],
+                };
+                funcs.push_str(&function.function(name, body, parameters));
+            }
+            Operand::InlineAssembly(wat) => {
+                funcs.push_str(&format!("  {wat}\n"));
+            }
+            _ => {
+                eprintln!("{operand} is not allowed outside of a function");
+                panic!()
+            }
+        }
+    }
+
+    let stack_top = data_end.next_multiple_of(PAGE_SIZE) + STACK_PAGES * PAGE_SIZE;
+    let mut module = String::from("(module\n");
+    module.push_str(&imports);
+    module.push_str(&format!(
+        "  (memory (export \"memory\") {})\n",
+        stack_top / PAGE_SIZE
+    ));
+    module.push_str(&format!(
+        "  (global $__stack_pointer (mut i32) (i32.const {stack_top}))\n"
+    ));
+    for (address, string) in &data {
+        module.push_str(&format!("  (data (i32.const {address}) \"{}\\00\")\n", escape(string)));
+    }
+    module.push_str(&funcs);
+    module.push_str(")\n");
+    module
+}
+
+fn escape(string: &str) -> String {
+    string
+        .bytes()
+        .map(|b| match b {
+            b'"' | b'\\' => format!("\\{}", b as char),
+            0x20..=0x7e => (b as char).to_string(),
+            _ => format!("\\{b:02x}"),
+        })
+        .collect()
+}
+
+/// Variables that have their address taken need to live in memory
+fn find_references(body: &[Operand], found: &mut HashSet<String>) {
+    fn value(v: &Value, found: &mut HashSet<String>) {
+        match v {
+            Value::Reference(name) => {
+                found.insert(name.clone());
+            }
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                value(lhs, found);
+                value(rhs, found);
+            }
+            Value::FunctionCall(_, parameters) => parameters.iter().for_each(|v| value(v, found)),
+            _ => {}
+        }
+    }
+
+    for operand in body {
+        match operand {
+            Operand::DeclareVariable(_, _, v) | Operand::Return(v) => value(v, found),
+            Operand::SetValue(lhs, rhs) | Operand::Add(_, lhs, rhs) | Operand::Subtract(_, lhs, rhs) => {
+                value(lhs, found);
+                value(rhs, found);
+            }
+            Operand::FunctionCall(_, parameters) => parameters.iter().for_each(|v| value(v, found)),
+            Operand::If {
+                predicate,
+                main_body,
+            } => {
+                value(&predicate.lhs, found);
+                value(&predicate.rhs, found);
+                find_references(main_body, found);
+            }
+            Operand::Switch { scrutinee, .. } => {
+                value(scrutinee, found);
+                operand.bodies().into_iter().for_each(|v| find_references(v, found));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl WasmFunction<'_> {
+    fn emit(&mut self, line: impl AsRef<str>) {
+        self.out.push(format!("{}{}", "  ".repeat(self.depth), line.as_ref()));
+    }
+
+    fn lookup(&self, name: &str) -> (Storage, OperandType) {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|v| v.get(name))
+            .cloned()
+            .unwrap_or_else(|| panic!("Variable {name} does not exist."))
+    }
+
+    fn declare(&mut self, name: &str, ty: &OperandType) -> Storage {
+        let storage = if self.address_taken.contains(name) {
+            let bytes = ty.size().get_bytes() as u32;
+            self.frame_size = (self.frame_size + bytes).next_multiple_of(bytes);
+            Storage::Memory(self.frame_size)
+        } else {
+            // Shadowed and redeclared variables get a fresh local
+            let mut local = format!("${name}");
+            let mut i = 0;
+            while self.locals.iter().any(|v| v.0 == local) {
+                i += 1;
+                local = format!("${name}.{i}");
+            }
+            self.locals.push((local.clone(), ValType::of(ty)));
+            Storage::Local(local)
+        };
+
+        self.scopes
+            .last_mut()
+            .expect("Variables are only declared inside functions")
+            .insert(name.to_string(), (storage.clone(), ty.clone()));
+        storage
+    }
+
+    fn function(
+        mut self,
+        name: &str,
+        body: &[Operand],
+        parameters: &[(String, OperandType)],
+    ) -> String {
+        self.scopes.push(HashMap::new());
+
+        let mut params = vec![];
+        for (parameter, ty) in parameters {
+            let param = format!("$arg.{parameter}");
+            params.push(format!("(param {param} {})", ValType::of(ty).name()));
+            self.emit(format!("local.get {param}"));
+            let storage = self.declare(parameter, ty);
+            self.store_top(&storage, ty);
+        }
+
+        self.body(body);
+
+        // Falling off the end of a function returns zero
+        let result = ValType::of(&self.return_type);
+        self.emit(format!("{}.const 0", result.name()));
+        self.leave();
+        self.emit("return");
+
+        let frame = self.frame_size.next_multiple_of(16);
+        params.push(format!("(result {})", result.name()));
+        let mut function = format!("  (func ${name} (export \"{name}\") {}\n", params.join(" "));
+        function.push_str("    (local $__frame i32)\n");
+        for (local, ty) in &self.locals {
+            function.push_str(&format!("    (local {local} {})\n", ty.name()));
+        }
+        for line in [
+            "global.get $__stack_pointer",
+            "local.tee $__frame",
+            &format!("i32.const {frame}"),
+            "i32.sub",
+            "global.set $__stack_pointer",
+        ] {
+            function.push_str(&format!("    {line}\n"));
+        }
+        for line in &self.out {
+            function.push_str(line);
+            function.push('\n');
+        }
+        function.push_str("  )\n");
+        function
+    }
+
+    /// Pops the function's frame off the shadow stack
+    fn leave(&mut self) {
+        self.emit("local.get $__frame");
+        self.emit("global.set $__stack_pointer");
+    }
+
+    /// `$__frame` is the top of the frame, slots sit `offset` bytes below it
+    fn slot_address(&mut self, offset: u32) {
+        self.emit("local.get $__frame");
+        self.emit(format!("i32.const {offset}"));
+        self.emit("i32.sub");
+    }
+
+    /// A scratch local of the given type for shuffling the operand stack
+    fn scratch(&mut self, ty: ValType) -> String {
+        let local = format!("$__scratch.{}", ty.name());
+        if !self.locals.iter().any(|v| v.0 == local) {
+            self.locals.push((local.clone(), ty));
+        }
+        local
+    }
+
+    /// Stores the value on top of the operand stack
+    fn store_top(&mut self, storage: &Storage, ty: &OperandType) {
+        match storage {
+            Storage::Local(local) => self.emit(format!("local.set {local}")),
+            Storage::Memory(offset) => {
+                let scratch = self.scratch(ValType::of(ty));
+                self.emit(format!("local.set {scratch}"));
+                self.slot_address(*offset);
+                self.emit(format!("local.get {scratch}"));
+                self.store_instruction(ty);
+            }
+        }
+    }
+
+    fn body(&mut self, body: &[Operand]) {
+        for operand in body {
+            self.operand(operand);
+        }
+    }
+
+    fn store_instruction(&mut self, ty: &OperandType) {
+        let width = match ty {
+            OperandType::Pointer(_) => "",
+            _ => match ty.size() {
+                Size::Byte => "8",
+                Size::Word => "16",
+                Size::DoubleWord if ValType::of(ty) == ValType::I64 => "32",
+                _ => "",
+            },
+        };
+        self.emit(format!("{}.store{width}", ValType::of(ty).name()));
+    }
+
+    fn load_instruction(&mut self, ty: &OperandType) {
+        self.emit(format!("{}.load{}", ValType::of(ty).name(), memory_suffix(ty)));
+    }
+
+    fn operand(&mut self, operand: &Operand) {
+        match operand {
+            Operand::DeclareVariable(ty, name, value) => {
+                // Evaluate before declaring so the value can still see a shadowed variable
+                self.value(value, ty);
+                let storage = self.declare(name, ty);
+                self.store_top(&storage, ty);
+            }
+            Operand::SetValue(lhs, value) => match lhs {
+                Value::Variable(name) => {
+                    let (storage, ty) = self.lookup(name);
+                    self.value(value, &ty);
+                    self.store_top(&storage, &ty);
+                }
+                Value::Dereference(name) => {
+                    let (storage, ty) = self.lookup(name);
+                    let OperandType::Pointer(inner) = ty.clone() else {
+                        panic!("Not a pointer")
+                    };
+                    self.load(&storage, &ty);
+                    self.value(value, &inner);
+                    self.store_instruction(&inner);
+                }
+                _ => {
+                    eprintln!("Can't be lhs operand");
+                    panic!()
+                }
+            },
+            Operand::FunctionCall(name, parameters) => {
+                self.call(name, parameters);
+                self.emit("drop");
+            }
+            Operand::If {
+                predicate,
+                main_body,
+            } => {
+                let ty = self
+                    .value_type(&predicate.lhs)
+                    .or(self.value_type(&predicate.rhs))
+                    .unwrap_or(OperandType::Int(Size::DoubleWord));
+                self.value(&predicate.lhs, &ty);
+                self.value(&predicate.rhs, &ty);
+
+                let sign = if ty.is_signed() { "_s" } else { "_u" };
+                let comparison = match predicate.operation {
+                    CompareOperation::GT => format!("gt{sign}"),
+                    CompareOperation::GTE => format!("ge{sign}"),
+                    CompareOperation::LT => format!("lt{sign}"),
+                    CompareOperation::LTE => format!("le{sign}"),
+                    CompareOperation::EQ => "eq".to_string(),
+                    CompareOperation::NEQ => "ne".to_string(),
+                };
+                self.emit(format!("{}.{comparison}", ValType::of(&ty).name()));
+                self.emit("if");
+                self.depth += 1;
+                self.scopes.push(HashMap::new());
+                self.body(main_body);
+                self.scopes.pop();
+                self.depth -= 1;
+                self.emit("end");
+            }
+            Operand::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => {
+                let ty = self.value_type(scrutinee).unwrap_or(OperandType::Int(Size::DoubleWord));
+                let val = ValType::of(&ty).name();
+                // Cases are compared one after another, each leaves the block when it matches
+                self.emit("block");
+                self.depth += 1;
+                self.value(scrutinee, &ty);
+                let scratch = self.scratch(ValType::of(&ty));
+                self.emit(format!("local.set {scratch}"));
+                for (case, body) in cases {
+                    self.emit(format!("local.get {scratch}"));
+                    self.emit(format!("{val}.const {}", wrap_to_type(*case, &ty)));
+                    self.emit(format!("{val}.eq"));
+                    self.emit("if");
+                    self.depth += 1;
+                    self.scopes.push(HashMap::new());
+                    self.body(body);
+                    self.scopes.pop();
+                    self.emit("br 1");
+                    self.depth -= 1;
+                    self.emit("end");
+                }
+                self.scopes.push(HashMap::new());
+                self.body(default);
+                self.scopes.pop();
+                self.depth -= 1;
+                self.emit("end");
+            }
+            Operand::Return(value) => {
+                let ty = self.return_type.clone();
+                if *value == Value::Null {
+                    self.emit(format!("{}.const 0", ValType::of(&ty).name()));
+                } else {
+                    self.value(value, &ty);
+                }
+                self.leave();
+                self.emit("return");
+            }
+            Operand::DropVariable(name) => {
+                for scope in self.scopes.iter_mut().rev() {
+                    if scope.remove(name).is_some() {
+                        break;
+                    }
+                }
+            }
+            Operand::InlineAssembly(wat) => self.emit(wat),
+            Operand::ExtendedAssembly(_) => {
+                eprintln!("Inline assembly with operands is only supported on x86_64");
+                panic!()
+            }
+            Operand::FunctionDecl(..) => {
+                eprintln!("Functions can't be declared inside other functions");
+                panic!()
+            }
+            Operand::Add(..) | Operand::Subtract(..) => {}
+        }
+    }
+
+    fn load(&mut self, storage: &Storage, ty: &OperandType) {
+        match storage {
+            Storage::Local(local) => self.emit(format!("local.get {local}")),
+            Storage::Memory(offset) => {
+                self.slot_address(*offset);
+                self.load_instruction(ty);
+            }
+        }
+    }
+
+    fn value_type(&self, value: &Value) -> Option<OperandType> {
+        match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                self.value_type(lhs).or(self.value_type(rhs))
+            }
+            Value::Reference(name) => Some(OperandType::Pointer(Box::new(self.lookup(name).1))),
+            Value::Dereference(name) => match self.lookup(name).1 {
+                OperandType::Pointer(inner) => Some(*inner),
+                _ => None,
+            },
+            Value::Variable(name) => Some(self.lookup(name).1),
+            Value::FunctionCall(name, _) => self.functions.get(name).map(|v| v.0.clone()),
+            Value::Char(_) => Some(OperandType::Char),
+            Value::StringLiteral(_) => Some(OperandType::Pointer(Box::new(OperandType::Char))),
+            Value::Int(_) | Value::Null => None,
+        }
+    }
+
+    /// Converts the value on top of the stack from `from` to `to`
+    fn convert(&mut self, from: &OperandType, to: &OperandType) {
+        match (ValType::of(from), ValType::of(to)) {
+            (ValType::I64, ValType::I32) => self.emit("i32.wrap_i64"),
+            (ValType::I32, ValType::I64) if from.is_signed() => self.emit("i64.extend_i32_s"),
+            (ValType::I32, ValType::I64) => self.emit("i64.extend_i32_u"),
+            _ => {}
+        }
+        self.normalize(to);
+    }
+
+    /// Keeps values narrower than their `ValType` sign or zero extended
+    fn normalize(&mut self, ty: &OperandType) {
+        if matches!(ty, OperandType::Pointer(_)) {
+            return;
+        }
+        let val = ValType::of(ty).name();
+        match (ty.size(), ty.is_signed()) {
+            (Size::Byte, true) => self.emit(format!("{val}.extend8_s")),
+            (Size::Word, true) => self.emit(format!("{val}.extend16_s")),
+            (Size::DoubleWord, true) if ValType::of(ty) == ValType::I64 => {
+                self.emit("i64.extend32_s")
+            }
+            (Size::Byte, false) => {
+                self.emit(format!("{val}.const 255"));
+                self.emit(format!("{val}.and"));
+            }
+            (Size::Word, false) => {
+                self.emit(format!("{val}.const 65535"));
+                self.emit(format!("{val}.and"));
+            }
+            _ => {}
+        }
+    }
+
+    fn call(&mut self, name: &str, parameters: &[Value]) {
+        let (_, types) = self
+            .functions
+            .get(name)
+            .cloned()
+            .expect("No Function Exists");
+        for (value, ty) in parameters.iter().zip(&types) {
+            self.value(value, ty);
+        }
+        self.emit(format!("call ${name}"));
+    }
+
+    /// Pushes `value` converted to the type `ty`
+    fn value(&mut self, value: &Value, ty: &OperandType) {
+        let val = ValType::of(ty).name();
+        match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                let own = self.value_type(value).unwrap_or(ty.clone());
+                self.value(lhs, &own);
+                self.value(rhs, &own);
+                let op = if matches!(value, Value::Add(..)) { "add" } else { "sub" };
+                self.emit(format!("{}.{op}", ValType::of(&own).name()));
+                self.normalize(&own);
+                self.convert(&own, ty);
+            }
+            Value::Reference(name) => {
+                let Storage::Memory(offset) = self.lookup(name).0 else {
+                    unreachable!("Referenced variables are always in memory")
+                };
+                self.slot_address(offset);
+                self.convert(&OperandType::Pointer(Box::new(OperandType::Undefined)), ty);
+            }
+            Value::Dereference(name) => {
+                let (storage, pointer) = self.lookup(name);
+                let OperandType::Pointer(inner) = pointer.clone() else {
+                    panic!("Not a pointer")
+                };
+                self.load(&storage, &pointer);
+                self.load_instruction(&inner);
+                self.convert(&inner, ty);
+            }
+            Value::Variable(name) => {
+                let (storage, own) = self.lookup(name);
+                self.load(&storage, &own);
+                self.convert(&own, ty);
+            }
+            Value::Char(c) => self.emit(format!("{val}.const {}", *c as u32)),
+            Value::Int(num) => {
+                let value = parse_int_literal(num)
+                    .unwrap_or_else(|| panic!("{num} is not a valid integer"));
+                self.emit(format!("{val}.const {value}"));
+                self.normalize(ty);
+            }
+            Value::StringLiteral(literal) => {
+                let address = *self.data_end;
+                *self.data_end += literal.len() as u32 + 1;
+                self.data.push((address, literal.clone()));
+                self.emit(format!("{val}.const {address}"));
+            }
+            Value::FunctionCall(name, parameters) => {
+                let own = self.functions.get(name).expect("No Function Exists").0.clone();
+                self.call(name, parameters);
+                self.convert(&own, ty);
+            }
+            Value::Null => panic!(),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    Ident(String),
+    /// An identifier written as `@"..."`, never treated as a keyword
+    RawIdent(String),
+    Number(String),
+    Str(String),
+    Char(char),
+    Punct(&'static str),
+    Eof,
+}
+
+impl Token {
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            Token::Ident(name) | Token::RawIdent(name) => format!("identifier `{name}`"),
+            Token::Number(num) => format!("number `{num}`"),
+            Token::Str(_) => "string literal".to_string(),
+            Token::Char(_) => "character literal".to_string(),
+            Token::Punct(p) => format!("`{p}`"),
+            Token::Eof => "end of input".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Spanned {
+    pub token: Token,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Longest punctuation first so `>=` wins over `>`
+const PUNCTUATION: &[&str] = &[
+    ">=", "<=", "==", "!=", "(", ")", "{", "}", ",", ";", ":", "=", "+", "-", "&", "*", ">", "<",
+];
+
+pub(crate) fn tokenize(source: &str) -> Result<Vec<Spanned>, ParseError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+    let mut line = 1;
+    let mut column = 1;
+
+    macro_rules! bump {
+        () => {{
+            if chars[i] == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+            i += 1;
+        }};
+    }
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            bump!();
+            continue;
+        }
+
+        if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                bump!();
+            }
+            continue;
+        }
+
+        let (start_line, start_column) = (line, column);
+        let error = |message: String| ParseError::new(start_line, start_column, message);
+
+        let token = if c.is_ascii_alphabetic() || c == '_' || c == '.' {
+            let mut ident = String::new();
+            while i < chars.len() && is_ident_char(chars[i]) {
+                ident.push(chars[i]);
+                bump!();
+            }
+            Token::Ident(ident)
+        } else if c.is_ascii_digit() {
+            let mut num = String::new();
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                num.push(chars[i]);
+                bump!();
+            }
+            Token::Number(num)
+        } else if c == '"' || (c == '@' && chars.get(i + 1) == Some(&'"')) {
+            let raw = c == '@';
+            if raw {
+                bump!();
+            }
+            bump!();
+            let mut string = String::new();
+            loop {
+                match chars.get(i) {
+                    None => return Err(error("Unterminated string literal".to_string())),
+                    Some('"') => {
+                        bump!();
+                        break;
+                    }
+                    Some('\\') => {
+                        bump!();
+                        let escaped = chars.get(i).copied().ok_or_else(|| {
+                            error("Unterminated string literal".to_string())
+                        })?;
+                        string.push(unescape(escaped).ok_or_else(|| {
+                            ParseError::new(line, column, format!("Unknown escape `\\{escaped}`"))
+                        })?);
+                        bump!();
+                    }
+                    Some(&other) => {
+                        string.push(other);
+                        bump!();
+                    }
+                }
+            }
+            if raw {
+                Token::RawIdent(string)
+            } else {
+                Token::Str(string)
+            }
+        } else if c == '\'' {
+            bump!();
+            let value = match chars.get(i) {
+                None => return Err(error("Unterminated character literal".to_string())),
+                Some('\\') => {
+                    bump!();
+                    let escaped = chars.get(i).copied().ok_or_else(|| {
+                        error("Unterminated character literal".to_string())
+                    })?;
+                    let value = unescape(escaped).ok_or_else(|| {
+                        ParseError::new(line, column, format!("Unknown escape `\\{escaped}`"))
+                    })?;
+                    bump!();
+                    value
+                }
+                Some(&other) => {
+                    bump!();
+                    other
+                }
+            };
+            if chars.get(i) != Some(&'\'') {
+                return Err(error("Expected `'` to close character literal".to_string()));
+            }
+            bump!();
+            Token::Char(value)
+        } else if let Some(punct) = PUNCTUATION
+            .iter()
+            .find(|p| p.chars().enumerate().all(|(j, pc)| chars.get(i + j) == Some(&pc)))
+        {
+            for _ in 0..punct.len() {
+                bump!();
+            }
+            Token::Punct(punct)
+        } else {
+            return Err(error(format!("Unexpected character `{c}`")));
+        };
+
+        tokens.push(Spanned {
+            token,
+            line: start_line,
+            column: start_column,
+        });
+    }
+
+    tokens.push(Spanned {
+        token: Token::Eof,
+        line,
+        column,
+    });
+
+    Ok(tokens)
+}
+
+pub(crate) fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+fn unescape(c: char) -> Option<char> {
+    match c {
+        'n' => Some('\n'),
+        't' => Some('\t'),
+        'r' => Some('\r'),
+        '0' => Some('\0'),
+        '\\' => Some('\\'),
+        '"' => Some('"'),
+        '\'' => Some('\''),
+        _ => None,
+    }
+}
+
+pub(crate) fn escape(c: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        '\0' => "\\0".to_string(),
+        '\\' => "\\\\".to_string(),
+        '"' => "\\\"".to_string(),
+        '\'' => "\\'".to_string(),
+        _ => c.to_string(),
+    }
+}
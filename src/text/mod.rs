@@ -0,0 +1,54 @@
+//! Textual form of the IR, handy for test fixtures and debug dumps.
+//!
+//! ```text
+//! fn i32 add(i32 a, i32 b) {
+//!     let i32 sum = a + b;
+//!     if sum > 10 {
+//!         asm "nop";
+//!     }
+//!     return sum;
+//! }
+//! ```
+//!
+//! Identifiers that clash with a keyword or contain other characters are written as `@"name"`,
+//! and integers that are not plain numerals as `int "text"`.
+//! Function declarations can start with `inline` or `noinline` to set their [`InlineHint`](crate::InlineHint).
+//! `asm` takes GCC style operands after the template, `asm "add %0, %1" : "+r"(sum) : "r"(a) : "rcx";`
+//! is an [`ExtendedAssembly`](crate::ExtendedAssembly).
+//! `switch x { case 1 { ... } case -2 { ... } default { ... } }` takes integer cases, the
+//! default is optional.
+
+mod lexer;
+
+mod parser;
+pub use parser::*;
+
+mod printer;
+pub use printer::*;
+
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(line: usize, column: usize, message: String) -> Self {
+        Self {
+            line,
+            column,
+            message,
+        }
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -0,0 +1,414 @@
+use super::lexer::{tokenize, Spanned, Token};
+use crate::*;
+
+struct Parser {
+    tokens: Vec<Spanned>,
+    position: usize,
+}
+
+/// Parses the textual IR into a list of top level operands
+pub fn parse_ir(source: &str) -> Result<Vec<Operand>, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+
+    let mut operands = vec![];
+    while parser.peek() != &Token::Eof {
+        operands.push(parser.operand()?);
+    }
+    Ok(operands)
+}
+
+/// Parses a single value expression, e.g. `a + f(b, 2)`
+pub fn parse_value(source: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+
+    let value = parser.value()?;
+    parser.expect_eof()?;
+    Ok(value)
+}
+
+/// Parses a single type, e.g. `*i32`
+pub fn parse_type(source: &str) -> Result<OperandType, ParseError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+    };
+
+    let ty = parser.operand_type()?;
+    parser.expect_eof()?;
+    Ok(ty)
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.position].token
+    }
+
+    fn peek_nth(&self, n: usize) -> &Token {
+        let index = (self.position + n).min(self.tokens.len() - 1);
+        &self.tokens[index].token
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.position].token.clone();
+        if self.position < self.tokens.len() - 1 {
+            self.position += 1;
+        }
+        token
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, ParseError> {
+        let Spanned { line, column, .. } = self.tokens[self.position];
+        Err(ParseError::new(line, column, message))
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, ParseError> {
+        self.error(format!("Expected {expected}, found {}", self.peek().describe()))
+    }
+
+    fn is_punct(&self, punct: &str) -> bool {
+        matches!(self.peek(), Token::Punct(p) if *p == punct)
+    }
+
+    fn expect_punct(&mut self, punct: &str) -> Result<(), ParseError> {
+        if !self.is_punct(punct) {
+            return self.unexpected(&format!("`{punct}`"));
+        }
+        self.next();
+        Ok(())
+    }
+
+    fn expect_eof(&mut self) -> Result<(), ParseError> {
+        if self.peek() != &Token::Eof {
+            return self.unexpected("end of input");
+        }
+        Ok(())
+    }
+
+    fn ident(&mut self) -> Result<String, ParseError> {
+        match self.peek().clone() {
+            Token::Ident(name) | Token::RawIdent(name) => {
+                self.next();
+                Ok(name)
+            }
+            _ => self.unexpected("identifier"),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        match self.peek().clone() {
+            Token::Str(string) => {
+                self.next();
+                Ok(string)
+            }
+            _ => self.unexpected("string literal"),
+        }
+    }
+
+    fn body(&mut self) -> Result<Vec<Operand>, ParseError> {
+        self.expect_punct("{")?;
+        let mut body = vec![];
+        while !self.is_punct("}") {
+            if self.peek() == &Token::Eof {
+                return self.unexpected("`}`");
+            }
+            body.push(self.operand()?);
+        }
+        self.next();
+        Ok(body)
+    }
+
+    fn operand(&mut self) -> Result<Operand, ParseError> {
+        let keyword = match self.peek() {
+            Token::Ident(name) => name.clone(),
+            _ => String::new(),
+        };
+
+        let operand = match keyword.as_str() {
+            "inline" | "noinline" | "fn" => {
+                let hint = match keyword.as_str() {
+                    "inline" => InlineHint::Always,
+                    "noinline" => InlineHint::Never,
+                    _ => InlineHint::Auto,
+                };
+                if hint != InlineHint::Auto {
+                    self.next();
+                    if self.peek() != &Token::Ident("fn".to_string()) {
+                        return self.unexpected("`fn`");
+                    }
+                }
+                self.next();
+                let return_type = self.operand_type()?;
+                let name = self.ident()?;
+                self.expect_punct("(")?;
+                let mut parameters = vec![];
+                while !self.is_punct(")") {
+                    let ty = self.operand_type()?;
+                    let name = self.ident()?;
+                    parameters.push((name, ty));
+                    if !self.is_punct(",") {
+                        break;
+                    }
+                    self.next();
+                }
+                self.expect_punct(")")?;
+                let body = self.body()?;
+                return Ok(Operand::FunctionDecl(return_type, name, body, parameters, hint));
+            }
+            "if" => {
+                self.next();
+                let lhs = self.value()?;
+                let operation = match self.peek() {
+                    Token::Punct(">") => CompareOperation::GT,
+                    Token::Punct(">=") => CompareOperation::GTE,
+                    Token::Punct("<") => CompareOperation::LT,
+                    Token::Punct("<=") => CompareOperation::LTE,
+                    Token::Punct("==") => CompareOperation::EQ,
+                    Token::Punct("!=") => CompareOperation::NEQ,
+                    _ => return self.unexpected("comparison operator"),
+                };
+                self.next();
+                let rhs = self.value()?;
+                let main_body = self.body()?;
+                return Ok(Operand::If {
+                    predicate: ComparePredicate { operation, lhs, rhs },
+                    main_body,
+                });
+            }
+            "switch" => {
+                self.next();
+                let scrutinee = self.value()?;
+                self.expect_punct("{")?;
+                let mut cases = vec![];
+                let mut default = None;
+                while !self.is_punct("}") {
+                    match self.peek() {
+                        Token::Ident(name) if name == "case" => {
+                            self.next();
+                            let case = self.case_value()?;
+                            cases.push((case, self.body()?));
+                        }
+                        Token::Ident(name) if name == "default" && default.is_none() => {
+                            self.next();
+                            default = Some(self.body()?);
+                        }
+                        _ => return self.unexpected("`case` or `default`"),
+                    }
+                }
+                self.next();
+                return Ok(Operand::Switch {
+                    scrutinee,
+                    cases,
+                    default: default.unwrap_or_default(),
+                });
+            }
+            "let" => {
+                self.next();
+                let ty = self.operand_type()?;
+                let name = self.ident()?;
+                self.expect_punct("=")?;
+                let value = self.value()?;
+                Operand::DeclareVariable(ty, name, value)
+            }
+            "add" | "sub" => {
+                self.next();
+                let ty = self.operand_type()?;
+                let lhs = self.value()?;
+                self.expect_punct(",")?;
+                let rhs = self.value()?;
+                if keyword == "add" {
+                    Operand::Add(ty, lhs, rhs)
+                } else {
+                    Operand::Subtract(ty, lhs, rhs)
+                }
+            }
+            "drop" => {
+                self.next();
+                Operand::DropVariable(self.ident()?)
+            }
+            "return" => {
+                self.next();
+                if self.is_punct(";") {
+                    Operand::Return(Value::Null)
+                } else {
+                    Operand::Return(self.value()?)
+                }
+            }
+            "asm" => {
+                self.next();
+                let template = self.string()?;
+                if !self.is_punct(":") {
+                    Operand::InlineAssembly(template)
+                } else {
+                    let mut asm = ExtendedAssembly::new(&template);
+                    self.next();
+                    asm.outputs = self.asm_operands()?;
+                    if self.is_punct(":") {
+                        self.next();
+                        asm.inputs = self.asm_operands()?;
+                    }
+                    if self.is_punct(":") {
+                        self.next();
+                        while let Token::Str(_) = self.peek() {
+                            asm.clobbers.push(self.string()?);
+                            if !self.is_punct(",") {
+                                break;
+                            }
+                            self.next();
+                        }
+                    }
+                    Operand::ExtendedAssembly(asm)
+                }
+            }
+            _ => {
+                let value = self.value()?;
+                if self.is_punct("=") {
+                    self.next();
+                    Operand::SetValue(value, self.value()?)
+                } else if let Value::FunctionCall(name, parameters) = value {
+                    Operand::FunctionCall(name, parameters)
+                } else {
+                    return self.unexpected("`=`");
+                }
+            }
+        };
+
+        self.expect_punct(";")?;
+        Ok(operand)
+    }
+
+    /// An integer literal, optionally negative
+    fn case_value(&mut self) -> Result<i64, ParseError> {
+        let negative = self.is_punct("-");
+        if negative {
+            self.next();
+        }
+        let Token::Number(num) = self.peek().clone() else {
+            return self.unexpected("integer");
+        };
+        let num = if negative { format!("-{num}") } else { num };
+        let Some(value) = parse_int_literal(&num) else {
+            return self.error(format!("{num} is not a valid integer"));
+        };
+        self.next();
+        Ok(value)
+    }
+
+    /// `"constraint"(value)` pairs separated by commas
+    fn asm_operands(&mut self) -> Result<Vec<AsmOperand>, ParseError> {
+        let mut operands = vec![];
+        while let Token::Str(_) = self.peek() {
+            let constraint = self.string()?;
+            self.expect_punct("(")?;
+            let value = self.value()?;
+            self.expect_punct(")")?;
+            operands.push(AsmOperand { constraint, value });
+            if !self.is_punct(",") {
+                break;
+            }
+            self.next();
+        }
+        Ok(operands)
+    }
+
+    fn operand_type(&mut self) -> Result<OperandType, ParseError> {
+        if self.is_punct("*") {
+            self.next();
+            return Ok(OperandType::Pointer(Box::new(self.operand_type()?)));
+        }
+
+        let ty = match self.peek() {
+            Token::Ident(name) => match name.as_str() {
+                "undefined" => OperandType::Undefined,
+                "char" => OperandType::Char,
+                "i8" => OperandType::Int(Size::Byte),
+                "i16" => OperandType::Int(Size::Word),
+                "i32" => OperandType::Int(Size::DoubleWord),
+                "i64" => OperandType::Int(Size::QuadWord),
+                "u8" => OperandType::UInt(Size::Byte),
+                "u16" => OperandType::UInt(Size::Word),
+                "u32" => OperandType::UInt(Size::DoubleWord),
+                "u64" => OperandType::UInt(Size::QuadWord),
+                _ => return self.unexpected("type"),
+            },
+            _ => return self.unexpected("type"),
+        };
+        self.next();
+        Ok(ty)
+    }
+
+    fn value(&mut self) -> Result<Value, ParseError> {
+        let mut lhs = self.primary()?;
+        loop {
+            if self.is_punct("+") {
+                self.next();
+                lhs = Value::Add(Box::new(lhs), Box::new(self.primary()?));
+            } else if self.is_punct("-") {
+                self.next();
+                lhs = Value::Sub(Box::new(lhs), Box::new(self.primary()?));
+            } else {
+                return Ok(lhs);
+            }
+        }
+    }
+
+    fn primary(&mut self) -> Result<Value, ParseError> {
+        let value = match self.peek().clone() {
+            Token::Punct("(") => {
+                self.next();
+                let value = self.value()?;
+                self.expect_punct(")")?;
+                return Ok(value);
+            }
+            Token::Punct("&") => {
+                self.next();
+                return Ok(Value::Reference(self.ident()?));
+            }
+            Token::Punct("*") => {
+                self.next();
+                return Ok(Value::Dereference(self.ident()?));
+            }
+            Token::Punct("-") => {
+                if let Token::Number(num) = self.peek_nth(1).clone() {
+                    self.next();
+                    Value::Int(format!("-{num}"))
+                } else {
+                    return self.unexpected("value");
+                }
+            }
+            Token::Number(num) => Value::Int(num),
+            Token::Char(c) => Value::Char(c),
+            Token::Str(string) => Value::StringLiteral(string),
+            Token::Ident(name) if name == "null" => Value::Null,
+            Token::Ident(name) if name == "int" => {
+                self.next();
+                return Ok(Value::Int(self.string()?));
+            }
+            Token::Ident(name) | Token::RawIdent(name) => {
+                self.next();
+                if !self.is_punct("(") {
+                    return Ok(Value::Variable(name));
+                }
+                self.next();
+                let mut parameters = vec![];
+                while !self.is_punct(")") {
+                    parameters.push(self.value()?);
+                    if !self.is_punct(",") {
+                        break;
+                    }
+                    self.next();
+                }
+                self.expect_punct(")")?;
+                return Ok(Value::FunctionCall(name, parameters));
+            }
+            _ => return self.unexpected("value"),
+        };
+        self.next();
+        Ok(value)
+    }
+}
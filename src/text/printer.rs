@@ -0,0 +1,203 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+
+use super::lexer::{escape, is_ident_char};
+use crate::*;
+
+const KEYWORDS: &[&str] = &[
+    "fn", "inline", "noinline", "if", "switch", "case", "default", "let", "add", "sub", "drop", "return", "asm", "null", "int", "undefined", "char",
+    "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64",
+];
+
+const INDENT: &str = "    ";
+
+/// Prints operands in the textual IR format, the output can be read back with [`parse_ir`]
+pub fn print_ir(operands: &[Operand]) -> String {
+    let mut buffer = String::new();
+    for operand in operands {
+        print_operand(operand, 0, &mut buffer);
+    }
+    buffer
+}
+
+fn print_body(body: &[Operand], depth: usize, buffer: &mut String) {
+    buffer.push_str("{\n");
+    for operand in body {
+        print_operand(operand, depth + 1, buffer);
+    }
+    buffer.push_str(&INDENT.repeat(depth));
+    buffer.push('}');
+}
+
+fn print_operand(operand: &Operand, depth: usize, buffer: &mut String) {
+    buffer.push_str(&INDENT.repeat(depth));
+    let line = match operand {
+        Operand::FunctionDecl(return_type, name, body, parameters, hint) => {
+            let parameters = parameters
+                .iter()
+                .map(|(name, ty)| format!("{ty} {}", ident(name)))
+                .collect::<Vec<String>>()
+                .join(", ");
+            match hint {
+                InlineHint::Auto => {}
+                InlineHint::Always => buffer.push_str("inline "),
+                InlineHint::Never => buffer.push_str("noinline "),
+            }
+            buffer.push_str(&format!("fn {return_type} {}({parameters}) ", ident(name)));
+            print_body(body, depth, buffer);
+            buffer.push('\n');
+            return;
+        }
+        Operand::If { predicate, main_body } => {
+            buffer.push_str(&format!("if {predicate} "));
+            print_body(main_body, depth, buffer);
+            buffer.push('\n');
+            return;
+        }
+        Operand::Switch { scrutinee, cases, default } => {
+            buffer.push_str(&format!("switch {scrutinee} {{\n"));
+            let indent = INDENT.repeat(depth + 1);
+            for (case, body) in cases {
+                buffer.push_str(&format!("{indent}case {case} "));
+                print_body(body, depth + 1, buffer);
+                buffer.push('\n');
+            }
+            if !default.is_empty() {
+                buffer.push_str(&format!("{indent}default "));
+                print_body(default, depth + 1, buffer);
+                buffer.push('\n');
+            }
+            buffer.push_str(&INDENT.repeat(depth));
+            buffer.push_str("}\n");
+            return;
+        }
+        Operand::DeclareVariable(ty, name, value) => format!("let {ty} {} = {value};", ident(name)),
+        Operand::Add(ty, lhs, rhs) => format!("add {ty} {lhs}, {rhs};"),
+        Operand::Subtract(ty, lhs, rhs) => format!("sub {ty} {lhs}, {rhs};"),
+        Operand::SetValue(lhs, value) => format!("{lhs} = {value};"),
+        Operand::DropVariable(name) => format!("drop {};", ident(name)),
+        Operand::FunctionCall(name, parameters) => {
+            format!("{};", Value::FunctionCall(name.clone(), parameters.clone()))
+        }
+        Operand::Return(Value::Null) => "return;".to_string(),
+        Operand::Return(value) => format!("return {value};"),
+        Operand::InlineAssembly(asm) => format!("asm {};", string(asm)),
+        Operand::ExtendedAssembly(asm) => {
+            let operands = |operands: &[AsmOperand]| {
+                operands
+                    .iter()
+                    .map(|v| format!("{}({})", string(&v.constraint), v.value))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            };
+            let clobbers = asm.clobbers.iter().map(|v| string(v)).collect::<Vec<String>>().join(", ");
+            let mut sections = vec![operands(&asm.outputs), operands(&asm.inputs), clobbers];
+            // Trailing empty sections can be left out, the first one marks the operands
+            while sections.len() > 1 && sections.last().is_some_and(|v| v.is_empty()) {
+                sections.pop();
+            }
+            let sections = sections
+                .into_iter()
+                .map(|v| if v.is_empty() { " :".to_string() } else { format!(" : {v}") })
+                .collect::<String>();
+            format!("asm {}{sections};", string(&asm.template))
+        }
+    };
+    buffer.push_str(&line);
+    buffer.push('\n');
+}
+
+/// Quotes identifiers that would otherwise lex as a keyword or not at all
+fn ident(name: &str) -> String {
+    let plain = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && name.chars().all(is_ident_char)
+        && !KEYWORDS.contains(&name);
+
+    if plain {
+        name.to_string()
+    } else {
+        format!("@{}", string(name))
+    }
+}
+
+fn string(literal: &str) -> String {
+    format!("\"{}\"", literal.chars().map(escape).collect::<String>())
+}
+
+fn is_number(num: &str) -> bool {
+    let digits = num.strip_prefix('-').unwrap_or(num);
+    digits.starts_with(|c: char| c.is_ascii_digit())
+        && digits.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl Display for OperandType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let size = |size: &Size| size.get_bytes() as u32 * 8;
+        match self {
+            OperandType::Undefined => write!(f, "undefined"),
+            OperandType::Int(s) => write!(f, "i{}", size(s)),
+            OperandType::UInt(s) => write!(f, "u{}", size(s)),
+            OperandType::Char => write!(f, "char"),
+            OperandType::Pointer(inner) => write!(f, "*{inner}"),
+        }
+    }
+}
+
+impl Display for CompareOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str(match self {
+            CompareOperation::GT => ">",
+            CompareOperation::GTE => ">=",
+            CompareOperation::LT => "<",
+            CompareOperation::LTE => "<=",
+            CompareOperation::EQ => "==",
+            CompareOperation::NEQ => "!=",
+        })
+    }
+}
+
+impl Display for ComparePredicate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{} {} {}", self.lhs, self.operation, self.rhs)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                let op = if matches!(self, Value::Add(..)) { '+' } else { '-' };
+                write!(f, "{lhs} {op} ")?;
+                // Addition and subtraction are left associative, so a nested rhs needs brackets
+                if matches!(**rhs, Value::Add(..) | Value::Sub(..)) {
+                    write!(f, "({rhs})")
+                } else {
+                    write!(f, "{rhs}")
+                }
+            }
+            Value::Reference(name) => write!(f, "&{}", ident(name)),
+            Value::Dereference(name) => write!(f, "*{}", ident(name)),
+            Value::Variable(name) => f.write_str(&ident(name)),
+            Value::Char(c) => write!(f, "'{}'", escape(*c)),
+            Value::Int(num) if is_number(num) => f.write_str(num),
+            Value::Int(num) => write!(f, "int {}", string(num)),
+            Value::StringLiteral(literal) => f.write_str(&string(literal)),
+            Value::FunctionCall(name, parameters) => {
+                let parameters = parameters
+                    .iter()
+                    .map(|v| v.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                write!(f, "{}({parameters})", ident(name))
+            }
+            Value::Null => f.write_str("null"),
+        }
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let mut buffer = String::new();
+        print_operand(self, 0, &mut buffer);
+        f.write_str(buffer.trim_end())
+    }
+}
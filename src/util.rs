@@ -1,5 +1,10 @@
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Size {
     Byte = 1,       // 8
     Word = 2,       // 16
@@ -27,3 +32,24 @@ impl Size {
         }
     }
 }
+
+/// Reads a `Value::Int` numeral, accepting decimal and `0x`/`0b`/`0o` prefixed literals
+pub fn parse_int_literal(num: &str) -> Option<i64> {
+    let (negative, digits) = match num.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, num),
+    };
+    let digits = digits.replace('_', "");
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()? as i64
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        u64::from_str_radix(binary, 2).ok()? as i64
+    } else if let Some(octal) = digits.strip_prefix("0o") {
+        u64::from_str_radix(octal, 8).ok()? as i64
+    } else {
+        digits.parse::<u64>().ok()? as i64
+    };
+
+    Some(if negative { value.wrapping_neg() } else { value })
+}
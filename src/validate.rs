@@ -0,0 +1,362 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+
+use crate::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// The function the problem was found in, `None` for the top level
+    pub function: Option<String>,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.function {
+            Some(function) => write!(f, "in function {function}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+struct Validator {
+    functions: FunctionManager,
+    /// Innermost scope is last, lookups fall back to the enclosing ones
+    scopes: Vec<HashMap<String, OperandType>>,
+    function: Option<(String, OperandType)>,
+    /// Variables of the current function that were dropped and not declared again
+    dropped: HashSet<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// Checks a program for everything that would otherwise panic or produce broken assembly
+/// during codegen, returning every problem found
+pub fn validate(operands: &[Operand]) -> Vec<Diagnostic> {
+    validate_with_externs(operands, &[])
+}
+
+/// Like [`validate`], allowing calls to functions defined outside of the IR
+pub fn validate_with_externs(operands: &[Operand], externs: &[ExternFunction]) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        functions: FunctionManager::new(),
+        scopes: vec![],
+        function: None,
+        dropped: HashSet::new(),
+        diagnostics: vec![],
+    };
+
+    for function in externs {
+        validator
+            .functions
+            .declare_function(&function.name, &function.return_type, &function.parameters);
+    }
+
+    for operand in operands {
+        if let Operand::FunctionDecl(return_type, name, _, parameters, ..) = operand {
+            if validator.functions.get_function_type(name).is_some() {
+                validator.error(format!("Function {name} is declared more than once"));
+            }
+            validator.functions.declare_function(
+                name,
+                return_type,
+                &parameters.iter().map(|v| v.1.clone()).collect::<Vec<OperandType>>(),
+            );
+        }
+    }
+
+    for operand in operands {
+        match operand {
+            Operand::FunctionDecl(..) => validator.operand(operand),
+            Operand::InlineAssembly(_) => {}
+            _ => validator.error(format!("{operand} is not allowed outside of a function")),
+        }
+    }
+
+    validator.diagnostics
+}
+
+impl Validator {
+    fn error(&mut self, message: String) {
+        self.diagnostics.push(Diagnostic {
+            function: self.function.as_ref().map(|v| v.0.clone()),
+            message,
+        });
+    }
+
+    fn lookup(&self, name: &str) -> Option<&OperandType> {
+        self.scopes.iter().rev().find_map(|v| v.get(name))
+    }
+
+    fn variable(&mut self, name: &str) -> Option<OperandType> {
+        let ty = self.lookup(name).cloned();
+        if ty.is_none() && self.dropped.contains(name) {
+            self.error(format!("Variable {name} is used after being dropped"));
+        } else if ty.is_none() {
+            self.error(format!("Variable {name} does not exist"));
+        }
+        ty
+    }
+
+    fn declare(&mut self, name: &str, ty: &OperandType) {
+        self.dropped.remove(name);
+        let scope = self.scopes.last_mut().expect("Declarations only happen in functions");
+        if scope.insert(name.to_string(), ty.clone()).is_some() {
+            self.error(format!("Variable {name} is declared more than once in the same scope"));
+        }
+    }
+
+    fn body(&mut self, operands: &[Operand]) {
+        self.scopes.push(HashMap::new());
+        for operand in operands {
+            self.operand(operand);
+        }
+        self.scopes.pop();
+    }
+
+    /// Checks that a value of type `found` fits in a location of `expected` size
+    fn assignable(&mut self, expected: &OperandType, found: Option<OperandType>, what: &str) {
+        if let Some(found) = found {
+            if found.size() != expected.size() {
+                self.error(format!(
+                    "Can't assign a {:?} value of type {found} to {what} of type {expected} ({:?})",
+                    found.size(),
+                    expected.size()
+                ));
+            }
+        }
+    }
+
+    fn operand(&mut self, operand: &Operand) {
+        match operand {
+            Operand::FunctionDecl(return_type, name, body, parameters, ..) => {
+                if self.function.is_some() {
+                    self.error(format!("Function {name} can't be declared inside another function"));
+                    return;
+                }
+
+                self.function = Some((name.clone(), return_type.clone()));
+                if parameters.len() > PARAMETER_REGISTERS.len() {
+                    self.error(format!(
+                        "Function {name} has more than {} parameters",
+                        PARAMETER_REGISTERS.len()
+                    ));
+                }
+                if !body.iter().any(|v| matches!(v, Operand::Return(_))) {
+                    self.error(format!("No return statement in function {name}"));
+                }
+
+                self.scopes.push(HashMap::new());
+                for (name, ty) in parameters {
+                    self.declare(name, ty);
+                }
+                self.body(body);
+                self.scopes.pop();
+                self.dropped.clear();
+                self.function = None;
+            }
+            Operand::DeclareVariable(ty, name, value) => {
+                let found = self.value(value);
+                self.assignable(ty, found, &format!("variable {name}"));
+                self.declare(name, ty);
+            }
+            Operand::SetValue(lhs, value) => {
+                if !matches!(lhs, Value::Variable(_) | Value::Dereference(_)) {
+                    self.error(format!("{lhs} can't be assigned to"));
+                    self.value(value);
+                    return;
+                }
+
+                let expected = self.value(lhs);
+                let found = self.value(value);
+                if let Some(expected) = expected {
+                    self.assignable(&expected, found, &lhs.to_string());
+                }
+            }
+            Operand::Add(ty, lhs, rhs) | Operand::Subtract(ty, lhs, rhs) => {
+                for value in [lhs, rhs] {
+                    let found = self.value(value);
+                    self.assignable(ty, found, "an operand");
+                }
+            }
+            Operand::DropVariable(name) => {
+                if self.variable(name).is_some() {
+                    for scope in self.scopes.iter_mut().rev() {
+                        if scope.remove(name).is_some() {
+                            break;
+                        }
+                    }
+                    self.dropped.insert(name.clone());
+                }
+            }
+            Operand::FunctionCall(name, parameters) => {
+                self.call(name, parameters);
+            }
+            Operand::If {
+                predicate,
+                main_body,
+            } => {
+                let lhs = self.value(&predicate.lhs);
+                let rhs = self.value(&predicate.rhs);
+                if let (Some(lhs), Some(rhs)) = (lhs, rhs) {
+                    if lhs.size() != rhs.size() {
+                        self.error(format!("Can't compare {lhs} with {rhs}"));
+                    }
+                }
+                self.body(main_body);
+            }
+            Operand::Switch {
+                scrutinee,
+                cases,
+                default,
+            } => {
+                let ty = self.value(scrutinee).unwrap_or(DEFAULT_TYPE);
+                if let OperandType::Pointer(_) = ty {
+                    self.error(format!("Can't switch on {scrutinee} of type {ty}"));
+                }
+                let mut seen = HashSet::new();
+                for (case, body) in cases {
+                    if wrap_to_type(*case, &ty) != *case {
+                        self.error(format!("Case {case} doesn't fit in {ty}"));
+                    } else if !seen.insert(*case) {
+                        self.error(format!("Case {case} appears more than once"));
+                    }
+                    self.body(body);
+                }
+                self.body(default);
+            }
+            Operand::Return(value) => {
+                let Some((_, return_type)) = self.function.clone() else {
+                    self.error("Return not paired with function".to_string());
+                    return;
+                };
+                if *value != Value::Null {
+                    let found = self.value(value);
+                    self.assignable(&return_type, found, "the return value");
+                }
+            }
+            Operand::InlineAssembly(_) => {}
+            Operand::ExtendedAssembly(asm) => self.extended_assembly(asm),
+        }
+    }
+
+    fn extended_assembly(&mut self, asm: &ExtendedAssembly) {
+        for output in &asm.outputs {
+            if !output.constraint.starts_with(['=', '+']) {
+                self.error(format!(
+                    "Output constraint \"{}\" has to start with = or +",
+                    output.constraint
+                ));
+            }
+            match &output.value {
+                Value::Variable(name) => {
+                    self.variable(name);
+                }
+                value => self.error(format!("{value} can't be an inline assembly output")),
+            }
+        }
+        for input in &asm.inputs {
+            if input.constraint.starts_with(['=', '+']) {
+                self.error(format!("Input constraint \"{}\" can't start with = or +", input.constraint));
+            }
+            self.value(&input.value);
+        }
+
+        let count = asm.outputs.len() + asm.inputs.len();
+        for (i, operand) in asm.operands().enumerate() {
+            match operand.placement() {
+                None => self.error(format!(
+                    "Unsupported inline assembly constraint \"{}\"",
+                    operand.constraint
+                )),
+                Some(AsmConstraint::Immediate) if !matches!(operand.value, Value::Int(_) | Value::Char(_)) => {
+                    self.error(format!("Operand %{i} has to be a constant"));
+                }
+                Some(AsmConstraint::Tied(_)) if i < asm.outputs.len() => {
+                    self.error(format!("Output %{i} can't be tied to another operand"));
+                }
+                Some(AsmConstraint::Tied(output)) => {
+                    let is_register = asm
+                        .outputs
+                        .get(output)
+                        .and_then(|v| v.placement())
+                        .is_some_and(|v| matches!(v, AsmConstraint::Register | AsmConstraint::Fixed(_)));
+                    if !is_register {
+                        self.error(format!("Operand %{i} is tied to %{output}, which isn't a register output"));
+                    }
+                }
+                _ => {}
+            }
+        }
+        for clobber in &asm.clobbers {
+            if !ExtendedAssembly::is_valid_clobber(clobber) {
+                self.error(format!("Unknown clobber \"{clobber}\""));
+            }
+        }
+        for i in asm.references() {
+            if i >= count {
+                self.error(format!("%{i} doesn't name an operand, there are {count}"));
+            }
+        }
+    }
+
+    fn call(&mut self, name: &str, parameters: &[Value]) -> Option<OperandType> {
+        let Some((return_type, expected)) = self.functions.get_function_type(name).cloned() else {
+            self.error(format!("Function {name} does not exist"));
+            parameters.iter().for_each(|v| {
+                self.value(v);
+            });
+            return None;
+        };
+
+        if expected.len() != parameters.len() {
+            self.error(format!(
+                "{name} takes {} arguments but {} were given",
+                expected.len(),
+                parameters.len()
+            ));
+        }
+
+        for (i, value) in parameters.iter().enumerate() {
+            let found = self.value(value);
+            if let Some(expected) = expected.get(i) {
+                self.assignable(expected, found, &format!("argument {} of {name}", i + 1));
+            }
+        }
+
+        Some(return_type)
+    }
+
+    /// Checks a value, returning its type if it has one, literals fit anywhere
+    fn value(&mut self, value: &Value) -> Option<OperandType> {
+        match value {
+            Value::Add(lhs, rhs) | Value::Sub(lhs, rhs) => {
+                let lhs = self.value(lhs);
+                let rhs = self.value(rhs);
+                match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) if lhs.size() != rhs.size() => {
+                        self.error(format!("Can't do arithmetic between {lhs} and {rhs}"));
+                        Some(lhs)
+                    }
+                    (lhs, rhs) => lhs.or(rhs),
+                }
+            }
+            Value::Reference(name) => self
+                .variable(name)
+                .map(|v| OperandType::Pointer(Box::new(v))),
+            Value::Dereference(name) => match self.variable(name)? {
+                OperandType::Pointer(inner) => Some(*inner),
+                ty => {
+                    self.error(format!("Can't dereference {name} of type {ty}, not a pointer"));
+                    None
+                }
+            },
+            Value::Variable(name) => self.variable(name),
+            Value::FunctionCall(name, parameters) => self.call(name, parameters),
+            Value::Null => {
+                self.error("null can only be returned".to_string());
+                None
+            }
+            Value::Char(_) | Value::Int(_) | Value::StringLiteral(_) => None,
+        }
+    }
+}
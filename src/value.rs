@@ -1,8 +1,12 @@
-use std::fmt::Display;
 
 pub use crate::*;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
 pub enum Value {
     Add(Box<Value>, Box<Value>),
     Sub(Box<Value>, Box<Value>),
@@ -25,15 +29,15 @@ impl Value {
     }
 
     /// If it has a defined size, return it, else return None, prefer lhs as a size definer, otherwise use rhs
-    fn estimate_size(&self, compiler: &mut Compiler) -> Option<Size>
+    pub(crate) fn estimate_size(&self, compiler: &mut Compiler) -> Option<Size>
     {
         match self
         {
             Value::Add(lhs, rhs) |
             Value::Sub(lhs, rhs) => lhs.estimate_size(compiler).or(rhs.estimate_size(compiler)),
-            Value::Reference(var) |
-            Value::Dereference(var) |
-            Value::Variable(var) => compiler.scope_manager.get_variable_manager().get(&var).map(|v| v.1.size()),
+            Value::Reference(_) => Some(Size::QuadWord),
+            Value::Dereference(var) => compiler.scope_manager.get_variable_manager().get(var).and_then(|v| v.1.deref_size()),
+            Value::Variable(var) => compiler.scope_manager.get_variable_manager().get(var).map(|v| v.1.size()),
             Value::FunctionCall(name, _) => compiler.scope_manager.get_function(name).map(|v| v.0.size()),
             Value::Null |
             Value::Char(_) |
@@ -42,17 +46,87 @@ impl Value {
         }
     }
 
-    pub fn codegen_size(&self, compiler: &mut Compiler, size : &Size) -> ValueCodegen
+    /// The type of the value when it has one of its own, the same way the sides of a predicate
+    /// are typed by the interpreter
+    pub(crate) fn estimate_type(&self, compiler: &mut Compiler) -> Option<OperandType>
+    {
+        match self
+        {
+            Value::Add(lhs, rhs) |
+            Value::Sub(lhs, rhs) => lhs.estimate_type(compiler).or(rhs.estimate_type(compiler)),
+            Value::Reference(var) => compiler.scope_manager.get_variable_manager().get(var).map(|v| OperandType::Pointer(Box::new(v.1))),
+            Value::Dereference(var) => match compiler.scope_manager.get_variable_manager().get(var) {
+                Some((_, OperandType::Pointer(inner))) => Some(*inner),
+                _ => None,
+            },
+            Value::Variable(var) => compiler.scope_manager.get_variable_manager().get(var).map(|v| v.1),
+            Value::FunctionCall(name, _) => compiler.scope_manager.get_function(name).map(|v| v.0.clone()),
+            Value::Char(_) => Some(OperandType::Char),
+            Value::StringLiteral(_) => Some(OperandType::Pointer(Box::new(OperandType::Char))),
+            Value::Null |
+            Value::Int(_) => None,
+        }
+    }
+
+    /// Whether evaluating the value overwrites the AX register
+    pub(crate) fn clobbers_accumulator(&self) -> bool
+    {
+        matches!(
+            self,
+            Value::Add(..) | Value::Sub(..) | Value::FunctionCall(..) | Value::Reference(_) | Value::Dereference(_)
+        )
+    }
+
+    pub(crate) fn has_call(&self) -> bool
+    {
+        match self
+        {
+            Value::Add(lhs, rhs) |
+            Value::Sub(lhs, rhs) => lhs.has_call() || rhs.has_call(),
+            Value::FunctionCall(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Registers of the variables the value reads
+    pub(crate) fn read_registers(&self, compiler: &mut Compiler) -> Vec<Register>
+    {
+        match self
+        {
+            Value::Add(lhs, rhs) |
+            Value::Sub(lhs, rhs) => {
+                let mut registers = lhs.read_registers(compiler);
+                registers.extend(rhs.read_registers(compiler));
+                registers
+            }
+            Value::Reference(var) |
+            Value::Dereference(var) |
+            Value::Variable(var) => compiler
+                .scope_manager
+                .get_variable_manager()
+                .get(var)
+                .and_then(|v| v.0.as_reg())
+                .into_iter()
+                .collect(),
+            Value::FunctionCall(_, parameters) => parameters.iter().flat_map(|v| v.read_registers(compiler)).collect(),
+            Value::Null |
+            Value::Char(_) |
+            Value::Int(_) |
+            Value::StringLiteral(_) => vec![],
+        }
+    }
+
+    pub fn codegen_size(&self, compiler: &mut Compiler, size : &Size) -> MachineOperand
     {
         self.m_codegen(compiler, Some(size))
     }
 
-    pub fn codegen(&self, compiler: &mut Compiler) -> ValueCodegen
+    pub fn codegen(&self, compiler: &mut Compiler) -> MachineOperand
     {
         self.m_codegen(compiler, None)
     }
 
-    pub fn codegen_lhs(&self, compiler: &mut Compiler) -> ValueCodegen
+    pub fn codegen_lhs(&self, compiler: &mut Compiler) -> MachineOperand
     {
         match self
         {
@@ -60,16 +134,14 @@ impl Value {
                 let variable = compiler
                     .scope_manager
                     .get_variable_manager()
-                    .get(name)
-                    .expect("Variable {name} does not exist.");
+                    .expect(name);
                 variable.0.as_gen(&variable.1.size())
             },
             Value::Dereference(ref name) => {
                 let variable = compiler
                     .scope_manager
                     .get_variable_manager()
-                    .get(name)
-                    .expect("Variable {name} does not exist.");
+                    .expect(name);
                 compiler.new_instruction(Instruction::Move(
                     Register::AX.as_gen(&Size::QuadWord),
                     variable.0.as_ptr(),
@@ -85,17 +157,14 @@ impl Value {
         }
     }
 
-    fn m_codegen(&self, compiler: &mut Compiler, size : Option<&Size>) -> ValueCodegen {
+    fn m_codegen(&self, compiler: &mut Compiler, size : Option<&Size>) -> MachineOperand {
         match self {
-            Value::Char(c) => {
-                ValueCodegen::StringLikeValue(format!("'{c}'"))
-            }
+            Value::Char(c) => MachineOperand::Immediate(*c as i64),
             Value::Reference(ref name) => {
                 let variable = compiler
                     .scope_manager
                     .get_variable_manager()
-                    .get(name)
-                    .expect("Variable {name} does not exist.");
+                    .expect(name);
                 compiler.new_instruction(Instruction::LoadAddress(
                     Register::AX.as_gen(&Size::QuadWord),
                     variable.0.as_gen(&variable.1.size()),
@@ -106,8 +175,7 @@ impl Value {
                 let variable = compiler
                     .scope_manager
                     .get_variable_manager()
-                    .get(name)
-                    .expect("Variable {name} does not exist.");
+                    .expect(name);
                 compiler.new_instruction(Instruction::Move(
                     Register::AX.as_gen(&Size::QuadWord),
                     variable.0.as_ptr(),
@@ -123,76 +191,50 @@ impl Value {
                 let variable = compiler
                     .scope_manager
                     .get_variable_manager()
-                    .get(name)
-                    .expect("Variable {name} does not exist.");
+                    .expect(name);
                 variable.0.as_gen(&variable.1.size())
             }
-            Value::Int(num) => ValueCodegen::Number(num.clone()),
-            Value::StringLiteral(literal) => ValueCodegen::StringLiteral(literal.clone()),
-            Value::FunctionCall(name, parameters) => {
-                ValueCodegen::Register(Register::AX.as_size(&function_call(name, parameters, compiler)))
+            // Anything that isn't a number names a symbol, such as a string define
+            Value::Int(num) => match parse_int_literal(num) {
+                Some(value) => MachineOperand::Immediate(value),
+                None => MachineOperand::Symbol(num.clone()),
+            },
+            Value::StringLiteral(literal) => {
+                let label = compiler.fetch_id("__string");
+                let value = literal.replace('\n', "\\n");
+                compiler.string_defines.push((label.clone(), value));
+                MachineOperand::Symbol(label)
             }
-            Value::Add(lhs, rhs) => {
-                let size = size.cloned().unwrap_or(self.size(compiler));
-                let lhs = lhs.m_codegen(compiler, Some(&size));
-                let rhs = rhs.m_codegen(compiler, Some(&size));
-                let dst = Register::AX.as_gen(&size);
-                compiler.new_instruction(Instruction::Move(dst.clone(), lhs.clone()));
-                compiler.new_instruction(Instruction::Add(dst.clone(), rhs));
-                dst
+            Value::FunctionCall(name, parameters) => {
+                Register::AX.as_gen(&function_call(name, parameters, compiler))
             }
+            Value::Add(lhs, rhs) |
             Value::Sub(lhs, rhs) => {
                 let size = size.cloned().unwrap_or(self.size(compiler));
-                let lhs = lhs.m_codegen(compiler, Some(&size));
-                let rhs = rhs.m_codegen(compiler, Some(&size));
+                let operation = |dst, src| match self {
+                    Value::Add(..) => Instruction::Add(dst, src),
+                    _ => Instruction::Sub(dst, src),
+                };
+                let reads = rhs.read_registers(compiler);
+                let lhs = compiler.with_live(&reads, |c| lhs.m_codegen(c, Some(&size)));
                 let dst = Register::AX.as_gen(&size);
-                compiler.new_instruction(Instruction::Move(dst.clone(), lhs.clone()));
-                compiler.new_instruction(Instruction::Sub(dst.clone(), rhs));
+
+                if rhs.clobbers_accumulator() {
+                    // lhs may be in AX, or in a register a call in rhs overwrites
+                    let lhs = compiler.preserve(lhs, &size);
+                    let rhs = rhs.m_codegen(compiler, Some(&size));
+                    compiler.new_instruction(operation(lhs.operand.clone(), rhs));
+                    compiler.new_instruction(Instruction::Move(dst.clone(), lhs.operand.clone()));
+                    compiler.release(lhs);
+                } else {
+                    let rhs = rhs.m_codegen(compiler, Some(&size));
+                    compiler.new_instruction(Instruction::Move(dst.clone(), lhs));
+                    let rhs = compiler.fit_immediate(rhs, &size);
+                    compiler.new_instruction(operation(dst.clone(), rhs));
+                }
                 dst
             }
             Value::Null => panic!(),
         }
     }
 }
-
-#[derive(Clone, Debug, PartialEq, PartialOrd)]
-pub enum ValueCodegen {
-    Register(String),
-    StackOffset(String),
-    Pointer(String),
-    Number(String),
-    StringLikeValue(String),
-    StringLiteral(String),
-}
-
-impl Display for ValueCodegen {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&self.inner())
-    }
-}
-
-impl ValueCodegen {
-    pub fn is_immediate(&self) -> bool
-    {
-        matches!(self, ValueCodegen::Number(_))
-    }
-
-    pub fn is_register(&self) -> bool {
-        matches!(self, ValueCodegen::Register(_))
-    }
-
-    pub fn is_stack(&self) -> bool {
-        matches!(self, ValueCodegen::StackOffset(_))
-    }
-
-    pub fn inner(&self) -> String {
-        match self {
-            ValueCodegen::Register(s)
-            | ValueCodegen::StackOffset(s)
-            | ValueCodegen::Pointer(s)
-            | ValueCodegen::Number(s)
-            | ValueCodegen::StringLikeValue(s)
-            | ValueCodegen::StringLiteral(s) => s.clone(),
-        }
-    }
-}
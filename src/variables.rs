@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::*;
 
@@ -20,29 +20,30 @@ impl VariableLocation {
         matches!(self, Self::StackOffset(_))
     }
 
-    pub fn as_gen(&self, size: &Size) -> ValueCodegen {
+    pub fn as_gen(&self, size: &Size) -> MachineOperand {
         match self {
             VariableLocation::Register(register) => register.as_gen(size),
             VariableLocation::StackOffset(stack) => {
-                ValueCodegen::StackOffset(format!("{} [rbp-{}]", size.name(), stack))
+                MachineOperand::Memory(MemoryOperand::new(*size, Register::BP, -(*stack as i32)))
             }
         }
     }
 
-    pub fn as_ptr(&self) -> ValueCodegen {
-        match self {
-            VariableLocation::Register(register) => register.as_ptr(),
-            VariableLocation::StackOffset(stack) => {
-                ValueCodegen::Pointer(format!("QWORD [rbp-{}]", stack))
-            }
-        }
+    /// The variable itself as a pointer sized operand
+    pub fn as_ptr(&self) -> MachineOperand {
+        self.as_gen(&Size::QuadWord)
     }
 }
 
 #[derive(Debug)]
 pub struct VariableManager {
-    variables: HashMap<String, (VariableLocation, OperandType)>,
+    /// One map per block, innermost last, lookups fall back to the enclosing blocks
+    variables: Vec<HashMap<String, (VariableLocation, OperandType)>>,
     stack_location: u32,
+    /// Offsets of dropped stack slots by their size in bytes, slots are naturally aligned so
+    /// any free slot of the same size can be reused
+    free_slots: HashMap<u8, Vec<u32>>,
+    dropped: HashSet<String>,
 }
 
 pub const PARAMETER_REGISTERS: &[Register] = &[
@@ -54,6 +55,9 @@ pub const PARAMETER_REGISTERS: &[Register] = &[
     Register::R9,
 ];
 
+/// Hold partial results while something else is evaluated, neither is used to pass arguments
+pub const SCRATCH_REGISTERS: &[Register] = &[Register::R10, Register::R11];
+
 impl Default for VariableManager {
     fn default() -> Self {
         Self::new()
@@ -61,25 +65,59 @@ impl Default for VariableManager {
 }
 
 impl VariableManager {
+    /// The most stack the function needed at once, dropped slots are counted once
     pub fn used_stack(&self) -> u32 {
         self.stack_location
     }
 
     pub fn new() -> Self {
         Self {
-            variables: HashMap::new(),
+            variables: vec![HashMap::new()],
             stack_location: 0,
+            free_slots: HashMap::new(),
+            dropped: HashSet::new(),
+        }
+    }
+
+    /// Starts a nested block, its variables can shadow the ones outside of it
+    pub fn enter_block(&mut self) {
+        self.variables.push(HashMap::new());
+    }
+
+    /// Ends the innermost block, the stack slots of its variables can be reused afterwards
+    pub fn leave_block(&mut self) {
+        let block = self.variables.pop().expect("Left more blocks than were entered");
+        for (location, _type) in block.into_values() {
+            self.free(location, &_type);
+        }
+    }
+
+    fn free(&mut self, location: VariableLocation, _type: &OperandType) {
+        if let VariableLocation::StackOffset(offset) = location {
+            self.free_slots
+                .entry(_type.size().get_bytes())
+                .or_default()
+                .push(offset);
         }
     }
 
+    /// Removes the innermost variable with the name, returning its stack slot to be reused by
+    /// later allocations
     pub fn deallocate(&mut self, var: &str) -> bool {
-        if !self.variables.contains_key(var) {
+        let Some((location, _type)) = self.variables.iter_mut().rev().find_map(|v| v.remove(var)) else {
             return false;
-        }
+        };
 
+        self.free(location, &_type);
+        self.dropped.insert(var.to_string());
         true
     }
 
+    /// Whether the variable was dropped and hasn't been declared again since
+    pub fn is_dropped(&self, var: &str) -> bool {
+        self.dropped.contains(var)
+    }
+
     pub fn allocate(
         &mut self,
         var: &str,
@@ -87,19 +125,32 @@ impl VariableManager {
     ) -> Option<(VariableLocation, OperandType)> {
         let size = _type.size().get_bytes();
 
-        self.stack_location += size as u32;
-        let variable = (
-            VariableLocation::StackOffset(self.stack_location),
-            _type.clone(),
-        );
+        // Redeclaring a variable in the same block replaces it
+        if let Some((location, old)) = self.innermost().remove(var) {
+            self.free(location, &old);
+        }
+        let offset = match self.free_slots.get_mut(&size).and_then(|v| v.pop()) {
+            Some(offset) => offset,
+            None => {
+                // Keep every slot naturally aligned
+                self.stack_location = (self.stack_location + size as u32).next_multiple_of(size as u32);
+                self.stack_location
+            }
+        };
+        let variable = (VariableLocation::StackOffset(offset), _type.clone());
 
-        self.variables.insert(var.to_string(), variable.clone());
+        self.dropped.remove(var);
+        self.innermost().insert(var.to_string(), variable.clone());
 
         Some(variable)
     }
 
+    fn innermost(&mut self) -> &mut HashMap<String, (VariableLocation, OperandType)> {
+        self.variables.last_mut().expect("There is always a block")
+    }
+
     pub fn allocate_parameter(&mut self, var: &str, _type: &OperandType, i: usize) {
-        self.variables.insert(
+        self.innermost().insert(
             var.to_string(),
             (
                 VariableLocation::Register(PARAMETER_REGISTERS[i]),
@@ -109,11 +160,25 @@ impl VariableManager {
     }
 
     pub fn get(&self, var: &str) -> Option<(VariableLocation, OperandType)> {
-        if !self.variables.contains_key(var) {
-            return None;
-        }
+        self.variables.iter().rev().find_map(|v| v.get(var)).cloned()
+    }
 
-        Some(self.variables[var].clone())
+    /// Registers holding a variable that is in scope
+    pub fn registers(&self) -> Vec<Register> {
+        self.variables
+            .iter()
+            .flat_map(|v| v.values())
+            .filter_map(|v| v.0.as_reg())
+            .collect()
+    }
+
+    /// Like [`VariableManager::get`], panicking with the reason the variable can't be used
+    pub fn expect(&self, var: &str) -> (VariableLocation, OperandType) {
+        match self.get(var) {
+            Some(variable) => variable,
+            None if self.is_dropped(var) => panic!("Variable {var} is used after being dropped."),
+            None => panic!("Variable {var} does not exist."),
+        }
     }
 
     pub fn get_or_allocate(
@@ -0,0 +1,90 @@
+use low_level_ir::*;
+
+const I32: OperandType = OperandType::Int(Size::DoubleWord);
+const I64: OperandType = OperandType::Int(Size::QuadWord);
+
+#[test]
+fn built_module_matches_its_text() {
+    let mut module = ModuleBuilder::new();
+    let twice = module.declare_function("twice", &I32, &[I32]).unwrap();
+    let main = module.declare_function("main", &I32, &[]).unwrap();
+
+    let mut f = module.define(&twice, &["x"]).unwrap();
+    let x = f.param(0);
+    let sum = f.add(&x, &x).unwrap();
+    f.ret(sum).unwrap();
+    module.finish_function(f).unwrap();
+
+    let mut f = module.define(&main, &[]).unwrap();
+    let r = f.declare("r", &I32, Expr::int(1)).unwrap();
+    f.if_then(&r, CompareOperation::EQ, Expr::int(1), |f| {
+        let call = f.call(&twice, &[Expr::int(20)])?;
+        f.set(&r, call)
+    })
+    .unwrap();
+    let p = f
+        .declare("p", &OperandType::Pointer(Box::new(I32)), r.reference())
+        .unwrap();
+    f.set(p.dereference().unwrap(), f.sub(&r, Expr::int(2)).unwrap())
+        .unwrap();
+    f.ret(&r).unwrap();
+    module.finish_function(f).unwrap();
+
+    let expected = parse_ir(
+        "fn i32 twice(i32 x) { return x + x; }
+        fn i32 main() {
+            let i32 r = 1;
+            if r == 1 { r = twice(20); }
+            let *i32 p = &r;
+            *p = r - 2;
+            return r;
+        }",
+    )
+    .unwrap();
+    assert_eq!(module.finish().unwrap(), expected);
+}
+
+#[test]
+fn mistakes_are_reported_when_building() {
+    let mut module = ModuleBuilder::new();
+    let f1 = module.declare_function("f", &I32, &[I32]).unwrap();
+    assert_eq!(
+        module.declare_function("f", &I32, &[]),
+        Err(BuildError::DuplicateFunction("f".to_string()))
+    );
+    assert!(matches!(
+        module.define(&f1, &[]),
+        Err(BuildError::ArityMismatch {
+            expected: 1,
+            found: 0,
+            ..
+        })
+    ));
+
+    let mut f = module.define(&f1, &["x"]).unwrap();
+    assert_eq!(
+        f.declare("x", &I32, Expr::int(0)).unwrap_err(),
+        BuildError::DuplicateVariable("x".to_string())
+    );
+    let wide = f.declare("wide", &I64, Expr::int(0)).unwrap();
+    assert!(matches!(
+        f.add(f.param(0), &wide),
+        Err(BuildError::TypeMismatch { .. })
+    ));
+    assert!(matches!(
+        f.call(&f1, &[]),
+        Err(BuildError::ArityMismatch { .. })
+    ));
+    assert_eq!(
+        f.set(Expr::int(3), Expr::int(4)),
+        Err(BuildError::NotAnLvalue(Value::Int("3".to_string())))
+    );
+    assert_eq!(
+        module.finish_function(f),
+        Err(BuildError::MissingReturn("f".to_string()))
+    );
+    assert_eq!(
+        module.finish(),
+        Err(BuildError::UndefinedFunction("f".to_string()))
+    );
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+fn compile(source: &str) -> Vec<String> {
+    let mut compiler = Compiler::new();
+    compiler.operands = parse("source", source);
+    let asm = compiler.try_compile().unwrap();
+    asm.lines().map(str::to_string).collect()
+}
+
+#[test]
+fn inline_assembly_uses_the_registers_it_names() {
+    let instruction = Instruction::AsmLiteral("mov rbx, 1\nadd r12d, [rsp+8]".to_string());
+    assert_eq!(
+        instruction.registers(),
+        [Register::BX, Register::R12, Register::SP]
+    );
+    assert!(Register::BX.is_callee_saved());
+    assert!(!Register::AX.is_callee_saved());
+    assert_eq!(Register::from_name("R13W"), Some(Register::R13));
+    assert_eq!(Register::from_name("label"), None);
+}
+
+#[test]
+fn used_callee_saved_registers_are_restored() {
+    let lines = compile("fn i32 main() { asm \"mov r13, 1\nmov rbx, 2\"; return 0; }");
+    let start = lines.iter().position(|v| v == "main:").unwrap();
+    assert_eq!(
+        lines[start + 1..start + 4],
+        ["push RBX", "push R13", "push RBP"]
+    );
+    // Three pushes and the return address leave the stack 16 byte aligned already
+    assert!(
+        !lines.iter().any(|v| v.starts_with("sub RSP")),
+        "{lines:#?}"
+    );
+    let ret = lines.iter().rposition(|v| v == "ret").unwrap();
+    assert_eq!(lines[ret - 3..ret], ["pop RBP", "pop R13", "pop RBX"]);
+}
+
+#[test]
+fn an_odd_number_of_saved_registers_keeps_the_stack_aligned() {
+    let lines = compile("fn i32 main() { asm \"mov r12, 1\"; return 0; }");
+    assert!(lines.iter().any(|v| v == "push R12"), "{lines:#?}");
+    assert!(lines.iter().any(|v| v == "sub RSP, 8"), "{lines:#?}");
+}
+
+#[test]
+fn functions_without_them_save_nothing() {
+    let lines = compile("fn i32 main() { let i32 x = 1; return x; }");
+    let start = lines.iter().position(|v| v == "main:").unwrap();
+    assert_eq!(lines[start + 1], "push RBP");
+}
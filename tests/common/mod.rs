@@ -0,0 +1,39 @@
+// Every test crate includes this module but only uses part of it
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use low_level_ir::*;
+
+/// Fixtures in `tests/programs` with what their `main` returns
+pub const PROGRAMS: &[(&str, &str, i32)] = &[
+    ("basics", include_str!("../programs/basics.lir"), 62),
+    ("calls", include_str!("../programs/calls.lir"), 144),
+    ("control", include_str!("../programs/control.lir"), 3732),
+    ("types", include_str!("../programs/types.lir"), 959),
+];
+
+/// A fixture with inline assembly, which only compiled code can run, and what it returns
+pub const ASSEMBLY: (&str, &str, i32) = ("asm", include_str!("../programs/asm.lir"), 70);
+
+pub fn parse(name: &str, source: &str) -> Vec<Operand> {
+    parse_ir(source).unwrap_or_else(|e| panic!("{name}: {e}"))
+}
+
+/// A path for `name` in cargo's scratch directory for integration tests
+pub fn scratch(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(name)
+}
+
+/// Whether `program` can be run, tests that need an external tool are skipped without it
+pub fn has_tool(program: &str) -> bool {
+    Command::new(program).arg("--version").output().is_ok()
+}
+
+/// What the reference interpreter says `main` returns
+pub fn interpret(name: &str, operands: &[Operand]) -> i32 {
+    let mut interpreter = Interpreter::new(operands).unwrap_or_else(|e| panic!("{name}: {e}"));
+    let result = interpreter.call("main", &[]);
+    result.unwrap_or_else(|e| panic!("{name}: {e}")) as i32
+}
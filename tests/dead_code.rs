@@ -0,0 +1,114 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+fn eliminate(source: &str) -> (String, DeadCodeReport) {
+    let (operands, report) = eliminate_dead_code(&parse("source", source), &["main".to_string()]);
+    (print_ir(&operands), report)
+}
+
+#[test]
+fn dead_stores_are_removed_but_their_calls_kept() {
+    let (output, report) = eliminate(
+        "fn i32 g() { return 1; }
+        fn i32 main() {
+            let i32 unused = 5;
+            let i32 called = g() + 1;
+            let i32 x = 1;
+            x = 2;
+            x = x + 3;
+            x = 4;
+            return x;
+        }",
+    );
+    let expected = parse(
+        "expected",
+        "fn i32 g() { return 1; }
+        fn i32 main() {
+            g();
+            let i32 x = 1;
+            x = 4;
+            return x;
+        }",
+    );
+    assert_eq!(output, print_ir(&expected));
+    let stores = report
+        .stores
+        .iter()
+        .map(|v| v.1.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(stores, ["unused", "called", "x", "x"]);
+    assert!(report.stores.iter().all(|v| v.0 == "main"));
+}
+
+#[test]
+fn uncalled_functions_are_removed() {
+    let (output, report) = eliminate(
+        "fn i32 used() { return 1; }
+        fn i32 unused() { return used(); }
+        fn i32 main() { let i32 r = used(); return r; }",
+    );
+    assert!(!output.contains("unused"), "{output}");
+    assert_eq!(report.functions, ["unused"]);
+    assert_eq!(report.to_string(), "removed uncalled function unused\n");
+}
+
+#[test]
+fn stores_through_pointers_and_assembly_are_kept() {
+    let source = "
+        fn i32 main() {
+            let i32 v = 1;
+            let *i32 p = &v;
+            *p = 2;
+            return v;
+        }
+        fn i32 other() { let i32 v = 1; asm \"nop\"; return 0; }";
+    let operands = parse("source", source);
+    let exports = ["main".to_string(), "other".to_string()];
+    let (output, report) = eliminate_dead_code(&operands, &exports);
+    assert_eq!(output, operands);
+    assert_eq!(report, DeadCodeReport::default());
+}
+
+#[test]
+fn eliminated_programs_compute_the_same() {
+    for (name, source, _) in PROGRAMS {
+        let operands = parse(name, source);
+        let (eliminated, _) = eliminate_dead_code(&operands, &["main".to_string()]);
+        assert_eq!(
+            interpret(name, &eliminated),
+            interpret(name, &operands),
+            "{name}"
+        );
+    }
+}
+
+#[test]
+fn operands_after_a_return_are_removed() {
+    let source = "noinline fn i32 g(i32 x) { return x; }
+        fn i32 main() {
+            let i32 c = 1;
+            let i32 v = 5;
+            if c == 1 { return 3; g(v); }
+            return 0;
+        }";
+    let (output, _) = eliminate(source);
+    let expected = parse(
+        "expected",
+        "noinline fn i32 g(i32 x) { return x; }
+        fn i32 main() {
+            let i32 c = 1;
+            if c == 1 { return 3; }
+            return 0;
+        }",
+    );
+    assert_eq!(output, print_ir(&expected));
+
+    let mut compiler = Compiler::new();
+    compiler.operands = parse("source", source);
+    compiler.opt_level = 1;
+    let module = compiler.compile_jit(&[]).unwrap();
+    let main: extern "C" fn() -> i32 = unsafe { module.get("main") }.unwrap();
+    assert_eq!(main(), 3);
+}
@@ -0,0 +1,103 @@
+//! The interpreter is the reference, compiled code has to agree with it at every level
+
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+fn jit(name: &str, operands: &[Operand], opt_level: u8) -> i32 {
+    let mut compiler = Compiler::new();
+    compiler.operands = operands.to_vec();
+    compiler.opt_level = opt_level;
+    let module = compiler
+        .compile_jit(&[])
+        .unwrap_or_else(|e| panic!("{name} at -O{opt_level}: {e}"));
+    let main: extern "C" fn() -> i32 = unsafe { module.get("main") }.unwrap();
+    main()
+}
+
+#[test]
+fn jit_matches_interpreter() {
+    for (name, source, _) in PROGRAMS {
+        let operands = parse(name, source);
+        let expected = interpret(name, &operands);
+        for opt_level in 0..=2 {
+            assert_eq!(
+                jit(name, &operands, opt_level),
+                expected,
+                "{name} at -O{opt_level}"
+            );
+        }
+    }
+}
+
+#[test]
+fn inline_assembly_runs_in_the_jit() {
+    let (name, source, expected) = ASSEMBLY;
+    let operands = parse(name, source);
+    for opt_level in 0..=2 {
+        assert_eq!(jit(name, &operands, opt_level), expected, "-O{opt_level}");
+    }
+}
+
+#[test]
+fn parameters_can_be_referenced() {
+    let operands = parse(
+        "referenced",
+        "fn i32 set(*i32 p) { *p = 9; return 0; }
+        fn i32 f(i32 a, i64 b) {
+            let *i32 p = &a;
+            *p = *p + 1;
+            let *i64 q = &b;
+            *q = *q + 2;
+            let i32 before = a;
+            set(&a);
+            if b == 4 {
+                before = before + 100;
+            }
+            return before + a;
+        }
+        fn i32 main() { return f(1, 2); }",
+    );
+    assert!(validate(&operands).is_empty());
+    assert_eq!(interpret("referenced", &operands), 111);
+    for opt_level in 0..=2 {
+        assert_eq!(
+            jit("referenced", &operands, opt_level),
+            111,
+            "-O{opt_level}"
+        );
+    }
+}
+
+#[test]
+fn literal_arithmetic_takes_the_size_it_is_used_at() {
+    let operands = parse(
+        "literals",
+        "fn u64 wide() { let u64 a = 0 - 1; return a; }
+        fn i64 big() {
+            let i64 b = 4294967296;
+            b = b + 8589934592;
+            let i64 c = 5 - 4294967296;
+            if b > 4294967296 { c = c - 1; }
+            if c == 0 - 4294967292 { b = b + 1; }
+            return b + c;
+        }
+        fn i32 main() {
+            let u8 x = 1 + 2;
+            if x == 1 + 2 { x = x + 250; }
+            let u64 a = wide();
+            if a == 0 - 1 { x = x + 1; }
+            let i64 d = big();
+            if d == 8589934597 { x = x + 1; }
+            let i32 r = 0;
+            if x == 255 { r = 1; }
+            return r;
+        }",
+    );
+    assert!(validate(&operands).is_empty());
+    assert_eq!(interpret("literals", &operands), 1);
+    for opt_level in 0..=2 {
+        assert_eq!(jit("literals", &operands, opt_level), 1, "-O{opt_level}");
+    }
+}
@@ -0,0 +1,58 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+const REUSE: &str = "fn i32 main() {
+    let i32 a = 20;
+    let i32 b = a + 1;
+    drop a;
+    let i32 c = b + b;
+    drop b;
+    let i32 d = c;
+    return d;
+}";
+
+#[test]
+fn dropped_slots_are_reused() {
+    let mut compiler = Compiler::new();
+    compiler.operands = parse("reuse", REUSE);
+    let asm = compiler.try_compile().unwrap();
+    assert!(asm.contains("[RBP-4]"), "{asm}");
+    assert!(asm.contains("[RBP-8]"), "{asm}");
+    assert!(!asm.contains("[RBP-12]"), "{asm}");
+}
+
+#[test]
+fn reused_slots_keep_their_values() {
+    let operands = parse("reuse", REUSE);
+    assert_eq!(interpret("reuse", &operands), 42);
+    let mut compiler = Compiler::new();
+    compiler.operands = operands;
+    let module = compiler.compile_jit(&[]).unwrap();
+    let main: extern "C" fn() -> i32 = unsafe { module.get("main") }.unwrap();
+    assert_eq!(main(), 42);
+}
+
+#[test]
+fn use_after_drop_is_reported() {
+    let operands = parse(
+        "dropped",
+        "fn i32 main() { let i32 a = 1; drop a; return a; }",
+    );
+    let messages = validate(&operands)
+        .into_iter()
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(messages, ["in function main: Variable a is used after being dropped"]);
+}
+
+#[test]
+fn dropped_variables_can_be_declared_again() {
+    let operands = parse(
+        "redeclared",
+        "fn i32 main() { let i32 a = 1; drop a; let i32 a = 2; return a; }",
+    );
+    assert!(validate(&operands).is_empty());
+    assert_eq!(interpret("redeclared", &operands), 2);
+}
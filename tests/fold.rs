@@ -0,0 +1,70 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+fn assert_folds(source: &str, expected: &str) {
+    let folded = fold_constants(&parse("source", source), &[]);
+    assert_eq!(print_ir(&folded), print_ir(&parse("expected", expected)));
+}
+
+#[test]
+fn literals_and_identities_are_folded() {
+    assert_folds(
+        "fn i32 f(i32 x) {
+            let i32 a = 2 + 3;
+            let i32 b = x + 0;
+            let i32 c = x - x;
+            let u8 d = 250 + 10;
+            let i8 e = 127 + 1;
+            return a + b + c;
+        }",
+        "fn i32 f(i32 x) {
+            let i32 a = 5;
+            let i32 b = x;
+            let i32 c = 0;
+            let u8 d = 4;
+            let i8 e = -128;
+            return a + b + c;
+        }",
+    );
+}
+
+#[test]
+fn known_predicates_remove_the_if() {
+    assert_folds(
+        "fn i32 f(i32 x) {
+            if 1 > 2 { x = 100; }
+            if 2 + 3 == 5 { x = x + 7; }
+            if x == 3 { x = 1; }
+            return x;
+        }",
+        "fn i32 f(i32 x) {
+            x = x + 7;
+            if x == 3 { x = 1; }
+            return x;
+        }",
+    );
+}
+
+#[test]
+fn calls_are_never_folded_away() {
+    let source = "
+        fn i32 g() { return 1; }
+        fn i32 f() { let i32 a = g() - g(); return a; }
+    ";
+    assert_folds(source, source);
+}
+
+#[test]
+fn folded_programs_compute_the_same() {
+    for (name, source, _) in PROGRAMS {
+        let operands = parse(name, source);
+        let folded = fold_constants(&operands, &[]);
+        assert_eq!(
+            interpret(name, &folded),
+            interpret(name, &operands),
+            "{name}"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+fn inline(source: &str, threshold: usize) -> (Vec<Operand>, InlineReport) {
+    let (operands, report) = inline_functions(&parse("source", source), &[], threshold);
+    let diagnostics = validate(&operands);
+    assert!(
+        diagnostics.is_empty(),
+        "{diagnostics:?}\n{}",
+        print_ir(&operands)
+    );
+    (operands, report)
+}
+
+fn callees(report: &InlineReport) -> Vec<&str> {
+    report.calls.iter().map(|v| v.1.as_str()).collect()
+}
+
+#[test]
+fn small_functions_are_inlined() {
+    let (operands, report) = inline(
+        "fn i32 add1(i32 a) { let i32 b = a + 1; return b; }
+        fn i32 main() { let i32 b = 3; let i32 c = add1(b) + add1(4); return b + c; }",
+        DEFAULT_INLINE_THRESHOLD,
+    );
+    assert_eq!(callees(&report), ["add1", "add1"]);
+    assert!(report.calls.iter().all(|v| v.0 == "main"));
+    assert_eq!(print_ir(&operands).matches("add1(").count(), 1);
+    assert_eq!(interpret("inlined", &operands), 12);
+}
+
+#[test]
+fn hints_override_the_threshold() {
+    let source = "inline fn i32 forced(i32 a) { return a + 1; }
+        noinline fn i32 never(i32 a) { return a; }
+        fn i32 plain(i32 a) { let i32 b = a; return b; }
+        fn i32 main() { return forced(1) + never(2) + plain(3); }";
+    let (_, report) = inline(source, 0);
+    assert_eq!(callees(&report), ["forced"]);
+    let (_, report) = inline(source, DEFAULT_INLINE_THRESHOLD);
+    assert_eq!(callees(&report), ["forced", "plain"]);
+}
+
+#[test]
+fn recursive_functions_are_not_inlined() {
+    let (_, report) = inline(
+        "fn i32 down(i32 n) { if n == 0 { return 0; } return down(n - 1); }
+        fn i32 main() { return down(3); }",
+        DEFAULT_INLINE_THRESHOLD,
+    );
+    assert!(report.calls.is_empty(), "{report}");
+}
+
+#[test]
+fn hints_round_trip() {
+    let source = "inline fn i32 f() { return 1; }\nnoinline fn i32 g() { return 2; }\n";
+    let operands = parse("hints", source);
+    assert_eq!(
+        print_ir(&parse("printed", &print_ir(&operands))),
+        print_ir(&operands)
+    );
+    assert!(print_ir(&operands).contains("inline fn i32 f()"));
+    assert!(print_ir(&operands).contains("noinline fn i32 g()"));
+}
+
+#[test]
+fn inlined_programs_compute_the_same() {
+    for (name, source, expected) in PROGRAMS {
+        let (operands, _) = inline(source, DEFAULT_INLINE_THRESHOLD);
+        assert_eq!(interpret(name, &operands), *expected, "{name}");
+    }
+}
+
+#[test]
+fn spilled_literals_take_the_type_they_are_used_as() {
+    let source = "noinline fn u64 f0(u64 a0, u64 a1) { return a0; }
+        fn u64 f1(u64 a0) { return a0 - 33; }
+        fn u64 main() { return f0(0 - 36, f1(4)); }";
+    let (operands, report) = inline(source, DEFAULT_INLINE_THRESHOLD);
+    assert_eq!(callees(&report), ["f1"]);
+    assert!(
+        print_ir(&operands).contains("let u64 spill"),
+        "{}",
+        print_ir(&operands)
+    );
+
+    let expected = 0u64.wrapping_sub(36);
+    for opt_level in 0..=2 {
+        let mut compiler = Compiler::new();
+        compiler.operands = parse("source", source);
+        compiler.opt_level = opt_level;
+        let module = compiler.compile_jit(&[]).unwrap();
+        let main: extern "C" fn() -> u64 = unsafe { module.get("main") }.unwrap();
+        assert_eq!(main(), expected, "-O{opt_level}");
+    }
+}
@@ -0,0 +1,87 @@
+use low_level_ir::*;
+
+fn register(register: Register, size: Size) -> MachineOperand {
+    MachineOperand::Register(register, size)
+}
+
+fn local(size: Size, displacement: i32) -> MachineOperand {
+    MachineOperand::Memory(MemoryOperand::new(size, Register::BP, displacement))
+}
+
+/// The NASM and GAS text of an instruction, and its machine code
+fn check(instruction: Instruction, nasm: &str, gas: &str, code: &[u8]) {
+    assert_eq!(instruction.clone().codegen_x86(), nasm);
+    assert_eq!(instruction.clone().codegen_gas(), gas);
+    assert_eq!(encode_x86(&[instruction]).unwrap().code, code, "{nasm}");
+}
+
+#[test]
+fn instructions_render_and_encode_the_same_operands() {
+    check(
+        Instruction::Move(
+            register(Register::AX, Size::DoubleWord),
+            MachineOperand::Immediate(5),
+        ),
+        "mov EAX, 5",
+        "mov EAX, 5",
+        &[0xB8, 5, 0, 0, 0],
+    );
+    check(
+        Instruction::Move(
+            register(Register::AX, Size::QuadWord),
+            local(Size::QuadWord, -8),
+        ),
+        "mov RAX, QWORD [RBP-8]",
+        "mov RAX, QWORD PTR [RBP-8]",
+        &[0x48, 0x8B, 0x45, 0xF8],
+    );
+    check(
+        Instruction::Add(
+            register(Register::DI, Size::DoubleWord),
+            register(Register::SI, Size::DoubleWord),
+        ),
+        "add EDI, ESI",
+        "add EDI, ESI",
+        &[0x01, 0xF7],
+    );
+    check(
+        Instruction::Sub(local(Size::Byte, -1), MachineOperand::Immediate(3)),
+        "sub BYTE [RBP-1], 3",
+        "sub BYTE PTR [RBP-1], 3",
+        &[0x80, 0x6D, 0xFF, 0x03],
+    );
+    check(Instruction::Return, "ret", "ret", &[0xC3]);
+}
+
+#[test]
+fn mismatched_operands_are_refused() {
+    let instruction = Instruction::Move(
+        register(Register::AX, Size::QuadWord),
+        register(Register::BX, Size::DoubleWord),
+    );
+    assert!(matches!(
+        encode_x86(&[instruction]),
+        Err(EncodeError::InvalidOperand(_))
+    ));
+}
+
+#[test]
+fn symbols_become_relocations() {
+    let encoded = encode_x86(&[Instruction::Call("puts".to_string())]).unwrap();
+    assert_eq!(encoded.code[0], 0xE8);
+    assert_eq!(encoded.relocations.len(), 1);
+    assert_eq!(encoded.relocations[0].symbol, "puts");
+    assert_eq!(encoded.relocations[0].kind, RelocationKind::Plt32);
+}
+
+#[test]
+fn lea_needs_a_memory_source() {
+    let lea = Instruction::LoadAddress(
+        register(Register::AX, Size::QuadWord),
+        register(Register::DI, Size::QuadWord),
+    );
+    assert!(matches!(
+        encode_x86(&[lea]),
+        Err(EncodeError::InvalidOperand(_))
+    ));
+}
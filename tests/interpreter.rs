@@ -0,0 +1,81 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+fn run(source: &str, function: &str, arguments: &[i64]) -> Result<i64, InterpretError> {
+    Interpreter::new(&parse("program", source))?.call(function, arguments)
+}
+
+#[test]
+fn fixtures_return_what_they_should() {
+    for (name, source, expected) in PROGRAMS {
+        assert_eq!(interpret(name, &parse(name, source)), *expected, "{name}");
+    }
+}
+
+#[test]
+fn recursion_and_nested_calls() {
+    let source = "
+        fn i32 fib(i32 n) {
+            let i32 r = n;
+            if n >= 2 { r = fib(n - 1) + fib(n - 2); }
+            return r;
+        }
+        fn i32 three(i32 a, i32 b, i32 c) { return a - b + c; }
+    ";
+    assert_eq!(run(source, "fib", &[20]), Ok(6765));
+    assert_eq!(run(source, "three", &[1, 2, 3]), Ok(2));
+}
+
+#[test]
+fn values_wrap_to_their_type() {
+    let source = "
+        fn u8 wrap(u8 a) { let u8 b = a + 10; return b; }
+        fn i8 negative(i8 a) { let i8 b = a - 100; return b; }
+        fn u32 unsigned() { let u32 a = 0 - 1; return a; }
+    ";
+    assert_eq!(run(source, "wrap", &[250]), Ok(4));
+    assert_eq!(run(source, "negative", &[-100]), Ok(56));
+    assert_eq!(run(source, "unsigned", &[]), Ok(u32::MAX as i64));
+    assert_eq!(wrap_to_type(-1, &OperandType::UInt(Size::Word)), 0xFFFF);
+    assert_eq!(wrap_to_type(0x80, &OperandType::Int(Size::Byte)), -128);
+}
+
+#[test]
+fn pointers_write_through_memory() {
+    let source = "
+        fn i32 bump(*i32 p) { *p = *p + 10; return 0; }
+        fn i32 main() { let i32 v = 5; bump(&v); return v; }
+    ";
+    assert_eq!(run(source, "main", &[]), Ok(15));
+}
+
+#[test]
+fn runtime_errors_are_reported() {
+    let source = "
+        fn i32 forever(i32 n) { return forever(n + 1); }
+        fn i32 null() { let *i32 p = 0; return *p; }
+        fn i32 main() { return 0; }
+    ";
+    let mut interpreter = Interpreter::new(&parse("program", source)).unwrap();
+    // Test threads have a smaller stack than the default depth is meant for
+    interpreter.set_max_call_depth(50);
+    assert_eq!(
+        interpreter.call("forever", &[0]),
+        Err(InterpretError::StackOverflow)
+    );
+    assert_eq!(run(source, "null", &[]), Err(InterpretError::Segfault(0)));
+    assert_eq!(
+        run(source, "missing", &[]),
+        Err(InterpretError::UndefinedFunction("missing".to_string()))
+    );
+    assert!(matches!(
+        run(source, "main", &[1]),
+        Err(InterpretError::ArityMismatch {
+            expected: 0,
+            found: 1,
+            ..
+        })
+    ));
+}
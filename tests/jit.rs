@@ -0,0 +1,59 @@
+use std::ffi::CStr;
+
+use low_level_ir::*;
+
+const I32: OperandType = OperandType::Int(Size::DoubleWord);
+
+extern "C" fn host_double(x: i32) -> i32 {
+    x * 2
+}
+
+fn compile(source: &str) -> Compiler {
+    let mut compiler = Compiler::new();
+    compiler.operands = parse_ir(source).unwrap();
+    compiler
+}
+
+#[test]
+fn functions_are_called_with_arguments() {
+    let module = compile("fn i64 sub(i64 a, i64 b) { return a - b; }")
+        .compile_jit(&[])
+        .unwrap();
+    let sub: extern "C" fn(i64, i64) -> i64 = unsafe { module.get("sub") }.unwrap();
+    assert_eq!(sub(10, 3), 7);
+    assert_eq!(sub(-5, i64::MAX), (-5i64).wrapping_sub(i64::MAX));
+    assert!(unsafe { module.get::<extern "C" fn()>("missing") }.is_none());
+}
+
+#[test]
+fn externs_call_into_the_host() {
+    let mut compiler =
+        compile("fn i32 twice_plus(i32 x) { let i32 d = host_double(x); return d + 1; }");
+    compiler.declare_extern("host_double", &I32, &[I32]);
+    let module = compiler
+        .compile_jit(&[("host_double", host_double as *const u8)])
+        .unwrap();
+
+    let twice_plus: extern "C" fn(i32) -> i32 = unsafe { module.get("twice_plus") }.unwrap();
+    assert_eq!(twice_plus(20), 41);
+}
+
+#[test]
+fn missing_host_symbols_are_reported() {
+    let mut compiler = compile("fn i32 f() { let i32 d = host_double(1); return d; }");
+    compiler.declare_extern("host_double", &I32, &[I32]);
+    assert!(matches!(
+        compiler.compile_jit(&[]),
+        Err(JitError::UndefinedSymbol(name)) if name == "host_double"
+    ));
+}
+
+#[test]
+fn string_literals_are_mapped_with_the_code() {
+    let module = compile(r#"fn *char greeting() { return "hello\n"; }"#)
+        .compile_jit(&[])
+        .unwrap();
+    let greeting: extern "C" fn() -> *const std::ffi::c_char =
+        unsafe { module.get("greeting") }.unwrap();
+    assert_eq!(unsafe { CStr::from_ptr(greeting()) }, c"hello\n");
+}
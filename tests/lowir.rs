@@ -0,0 +1,148 @@
+//! Runs the `lowir` binary on fixtures written to cargo's scratch directory
+
+mod common;
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use common::*;
+
+fn write_input(name: &str, contents: &str) -> PathBuf {
+    let path = scratch(name);
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+fn lowir(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lowir"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn assembly_is_written_next_to_the_input() {
+    let input = write_input("next_to.lir", "fn i32 main() { return 3; }");
+    let output = lowir(&[input.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    let asm = std::fs::read_to_string(input.with_extension("asm")).unwrap();
+    assert!(asm.contains("main:"), "{asm}");
+}
+
+#[test]
+fn output_path_and_level_are_honoured() {
+    let input = write_input("flags.lir", "fn i32 main() { let i32 x = 2; return x; }");
+    let output_path = input.with_file_name("flags_renamed.s");
+    let output = lowir(&[
+        "-O",
+        "1",
+        "-o",
+        output_path.to_str().unwrap(),
+        input.to_str().unwrap(),
+    ]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(output_path.exists());
+}
+
+#[test]
+fn parse_errors_are_reported_with_their_position() {
+    let input = write_input("broken.lir", "fn i32 main() {\n    let i32 x = ;\n}");
+    let output = lowir(&[input.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.starts_with("lowir: ") && stderr.contains("broken.lir:2:"),
+        "{stderr}"
+    );
+}
+
+#[test]
+fn unknown_flags_are_rejected() {
+    let output = lowir(&["--frobnicate", "x.lir"]);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_input_is_compiled() {
+    let operands = low_level_ir::parse_ir("fn i32 main() { return 3; }").unwrap();
+    let input = write_input("from_json.json", &low_level_ir::to_json(&operands));
+    let output = lowir(&[input.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+    assert!(input.with_extension("asm").exists());
+}
+
+#[test]
+fn gas_executables_run() {
+    if !has_tool("cc") {
+        return;
+    }
+    let input = write_input(
+        "gas_exe.lir",
+        "fn i32 main() { let i32 x = 40; return x + 2; }",
+    );
+    let output = lowir(&["--syntax", "gas", "--emit", "exe", input.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    let status = Command::new(input.with_extension("")).status().unwrap();
+    assert_eq!(status.code(), Some(42));
+}
+
+#[test]
+fn builtin_assembler_builds_executables() {
+    if !has_tool("cc") {
+        return;
+    }
+    let input = write_input(
+        "builtin_exe.lir",
+        "fn i32 main() { let i32 x = 40; return x + 3; }",
+    );
+    let output = lowir(&["--emit", "exe", input.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    let status = Command::new(input.with_extension("")).status().unwrap();
+    assert_eq!(status.code(), Some(43));
+}
+
+#[test]
+fn inline_threshold_is_configurable() {
+    let input = write_input(
+        "inline.lir",
+        "fn i32 f(i32 a) { let i32 b = a + 1; return b; }\nfn i32 main() { return f(1); }",
+    );
+    let input = input.to_str().unwrap();
+    let output = lowir(&["-O", "1", "--debug", input]);
+    assert!(output.status.success(), "{output:?}");
+    let report = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        report.contains("in function main: inlined call to f"),
+        "{report}"
+    );
+
+    let output = lowir(&["-O", "1", "--debug", "--inline-threshold", "0", input]);
+    assert!(output.status.success(), "{output:?}");
+    let report = String::from_utf8_lossy(&output.stderr);
+    assert!(!report.contains("inlined call"), "{report}");
+
+    let output = lowir(&["--inline-threshold", "many", input]);
+    assert!(!output.status.success());
+    let error = String::from_utf8_lossy(&output.stderr);
+    assert!(error.contains("Invalid inline threshold `many`"), "{error}");
+}
+
+#[test]
+fn executables_never_replace_an_input_without_extension() {
+    if !has_tool("cc") {
+        return;
+    }
+    let source = "fn i32 main() { return 5; }";
+    let input = write_input("no_extension", source);
+    let output = lowir(&["--emit", "exe", input.to_str().unwrap()]);
+    assert!(output.status.success(), "{output:?}");
+
+    assert_eq!(std::fs::read_to_string(&input).unwrap(), source);
+    let status = Command::new(input.with_extension("out")).status().unwrap();
+    assert_eq!(status.code(), Some(5));
+}
@@ -0,0 +1,136 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+fn reg(register: Register) -> MachineOperand {
+    MachineOperand::Register(register, Size::QuadWord)
+}
+
+fn label(name: &str) -> Instruction {
+    Instruction::Label(name.to_string())
+}
+
+fn jump(name: &str) -> Instruction {
+    Instruction::JumpConditional {
+        label_destination: name.to_string(),
+        conditional: CompareOperation::EQ,
+        signed: true,
+    }
+}
+
+#[test]
+fn redundant_moves_are_removed() {
+    let instructions = vec![
+        Instruction::Move(reg(Register::AX), reg(Register::AX)),
+        Instruction::Move(reg(Register::CX), reg(Register::AX)),
+        Instruction::Move(reg(Register::AX), reg(Register::CX)),
+        Instruction::Move(reg(Register::DX), MachineOperand::Immediate(1)),
+        Instruction::Move(reg(Register::DX), MachineOperand::Immediate(2)),
+        Instruction::Return,
+    ];
+    assert_eq!(
+        peephole(instructions),
+        vec![
+            Instruction::Move(reg(Register::CX), reg(Register::AX)),
+            Instruction::Move(reg(Register::DX), MachineOperand::Immediate(2)),
+            Instruction::Return,
+        ]
+    );
+}
+
+#[test]
+fn moves_with_side_effects_are_kept() {
+    let eax = MachineOperand::Register(Register::AX, Size::DoubleWord);
+    let ecx = MachineOperand::Register(Register::CX, Size::DoubleWord);
+    let through_rax = MachineOperand::Memory(MemoryOperand::new(Size::QuadWord, Register::AX, 0));
+    // Clears the upper half of RAX
+    let kept = vec![
+        Instruction::Move(eax.clone(), eax.clone()),
+        Instruction::Return,
+    ];
+    assert_eq!(peephole(kept.clone()), kept);
+
+    let kept = vec![
+        // The second move clears the upper half of RCX
+        Instruction::Move(eax.clone(), ecx.clone()),
+        Instruction::Move(ecx, eax),
+        // The second move stores through the loaded address
+        Instruction::Move(reg(Register::AX), through_rax.clone()),
+        Instruction::Move(through_rax, reg(Register::AX)),
+        Instruction::Return,
+    ];
+    assert_eq!(peephole(kept.clone()), kept);
+}
+
+#[test]
+fn no_op_arithmetic_and_unused_compares_are_removed() {
+    let instructions = vec![
+        Instruction::Add(reg(Register::AX), MachineOperand::Immediate(0)),
+        Instruction::IntMultiply(reg(Register::AX), MachineOperand::Immediate(1)),
+        Instruction::Compare(reg(Register::AX), MachineOperand::Immediate(3)),
+        Instruction::Sub(reg(Register::AX), MachineOperand::Immediate(0)),
+        Instruction::Return,
+    ];
+    assert_eq!(peephole(instructions), vec![Instruction::Return]);
+
+    // The jump still needs the flags
+    let kept = vec![
+        Instruction::Compare(reg(Register::AX), MachineOperand::Immediate(3)),
+        jump(".L1"),
+        Instruction::Return,
+        label(".L1"),
+        Instruction::Return,
+    ];
+    assert_eq!(peephole(kept.clone()), kept);
+}
+
+#[test]
+fn jumps_to_the_next_instruction_and_unused_labels_are_removed() {
+    let instructions = vec![
+        Instruction::Compare(reg(Register::AX), MachineOperand::Immediate(3)),
+        jump(".L1"),
+        label(".L1"),
+        label(".unused"),
+        label("exported"),
+        Instruction::Return,
+    ];
+    assert_eq!(
+        peephole(instructions),
+        vec![label("exported"), Instruction::Return]
+    );
+}
+
+#[test]
+fn registers_are_only_saved_when_read_afterwards() {
+    let instructions = vec![
+        Instruction::Push(reg(Register::DI)),
+        Instruction::Call("f".to_string()),
+        Instruction::Pop(reg(Register::DI)),
+        Instruction::Move(reg(Register::DI), MachineOperand::Immediate(0)),
+        Instruction::Return,
+    ];
+    assert_eq!(
+        peephole(instructions),
+        vec![
+            Instruction::Call("f".to_string()),
+            Instruction::Move(reg(Register::DI), MachineOperand::Immediate(0)),
+            Instruction::Return,
+        ]
+    );
+}
+
+#[test]
+fn optimized_output_is_smaller() {
+    for (name, source, _) in PROGRAMS {
+        let lines = |opt_level| {
+            let mut compiler = Compiler::new();
+            compiler.operands = parse(name, source);
+            compiler.opt_level = opt_level;
+            // Inlining trades size for speed, which would hide what the rules removed
+            compiler.inline_threshold = 0;
+            compiler.compile().lines().count()
+        };
+        assert!(lines(1) < lines(0), "{name}");
+    }
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+#[test]
+fn text_round_trips() {
+    for (name, source, _) in PROGRAMS {
+        let operands = parse(name, source);
+        let printed = print_ir(&operands);
+        let reparsed = parse_ir(&printed).unwrap_or_else(|e| panic!("{name}: {e}\n{printed}"));
+
+        assert_eq!(reparsed, operands, "{name}");
+        assert_eq!(print_ir(&reparsed), printed, "{name}");
+    }
+}
+
+#[test]
+fn odd_identifiers_and_literals_round_trip() {
+    let source = r#"
+        fn i32 @"let"(i32 @"a b") { return @"a b"; }
+        fn *char text() { return "a \"quoted\"\nline"; }
+        fn i64 main() {
+            let char c = '\n';
+            let i64 big = 9223372036854775807;
+            return @"let"(-5);
+        }
+    "#;
+    let operands = parse("identifiers", source);
+    assert_eq!(parse_ir(&print_ir(&operands)).unwrap(), operands);
+}
+
+#[test]
+fn parse_errors_point_at_the_problem() {
+    let error = parse_ir("fn i32 main() {\n    let i32 x = ;\n}").unwrap_err();
+    assert_eq!((error.line, error.column), (2, 17));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_round_trips() {
+    for (name, source, _) in PROGRAMS {
+        let operands = parse(name, source);
+        assert_eq!(from_json(&to_json(&operands)).unwrap(), operands, "{name}");
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_with_another_version_is_rejected() {
+    let json = format!(
+        r#"{{ "version": {}, "operands": [] }}"#,
+        IR_SCHEMA_VERSION + 1
+    );
+    let error = from_json(&json).unwrap_err().to_string();
+    assert!(error.contains("Unsupported IR schema version"), "{error}");
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn version_is_checked_before_the_operands() {
+    // An older module whose operands don't match the current layout
+    let json = r#"{ "version": 1, "operands": [ { "unknown": [] } ] }"#;
+    let error = from_json(json).unwrap_err().to_string();
+    assert!(error.contains("Unsupported IR schema version 1"), "{error}");
+}
+
+#[test]
+fn extended_assembly_round_trips() {
+    let (_, source, _) = ASSEMBLY;
+    let operands = parse("asm", source);
+    let printed = print_ir(&operands);
+    assert_eq!(parse("printed", &printed), operands, "{printed}");
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+fn jit(operands: &[Operand], opt_level: u8) -> i32 {
+    let mut compiler = Compiler::new();
+    compiler.operands = operands.to_vec();
+    compiler.opt_level = opt_level;
+    let module = compiler.compile_jit(&[]).unwrap();
+    let main: extern "C" fn() -> i32 = unsafe { module.get("main") }.unwrap();
+    main()
+}
+
+fn check(name: &str, source: &str, expected: i32) {
+    let operands = parse(name, source);
+    assert!(validate(&operands).is_empty(), "{name}");
+    assert_eq!(interpret(name, &operands), expected, "{name}");
+    for opt_level in 0..=2 {
+        assert_eq!(
+            jit(&operands, opt_level),
+            expected,
+            "{name} at -O{opt_level}"
+        );
+    }
+}
+
+#[test]
+fn if_bodies_shadow_outer_variables() {
+    check(
+        "shadowed",
+        "fn i32 main() {
+            let i32 x = 10;
+            let i32 seen = 0;
+            if x > 5 {
+                let i32 x = x + 1;
+                seen = x;
+            }
+            return seen + x;
+        }",
+        21,
+    );
+}
+
+#[test]
+fn assignments_in_if_bodies_reach_outer_variables() {
+    check(
+        "assigned",
+        "fn i32 main() {
+            let i32 x = 1;
+            if x == 1 {
+                let i32 y = 4;
+                x = x + y;
+            }
+            return x;
+        }",
+        5,
+    );
+}
+
+#[test]
+fn block_variables_are_gone_after_the_block() {
+    let operands = parse(
+        "scoped",
+        "fn i32 main() { if 1 == 1 { let i32 y = 4; } return y; }",
+    );
+    let messages = validate(&operands)
+        .into_iter()
+        .map(|d| d.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(messages, ["in function main: Variable y does not exist"]);
+}
+
+#[test]
+fn block_slots_are_reused_after_the_block() {
+    let mut compiler = Compiler::new();
+    compiler.operands = parse(
+        "reuse",
+        "fn i32 main() {
+            let i32 a = 1;
+            if a == 1 { let i32 b = 2; a = a + b; }
+            let i32 c = a;
+            return c;
+        }",
+    );
+    let asm = compiler.try_compile().unwrap();
+    assert!(!asm.contains("[RBP-12]"), "{asm}");
+}
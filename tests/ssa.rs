@@ -0,0 +1,103 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+fn build(source: &str) -> Vec<SsaFunction> {
+    build_ssa(&parse("program", source), &[]).unwrap()
+}
+
+#[test]
+fn assignments_in_an_if_merge_with_a_phi() {
+    let functions = build("fn i32 f(i32 x) { let i32 r = 1; if x > 0 { r = 2; } return r; }");
+    let f = &functions[0];
+
+    let Terminator::Branch {
+        operation,
+        then,
+        otherwise,
+        ..
+    } = f.block(SsaFunction::ENTRY).terminator.clone()
+    else {
+        panic!("{f}");
+    };
+    assert_eq!(operation, CompareOperation::GT);
+    assert_eq!(f.block(then).terminator, Terminator::Jump(otherwise));
+
+    let join = f.block(otherwise);
+    let Terminator::Return(Some(result)) = join.terminator else {
+        panic!("{f}");
+    };
+    let SsaOp::Phi(incoming) = &f.value(result).op else {
+        panic!("{f}");
+    };
+    let mut sources = incoming
+        .iter()
+        .map(|(block, value)| (*block, f.value(*value).op.clone()))
+        .collect::<Vec<_>>();
+    sources.sort_by_key(|v| v.0);
+    assert_eq!(
+        sources,
+        [
+            (SsaFunction::ENTRY, SsaOp::Constant(1)),
+            (then, SsaOp::Constant(2))
+        ]
+    );
+
+    let cfg = Cfg::new(f);
+    assert_eq!(cfg.immediate_dominator(then), Some(SsaFunction::ENTRY));
+    assert_eq!(cfg.immediate_dominator(otherwise), Some(SsaFunction::ENTRY));
+    assert!(cfg.dominates(SsaFunction::ENTRY, otherwise));
+    assert!(!cfg.dominates(then, otherwise));
+    assert_eq!(cfg.dominance_frontiers()[then.0], [otherwise]);
+}
+
+#[test]
+fn variables_with_their_address_taken_live_in_memory() {
+    let functions = build("fn i32 f() { let i32 v = 1; let *i32 p = &v; *p = 2; return v; }");
+    let f = &functions[0];
+    assert!(
+        f.values
+            .iter()
+            .any(|v| v.op == SsaOp::StackSlot("v".to_string())),
+        "{f}"
+    );
+    assert!(
+        f.values.iter().any(|v| matches!(v.op, SsaOp::Store { .. })),
+        "{f}"
+    );
+}
+
+#[test]
+fn straight_line_code_needs_no_blocks() {
+    let functions = build("fn i32 f(i32 a, i32 b) { let i32 c = a + b; return c - 1; }");
+    assert_eq!(functions[0].blocks.len(), 1);
+}
+
+#[test]
+fn fixtures_convert() {
+    for (name, source, _) in PROGRAMS {
+        build_ssa(&parse(name, source), &[]).unwrap_or_else(|e| panic!("{name}: {e}"));
+    }
+}
+
+#[test]
+fn calls_nested_in_expressions_run_at_o2() {
+    let source = "
+        fn i32 fib(i32 n) {
+            let i32 r = n;
+            if n >= 2 { r = fib(n - 1) + fib(n - 2); }
+            return r;
+        }
+        fn i32 id(i32 x) { return x; }
+        fn i32 three(i32 a, i32 b, i32 c) { return a - b + c; }
+        fn i32 main() { return fib(10) + three(3, id(1), 2) + (id(5) - (id(2) + id(1))); }
+    ";
+    let operands = parse("calls", source);
+    let mut compiler = Compiler::new();
+    compiler.operands = operands.clone();
+    compiler.opt_level = 2;
+    let module = compiler.compile_jit(&[]).unwrap();
+    let main: extern "C" fn() -> i32 = unsafe { module.get("main") }.unwrap();
+    assert_eq!(main(), interpret("calls", &operands));
+}
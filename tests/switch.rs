@@ -0,0 +1,104 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+fn compile(source: &str, syntax: AssemblySyntax) -> String {
+    let mut compiler = Compiler::new();
+    compiler.operands = parse("switch", source);
+    compiler.syntax = syntax;
+    compiler.try_compile().unwrap()
+}
+
+const DENSE: &str = "fn i32 main() {
+    let i32 x = 2;
+    let i32 r = 0;
+    switch x { case 0 { r = 1; } case 1 { r = 2; } case 2 { r = 3; } case 4 { r = 4; } }
+    return r;
+}";
+
+#[test]
+fn dense_cases_use_a_jump_table() {
+    let asm = compile(DENSE, AssemblySyntax::Gas);
+    assert_eq!(asm.matches(".quad").count(), 1, "{asm}");
+    // The table covers 0 to 4, the missing 3 goes to the default
+    let table = asm.lines().find(|v| v.contains(".quad")).unwrap();
+    assert_eq!(table.split(',').count(), 5, "{asm}");
+
+    let asm = compile(DENSE, AssemblySyntax::Nasm);
+    assert!(asm.contains("dq main.SW"), "{asm}");
+}
+
+#[test]
+fn sparse_cases_are_compared() {
+    let asm = compile(
+        "fn i32 main() {
+            let i32 r = 0;
+            switch r { case 1 { r = 1; } case 100 { r = 2; } case 5000 { r = 3; } case 90000 { r = 4; } }
+            return r;
+        }",
+        AssemblySyntax::Gas,
+    );
+    assert!(!asm.contains(".quad"), "{asm}");
+}
+
+#[test]
+fn switches_round_trip() {
+    let operands = parse("dense", DENSE);
+    assert_eq!(parse("printed", &print_ir(&operands)), operands);
+}
+
+#[test]
+fn cases_are_checked() {
+    let operands = parse(
+        "invalid",
+        "fn i32 main() {
+            let i8 x = 1;
+            switch x { case 1 { } case 1 { } case 300 { } }
+            let *i8 p = &x;
+            switch p { default { } }
+            return 0;
+        }",
+    );
+    let messages = validate(&operands)
+        .into_iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        messages,
+        [
+            "in function main: Case 1 appears more than once",
+            "in function main: Case 300 doesn't fit in i8",
+            "in function main: Can't switch on p of type *i8",
+        ]
+    );
+}
+
+#[test]
+fn unreachable_switches_leave_no_table() {
+    let source = "fn i32 main() {
+        let i32 x = 2;
+        switch x { case 0 { return 22; } default { return 23; } }
+        switch x { case 0 { x = 1; } case 1 { x = 2; } case 2 { x = 3; } case 3 { x = 4; } }
+        return x;
+    }";
+    for opt_level in 1..=2 {
+        let compiler = || {
+            let mut compiler = Compiler::new();
+            compiler.operands = parse("unreachable", source);
+            compiler.opt_level = opt_level;
+            compiler
+        };
+        let module = compiler().compile_jit(&[]).unwrap();
+        let main: extern "C" fn() -> i32 = unsafe { module.get("main") }.unwrap();
+        assert_eq!(main(), 23, "-O{opt_level}");
+
+        compiler().compile_object().unwrap();
+        for syntax in [AssemblySyntax::Nasm, AssemblySyntax::Gas] {
+            let mut compiler = compiler();
+            compiler.syntax = syntax;
+            let asm = compiler.compile();
+            assert!(!asm.contains("__jump_table"), "-O{opt_level}\n{asm}");
+        }
+    }
+}
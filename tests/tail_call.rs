@@ -0,0 +1,63 @@
+//! Calls that are returned directly reuse the frame of their caller
+
+use low_level_ir::*;
+
+/// Each level calls itself ten million times, which only fits in the stack of the thread
+/// below when every call reuses its caller's frame
+const PROGRAM: &str = "
+fn i64 sum(i64 n, i64 acc) {
+    if n == 0 { return acc; }
+    return sum(n - 1, acc + 2);
+}
+fn i32 count(i32 n) {
+    switch n {
+        case 0 { return 7; }
+    }
+    return count(n - 1);
+}
+";
+
+const STACK_SIZE: usize = 256 * 1024;
+
+fn run_deep(opt_level: u8) -> (i64, i32) {
+    let operands = parse_ir(PROGRAM).unwrap();
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut compiler = Compiler::new();
+            compiler.operands = operands;
+            compiler.opt_level = opt_level;
+            compiler.exports = vec!["sum".to_string(), "count".to_string()];
+            let module = compiler.compile_jit(&[]).unwrap();
+
+            let sum: extern "C" fn(i64, i64) -> i64 = unsafe { module.get("sum") }.unwrap();
+            let count: extern "C" fn(i32) -> i32 = unsafe { module.get("count") }.unwrap();
+            (sum(10_000_000, 0), count(10_000_000))
+        })
+        .unwrap()
+        .join()
+        .unwrap()
+}
+
+#[test]
+fn deep_recursion_runs_in_constant_stack() {
+    for opt_level in 0..=2 {
+        assert_eq!(run_deep(opt_level), (20_000_000, 7), "-O{opt_level}");
+    }
+}
+
+#[test]
+fn returned_calls_become_jumps() {
+    for opt_level in 0..=1 {
+        let mut compiler = Compiler::new();
+        compiler.operands = parse_ir(
+            "noinline fn i32 next(i32 n) { return n + 1; }
+            fn i32 main() { let i32 x = 4; return next(x); }",
+        )
+        .unwrap();
+        compiler.opt_level = opt_level;
+        let asm = compiler.try_compile().unwrap();
+        assert!(asm.contains("jmp next"), "-O{opt_level}\n{asm}");
+        assert!(!asm.contains("call next"), "-O{opt_level}\n{asm}");
+    }
+}
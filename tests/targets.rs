@@ -0,0 +1,301 @@
+//! Every fixture compiles for every target, the output is assembled and run when the tools
+//! for it are installed and has to return what the interpreter does
+
+mod common;
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use common::*;
+use low_level_ir::*;
+
+fn compile(operands: &[Operand], target: Target, syntax: AssemblySyntax) -> String {
+    let mut compiler = Compiler::new();
+    compiler.operands = operands.to_vec();
+    compiler.target = target;
+    compiler.syntax = syntax;
+    compiler.compile()
+}
+
+/// Assembles and links `asm` with a compiler driver, returning the exit code of running it
+/// directly or under `emulator`
+fn run(name: &str, asm: &str, driver: &[&str], emulator: Option<&str>) -> i32 {
+    let source = scratch(&format!("{name}.s"));
+    let executable = scratch(name);
+    std::fs::write(&source, asm).unwrap();
+
+    let build = Command::new(driver[0])
+        .args(&driver[1..])
+        .args(["-x", "assembler"])
+        .arg(&source)
+        .arg("-o")
+        .arg(&executable)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&build.stderr);
+    assert!(build.status.success(), "{name}: {stderr}\n{asm}");
+
+    let status = match emulator {
+        Some(emulator) => Command::new(emulator).arg(&executable).status(),
+        None => Command::new(&executable).status(),
+    };
+    status.unwrap().code().unwrap()
+}
+
+/// Checks that llvm-mc accepts `asm`
+fn assemble(llvm_mc: &[&str], name: &str, asm: &str) {
+    let mut child = Command::new("llvm-mc")
+        .args(llvm_mc)
+        .args(["-filetype=obj", "-o", "/dev/null"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(asm.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{name}: {stderr}\n{asm}");
+}
+
+/// Compiles every fixture for a target lowered through `Isa`, then assembles the output with
+/// llvm-mc and runs it under qemu when they are installed
+fn check_target(target: Target, llvm_mc: &[&str], driver: &str, emulator: &str) {
+    for (name, source, _) in PROGRAMS {
+        let operands = parse(name, source);
+        let asm = compile(&operands, target, AssemblySyntax::Gas);
+        assert!(
+            asm.contains("main:") && asm.contains("ret"),
+            "{name}:\n{asm}"
+        );
+
+        if has_tool("llvm-mc") {
+            assemble(llvm_mc, name, &asm);
+        }
+        if has_tool(driver) && has_tool(emulator) {
+            let name = format!("{name}_{target:?}");
+            let result = run(&name, &asm, &[driver, "-static"], Some(emulator));
+            assert_eq!(result, interpret(&name, &operands) & 0xFF, "{name}");
+        }
+    }
+}
+
+#[test]
+fn x86_64_nasm() {
+    for (name, source, _) in PROGRAMS {
+        let asm = compile(&parse(name, source), Target::X86_64, AssemblySyntax::Nasm);
+        assert!(asm.contains("global main"), "{name}:\n{asm}");
+        assert!(
+            asm.contains("main:") && asm.contains("ret"),
+            "{name}:\n{asm}"
+        );
+    }
+}
+
+#[test]
+fn x86_64_gas() {
+    for (name, source, _) in PROGRAMS {
+        let operands = parse(name, source);
+        let asm = compile(&operands, Target::X86_64, AssemblySyntax::Gas);
+        assert!(asm.starts_with(".intel_syntax noprefix"), "{name}:\n{asm}");
+
+        if has_tool("llvm-mc") {
+            assemble(&["-triple=x86_64"], name, &asm);
+        }
+        if has_tool("cc") {
+            // Exit codes only keep the low byte
+            let result = run(name, &asm, &["cc", "-no-pie"], None);
+            assert_eq!(result, interpret(name, &operands) & 0xFF, "{name}");
+        }
+    }
+}
+
+#[test]
+fn x86_64_gas_inline_assembly() {
+    let (name, source, expected) = ASSEMBLY;
+    let operands = parse(name, source);
+    for opt_level in 0..=2 {
+        let mut compiler = Compiler::new();
+        compiler.operands = operands.clone();
+        compiler.opt_level = opt_level;
+        compiler.syntax = AssemblySyntax::Gas;
+        let asm = compiler.compile();
+
+        if has_tool("llvm-mc") {
+            assemble(&["-triple=x86_64"], name, &asm);
+        }
+        if has_tool("cc") {
+            let name = format!("{name}_O{opt_level}");
+            assert_eq!(
+                run(&name, &asm, &["cc", "-no-pie"], None),
+                expected,
+                "{name}"
+            );
+        }
+    }
+}
+
+#[test]
+fn aarch64() {
+    check_target(
+        Target::AArch64,
+        &["-triple=aarch64"],
+        "aarch64-linux-gnu-gcc",
+        "qemu-aarch64",
+    );
+}
+
+#[test]
+fn riscv64() {
+    check_target(
+        Target::RiscV64,
+        &["-triple=riscv64", "-mattr=+m"],
+        "riscv64-linux-gnu-gcc",
+        "qemu-riscv64",
+    );
+}
+
+#[test]
+fn wasm32() {
+    for (name, source, _) in PROGRAMS {
+        let wat = compile(&parse(name, source), Target::Wasm32, AssemblySyntax::Gas);
+        assert!(wat.starts_with("(module"), "{name}:\n{wat}");
+        assert!(
+            wat.contains("(func $main (export \"main\")"),
+            "{name}:\n{wat}"
+        );
+        assert_eq!(
+            wat.matches('(').count(),
+            wat.matches(')').count(),
+            "{name}:\n{wat}"
+        );
+    }
+}
+
+#[test]
+fn x86_64_object() {
+    for (name, source, _) in PROGRAMS {
+        let operands = parse(name, source);
+        let mut compiler = Compiler::new();
+        compiler.operands = operands.clone();
+        let object = compiler
+            .compile_object()
+            .unwrap_or_else(|e| panic!("{name}: {e}"));
+        assert_eq!(&object[..4], b"\x7fELF", "{name}");
+        // ELFCLASS64, little endian, ET_REL and EM_X86_64
+        assert_eq!((object[4], object[5]), (2, 1), "{name}");
+        assert_eq!(u16::from_le_bytes([object[16], object[17]]), 1, "{name}");
+        assert_eq!(u16::from_le_bytes([object[18], object[19]]), 0x3E, "{name}");
+
+        if has_tool("cc") {
+            let path = scratch(&format!("{name}.o"));
+            let executable = scratch(&format!("{name}_object"));
+            std::fs::write(&path, &object).unwrap();
+            let link = Command::new("cc")
+                .arg("-no-pie")
+                .arg(&path)
+                .arg("-o")
+                .arg(&executable)
+                .output()
+                .unwrap();
+            let stderr = String::from_utf8_lossy(&link.stderr);
+            assert!(link.status.success(), "{name}: {stderr}");
+
+            let result = Command::new(&executable).status().unwrap().code().unwrap();
+            assert_eq!(result, interpret(name, &operands) & 0xFF, "{name}");
+        }
+    }
+}
+
+/// Names and types of the sections in an ELF64 object
+fn sections(object: &[u8]) -> Vec<(String, u32)> {
+    let read = |offset: usize, len: usize| {
+        let mut bytes = [0; 8];
+        bytes[..len].copy_from_slice(&object[offset..offset + len]);
+        u64::from_le_bytes(bytes) as usize
+    };
+    let headers = read(0x28, 8);
+    let count = read(0x3C, 2);
+    let header = |i: usize| headers + i * 64;
+    let names = read(header(read(0x3E, 2)) + 0x18, 8);
+    (0..count)
+        .map(|i| {
+            let name = &object[names + read(header(i), 4)..];
+            let name = &name[..name.iter().position(|v| *v == 0).unwrap()];
+            let kind = read(header(i) + 4, 4) as u32;
+            (String::from_utf8(name.to_vec()).unwrap(), kind)
+        })
+        .collect()
+}
+
+#[test]
+fn objects_have_data_and_bss_sections() {
+    let mut compiler = Compiler::new();
+    compiler.operands = parse("empty", "fn i32 main() { return 0; }");
+    let sections = sections(&compiler.compile_object().unwrap());
+    // SHT_PROGBITS and SHT_NOBITS
+    assert!(sections.contains(&(".data".to_string(), 1)), "{sections:?}");
+    assert!(sections.contains(&(".bss".to_string(), 8)), "{sections:?}");
+}
+
+#[test]
+fn data_and_bss_symbols_are_linked() {
+    let symbol = |name: &str, offset| ElfSymbol {
+        name: name.to_string(),
+        offset,
+        size: 4,
+        global: false,
+    };
+    let load = |offset, symbol: &str| Relocation {
+        offset,
+        symbol: symbol.to_string(),
+        kind: RelocationKind::Pc32,
+        addend: -4,
+    };
+    let object = ElfObject {
+        // mov eax, [rip + answer]; add eax, [rip + zero]; ret
+        text: vec![0x8B, 0x05, 0, 0, 0, 0, 0x03, 0x05, 0, 0, 0, 0, 0xC3],
+        data: [0u32, 41].iter().flat_map(|v| v.to_le_bytes()).collect(),
+        bss_size: 8,
+        functions: vec![ElfSymbol {
+            global: true,
+            ..symbol("main", 0)
+        }],
+        data_symbols: vec![symbol("answer", 4)],
+        bss_symbols: vec![symbol("zero", 4)],
+        relocations: vec![load(2, "answer"), load(8, "zero")],
+        ..Default::default()
+    };
+    if !has_tool("cc") {
+        return;
+    }
+    let path = scratch("data_and_bss.o");
+    let executable = scratch("data_and_bss");
+    std::fs::write(&path, object.write()).unwrap();
+    let link = Command::new("cc")
+        .arg("-no-pie")
+        .arg(&path)
+        .arg("-o")
+        .arg(&executable)
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&link.stderr);
+    assert!(link.status.success(), "{stderr}");
+    let result = Command::new(&executable).status().unwrap().code();
+    assert_eq!(result, Some(41));
+}
+
+#[test]
+fn objects_are_only_encoded_for_x86_64() {
+    let mut compiler = Compiler::new();
+    compiler.operands = parse_ir("fn i32 main() { return 0; }").unwrap();
+    compiler.target = Target::AArch64;
+    assert!(matches!(
+        compiler.compile_object(),
+        Err(EncodeError::Unsupported(_))
+    ));
+}
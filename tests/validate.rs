@@ -0,0 +1,97 @@
+mod common;
+
+use common::*;
+use low_level_ir::*;
+
+fn messages(source: &str) -> Vec<String> {
+    validate(&parse("invalid", source))
+        .into_iter()
+        .map(|v| v.to_string())
+        .collect()
+}
+
+#[test]
+fn fixtures_are_valid() {
+    for (name, source, _) in PROGRAMS {
+        assert_eq!(validate(&parse(name, source)), vec![], "{name}");
+    }
+}
+
+#[test]
+fn every_problem_is_reported() {
+    let source = "
+        fn i32 f(i32 a) { return a; }
+        fn i32 f(i32 b) { return b; }
+        fn i32 main() {
+            let i64 wide = 1;
+            let i32 narrow = wide;
+            let i32 narrow = missing;
+            let i32 call = f(1, 2);
+            let i32 twice = g();
+            let i32 deref = *narrow;
+            if wide == narrow { return 1; }
+            return wide + narrow;
+        }
+        fn i32 nothing() { let i32 x = 1; }
+    ";
+    assert_eq!(
+        messages(source),
+        [
+            "Function f is declared more than once",
+            "in function main: Can't assign a QuadWord value of type i64 to variable narrow of type \
+             i32 (DoubleWord)",
+            "in function main: Variable missing does not exist",
+            "in function main: Variable narrow is declared more than once in the same scope",
+            "in function main: f takes 1 arguments but 2 were given",
+            "in function main: Function g does not exist",
+            "in function main: Can't dereference narrow of type i32, not a pointer",
+            "in function main: Can't compare i64 with i32",
+            "in function main: Can't do arithmetic between i64 and i32",
+            "in function main: Can't assign a QuadWord value of type i64 to the return value of \
+             type i32 (DoubleWord)",
+            "in function nothing: No return statement in function nothing",
+        ]
+    );
+}
+
+#[test]
+fn literals_fit_any_integer() {
+    let source = "fn u8 main() { let i64 a = 5; let u8 b = 'a'; return 300 - 1; }";
+    assert_eq!(messages(source), Vec::<String>::new());
+}
+
+#[test]
+fn try_compile_refuses_invalid_programs() {
+    let mut compiler = Compiler::new();
+    compiler.operands = parse("invalid", "fn i32 main() { return missing; }");
+    let diagnostics = compiler.try_compile().unwrap_err();
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].function.as_deref(), Some("main"));
+
+    let mut compiler = Compiler::new();
+    compiler.operands = parse("valid", "fn i32 main() { return 0; }");
+    assert!(compiler.try_compile().unwrap().contains("main:"));
+}
+
+#[test]
+fn inline_assembly_operands_are_checked() {
+    let source = r#"fn i32 main() {
+        let i32 x = 1;
+        asm "mov %0, %1" : "r"(x) : "=r"(x);
+        asm "add %0, %1" : "=r"(x) : "i"(x), "q"(x);
+        asm "nop" : "=r"(3);
+        asm "mov %0, %1" : "=m"(x) : "0"(x);
+        return x;
+    }"#;
+    assert_eq!(
+        messages(source),
+        [
+            "in function main: Output constraint \"r\" has to start with = or +",
+            "in function main: Input constraint \"=r\" can't start with = or +",
+            "in function main: Operand %1 has to be a constant",
+            "in function main: Unsupported inline assembly constraint \"q\"",
+            "in function main: 3 can't be an inline assembly output",
+            "in function main: Operand %1 is tied to %0, which isn't a register output",
+        ]
+    );
+}